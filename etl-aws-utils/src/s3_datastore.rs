@@ -1,3 +1,4 @@
+use etl_core::datastore::compression::{BytesEncoder, Compression};
 use etl_core::datastore::error::*;
 use etl_core::datastore::{*, simple::SimpleStore};
 use etl_core::deps::{
@@ -6,7 +7,7 @@ use etl_core::deps::{
     serde::de::DeserializeOwned,
     serde::Serialize,
     tokio,
-    tokio::io::{AsyncBufReadExt, BufReader},
+    tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader},
     tokio::sync::mpsc::{channel, Receiver},
     tokio::task::JoinHandle,
     log,
@@ -28,6 +29,11 @@ pub struct S3Storage {
     pub credentials_path: Option<String>,
     /// defaults to UsEast1
     pub region: Region,
+    /// When not set, compression is detected from the extension of each key (`.gz`, `.zst`)
+    pub compression: Option<Compression>,
+    /// When used as a DataSource, send the content of each key as a single message instead of
+    /// line by line.  Needed by binary formats like Parquet
+    pub whole_files: bool,
 }
 
 impl Default for S3Storage {
@@ -38,6 +44,8 @@ impl Default for S3Storage {
             s3_output_key: None,
             credentials_path: None,
             region: Region::UsEast1,
+            compression: None,
+            whole_files: false,
         }
     }
 }
@@ -90,18 +98,25 @@ impl DataOutput<Bytes> for S3Storage {
     async fn start_stream(
         self: Box<Self>,
     ) -> anyhow::Result<DataOutputTask<Bytes>> {
-        let (tx, mut rx): (DataOutputTx<Bytes>, _) = channel(1);
+        let (tx, mut rx): (DataOutputTx<Bytes>, _) = channel(channels::buffer_size());
         let name = self.name();
         let p = self.create_chain_provider()?;
-        let (s3_tx, s3_rx) = channel(1);
+        let (s3_tx, s3_rx) = channel(channels::buffer_size());
         let s3_output_key = self.s3_output_key.ok_or_else(|| {
             DataStoreError::FatalIO(
                 "s3_output_key is required when using as a DataOutput".to_string(),
             )
         })?;
-        let s3_jh =
-            s3_write_bytes_multipart(p, &self.s3_bucket, &s3_output_key, s3_rx, self.region)
-                .await
+        let compression = Compression::or_from_path(self.compression, &s3_output_key);
+        let s3_jh = s3_write_bytes_multipart(
+            p,
+            &self.s3_bucket,
+            &s3_output_key,
+            s3_rx,
+            self.region,
+            compression,
+        )
+        .await
                 .map_err(|e| {
                     DataStoreError::FatalIO(format!("S3Storage Error: {}", e.to_string()))
                 })?;
@@ -121,7 +136,9 @@ impl DataOutput<Bytes> for S3Storage {
             s3_jh.await??;
             Ok(DataOutputStats {
                 name,
+                key: Some(s3_output_key),
                 lines_written: num_lines,
+                ..Default::default()
             })
         });
         Ok((tx, jh))
@@ -137,9 +154,11 @@ impl DataSource<Bytes> for S3Storage {
         use rusoto_core::RusotoError;
         let p = self.create_chain_provider()?;
         let client: S3Client = create_client_2(p, &self.region)?;
-        let (tx, rx) = channel(1);
+        let (tx, rx) = channel(channels::buffer_size());
         let files = self.s3_keys.clone();
         let s3_bucket = self.s3_bucket.clone();
+        let compression = self.compression;
+        let whole_files = self.whole_files;
         let name = String::from("S3Storage");
         let jh = tokio::spawn(async move {
             let mut lines_scanned = 0_usize;
@@ -155,17 +174,43 @@ impl DataSource<Bytes> for S3Storage {
                         let reader = res.body.unwrap().into_async_read();
                         // 68 mb
                         let r = BufReader::with_capacity(1 << 26, reader);
-                        let mut lines = r.lines();
+                        let mut reader =
+                            Compression::or_from_path(compression, &s3_key).decompress_reader(r);
+                        if whole_files {
+                            let mut content = Vec::new();
+                            reader.read_to_end(&mut content).await?;
+                            lines_scanned += 1;
+                            tx.send(Ok(DataSourceMessage::new(
+                                Provenance::new(&name, &s3_key),
+                                Bytes::from(content),
+                            )))
+                            .await
+                            .map_err(|er| DataStoreError::send_error(&name, &s3_key, er))?;
+                            continue;
+                        }
+                        let mut line = String::new();
+                        let mut line_number = 0_usize;
+                        let mut offset = 0_u64;
                         loop {
-                            if let Some(line) = lines.next_line().await? {
-                                lines_scanned += 1;
-                                tx.send(Ok(DataSourceMessage::new(&s3_key, Bytes::from(line))))
-                                    .await
-                                    .map_err(|er| DataStoreError::send_error(&name, &s3_key, er))?;
-                            } else {
+                            line.clear();
+                            let len = reader.read_line(&mut line).await?;
+                            if len == 0 {
                                 // reached end of file
                                 break;
                             }
+                            lines_scanned += 1;
+                            line_number += 1;
+                            let provenance = Provenance::new(&name, &s3_key)
+                                .with_line(line_number)
+                                .with_offset(offset);
+                            offset += len as u64;
+                            let content = line.trim_end_matches('\n').trim_end_matches('\r');
+                            tx.send(Ok(DataSourceMessage::new(
+                                provenance,
+                                Bytes::from(content.to_string()),
+                            )))
+                            .await
+                            .map_err(|er| DataStoreError::send_error(&name, &s3_key, er))?;
                         }
                     }
                     Err(RusotoError::Service(GetObjectError::NoSuchKey(key))) => {
@@ -185,7 +230,7 @@ impl DataSource<Bytes> for S3Storage {
                     }
                 }
             }
-            Ok(DataSourceStats { lines_scanned })
+            Ok(DataSourceStats::new(lines_scanned))
         });
         Ok((rx, jh))
     }
@@ -269,13 +314,14 @@ fn create_client_2(
     ))
 }
 
-/// Upload to S3 with 30 mb size increments
+/// Upload to S3 with 30 mb size increments, the sizes are of the compressed content
 pub async fn s3_write_bytes_multipart(
     profile_provider: ChainProvider,
     s3_bucket: &str,
     s3_key: &str,
     mut body_stream: Receiver<Bytes>,
     region: Region,
+    compression: Compression,
 ) -> anyhow::Result<JoinHandle<anyhow::Result<()>>> {
     let client = S3Client::new_with(HttpClient::new().unwrap(), profile_provider, region);
 
@@ -295,23 +341,21 @@ pub async fn s3_write_bytes_multipart(
             })
             .await?
         {
-            use bytes::BytesMut;
             use rusoto_core::ByteStream;
-            let mut buf = BytesMut::with_capacity(max_size);
+            let mut buf = BytesEncoder::new(compression);
             let mut part_number = 1;
             loop {
                 match body_stream.recv().await {
                     Some(s) => {
-                        buf.extend(s);
+                        buf.write(&s).await?;
                         if buf.len() >= max_size {
                             println!("uploading because reached max size");
-                            let b = buf;
-                            buf = BytesMut::with_capacity(max_size);
+                            let b = buf.take();
                             let request = UploadPartRequest {
                                 bucket: s3_bucket.to_owned(),
                                 key: s3_key.to_owned(),
                                 //content_length: Some(buf.len() as i64),
-                                body: Some(ByteStream::from(Bytes::from(b).to_vec())),
+                                body: Some(ByteStream::from(b.to_vec())),
                                 upload_id: upload_id.clone(),
                                 part_number,
                                 ..Default::default()
@@ -347,11 +391,12 @@ pub async fn s3_write_bytes_multipart(
                         }
                     }
                     None => {
+                        let b = buf.finish().await?;
                         let request = UploadPartRequest {
                             bucket: s3_bucket.to_owned(),
                             key: s3_key.to_owned(),
                             //content_length: Some(buf.len() as i64),
-                            body: Some(ByteStream::from(Bytes::from(buf).to_vec())),
+                            body: Some(ByteStream::from(b.to_vec())),
                             upload_id: upload_id.clone(),
                             part_number,
                             ..Default::default()
@@ -1,70 +1,237 @@
 use crate::datastore::error::DataStoreError;
 use crate::datastore::*;
 use async_trait::async_trait;
+use serde::Serialize;
 use std::fmt::Debug;
+use std::time::Duration;
 use tokio::sync::mpsc::Receiver;
 use tokio::task::JoinHandle;
+use tokio::time::Instant;
 
 /// For use cases when you want to group stream elements in some form of custom fashion where a
-/// decoder can't handle.
+/// decoder can't handle.  A batch is also closed once it reaches any of the limits which are
+/// set, whichever comes first.  Errors of the input are sent along as they come, without
+/// closing the batch
 pub struct Batcher<I> {
     pub input: Box<dyn DataSource<I>>,
     /// Returns if a new batch should be started.  The element sent will be the first element in a
     /// new batch
-    pub new_batch: fn(&'_ I, &'_ Vec<I>) -> bool,
+    pub new_batch: BoxedNewBatchFn<I>,
+    pub max_items: Option<usize>,
+    /// Maximum sum of the sizes given by `size` of the elements of a batch.  An element bigger
+    /// than this is sent in a batch of its own
+    pub max_bytes: Option<usize>,
+    pub size: Option<BoxedSizeFn<I>>,
+    /// How long a batch is kept open after its first element was received
+    pub max_wait: Option<Duration>,
 }
 
-#[async_trait]
-impl<I: Debug + Send + Sync + 'static> DataSource<Vec<I>> for Batcher<I> {
-    fn name(&self) -> String {
-        format!("Batcher-{}", self.input.name())
+pub type BoxedNewBatchFn<I> = Box<dyn Fn(&'_ I, &'_ Vec<I>) -> bool + Send + Sync>;
+pub type BoxedSizeFn<I> = Box<dyn Fn(&'_ I) -> usize + Send + Sync>;
+
+impl<I> Batcher<I> {
+    pub fn new<F>(input: Box<dyn DataSource<I>>, new_batch: F) -> Self
+    where
+        F: Fn(&'_ I, &'_ Vec<I>) -> bool + Send + Sync + 'static,
+    {
+        Batcher {
+            input,
+            new_batch: Box::new(new_batch),
+            max_items: None,
+            max_bytes: None,
+            size: None,
+            max_wait: None,
+        }
     }
 
-    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<Vec<I>>, DataStoreError> {
+    /// Batches which are only closed by the limits set with the `with_max_*` methods
+    pub fn windowed(input: Box<dyn DataSource<I>>) -> Self {
+        Batcher::new(input, |_, _| false)
+    }
+
+    pub fn with_max_items(self, max_items: usize) -> Self {
+        Batcher {
+            max_items: Some(max_items.max(1)),
+            ..self
+        }
+    }
+
+    /// Limits the size of a batch to the size of its elements serialized as JSON
+    pub fn with_max_bytes(self, max_bytes: usize) -> Self
+    where
+        I: Serialize,
+    {
+        self.with_max_size(max_bytes, |item| {
+            serde_json::to_vec(item).map(|v| v.len()).unwrap_or(0)
+        })
+    }
+
+    /// Limits the size of a batch, with the size of each element given by `size`
+    pub fn with_max_size<F>(self, max_bytes: usize, size: F) -> Self
+    where
+        F: Fn(&'_ I) -> usize + Send + Sync + 'static,
+    {
+        Batcher {
+            max_bytes: Some(max_bytes),
+            size: Some(Box::new(size)),
+            ..self
+        }
+    }
+
+    pub fn with_max_wait(self, max_wait: Duration) -> Self {
+        Batcher {
+            max_wait: Some(max_wait),
+            ..self
+        }
+    }
+}
+
+impl<I: Debug + Send + Sync + 'static> Batcher<I> {
+    /// Reads the input a chunk at a time, `tx` is flushed before waiting for the next chunk
+    fn start(
+        self,
+        mut tx: DataSourceChunkTx<Vec<I>>,
+    ) -> Result<DataSourceJoinHandle, DataStoreError> {
         let name = self.name();
-        use tokio::sync::mpsc::channel;
-        let (tx, rx): (
-            _,
-            Receiver<Result<DataSourceMessage<Vec<I>>, DataStoreError>>,
-        ) = channel(1);
-        let (mut input_rx, _) = self.input.start_stream()?;
+        let (mut input_rx, input_jh) = self.input.start_chunk_stream()?;
         let new_batch_func = self.new_batch;
+        let max_items = self.max_items;
+        let max_bytes = self.max_bytes.zip(self.size);
+        let max_wait = self.max_wait;
         let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
             let mut lines_scanned = 0_usize;
-            let mut batch_vec: Vec<I> = Vec::new();
-            let source: String = name.clone();
+            let mut batch = Batch::new(&name);
             loop {
-                match input_rx.recv().await {
-                    Some(Ok(DataSourceMessage::Data {
-                        source: _,
-                        content: input_item,
-                    })) => match new_batch_func(&input_item, &batch_vec) {
-                        true => {
-                            //source = s;
-                            lines_scanned += 1;
-                            if batch_vec.len() > 0 {
-                                tx.send(Ok(DataSourceMessage::new(&source, batch_vec)))
-                                    .await
-                                    .map_err(|e| DataStoreError::send_error(&name, &source, e))?;
-                                batch_vec = Vec::new();
+                let chunk = match batch.deadline {
+                    Some(deadline) => {
+                        match tokio::time::timeout_at(deadline, input_rx.recv_chunk()).await {
+                            Ok(chunk) => chunk,
+                            Err(_) => {
+                                batch.send(&mut tx, &name).await?;
+                                flush(&mut tx, &name).await?;
+                                continue;
                             }
-                            batch_vec.push(input_item);
                         }
-                        false => {
-                            batch_vec.push(input_item);
-                        }
-                    },
-                    Some(Err(er)) => println!("ERROR: {}", er),
+                    }
+                    None => input_rx.recv_chunk().await,
+                };
+                let chunk = match chunk {
+                    Some(chunk) => chunk,
                     None => break,
                 };
+                for message in chunk {
+                    match message {
+                        Ok(DataSourceMessage::Data {
+                            source,
+                            content: input_item,
+                        }) => {
+                            lines_scanned += 1;
+                            let item_bytes = match &max_bytes {
+                                Some((_, size)) => size(&input_item),
+                                None => 0,
+                            };
+                            let too_big = match &max_bytes {
+                                Some((max_bytes, _)) => batch.bytes + item_bytes > *max_bytes,
+                                None => false,
+                            };
+                            if !batch.items.is_empty()
+                                && (too_big || new_batch_func(&input_item, &batch.items))
+                            {
+                                batch.send(&mut tx, &name).await?;
+                            }
+                            if batch.items.is_empty() {
+                                batch.source = source;
+                                batch.deadline = max_wait.map(|wait| Instant::now() + wait);
+                            }
+                            batch.items.push(input_item);
+                            batch.bytes += item_bytes;
+                            if max_items.is_some_and(|max| batch.items.len() >= max) {
+                                batch.send(&mut tx, &name).await?;
+                            }
+                        }
+                        Err(er) => {
+                            lines_scanned += 1;
+                            tx.send(Err(er))
+                                .await
+                                .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+                        }
+                    }
+                }
+                flush(&mut tx, &name).await?;
             }
-            if batch_vec.len() > 0 {
-                tx.send(Ok(DataSourceMessage::new(&source, batch_vec)))
-                    .await
-                    .map_err(|e| DataStoreError::send_error(&name, &source, e))?;
-            }
-            Ok(DataSourceStats { lines_scanned })
+            batch.send(&mut tx, &name).await?;
+            flush(&mut tx, &name).await?;
+            input_jh.await??;
+            Ok(DataSourceStats::new(lines_scanned))
         });
+        Ok(jh)
+    }
+}
+
+#[async_trait]
+impl<I: Debug + Send + Sync + 'static> DataSource<Vec<I>> for Batcher<I> {
+    fn name(&self) -> String {
+        format!("Batcher-{}", self.input.name())
+    }
+
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<Vec<I>>, DataStoreError> {
+        use tokio::sync::mpsc::channel;
+        let (tx, rx): (
+            _,
+            Receiver<Result<DataSourceMessage<Vec<I>>, DataStoreError>>,
+        ) = channel(channels::buffer_size());
+        let jh = (*self).start(tx.into())?;
         Ok((rx, jh))
     }
+
+    fn start_chunk_stream(self: Box<Self>) -> Result<DataSourceChunkTask<Vec<I>>, DataStoreError> {
+        let (tx, rx) = channels::chunk_channel();
+        let jh = (*self).start(tx)?;
+        Ok((rx, jh))
+    }
+}
+
+async fn flush<I: Send>(tx: &mut DataSourceChunkTx<I>, name: &str) -> Result<(), DataStoreError> {
+    tx.flush()
+        .await
+        .map_err(|e| DataStoreError::send_error(name, "", e))
+}
+
+/// The batch being filled
+struct Batch<I> {
+    items: Vec<I>,
+    /// a batch has the provenance of its first element
+    source: Provenance,
+    bytes: usize,
+    /// when the batch has to be sent, if there is a max wait
+    deadline: Option<Instant>,
+}
+
+impl<I: Debug + Send> Batch<I> {
+    fn new(name: &str) -> Self {
+        Batch {
+            items: Vec::new(),
+            source: Provenance::from(name),
+            bytes: 0,
+            deadline: None,
+        }
+    }
+
+    /// Sends the batch if it is not empty, and starts a new one
+    async fn send(
+        &mut self,
+        tx: &mut DataSourceChunkTx<Vec<I>>,
+        name: &str,
+    ) -> Result<(), DataStoreError> {
+        self.deadline = None;
+        self.bytes = 0;
+        if self.items.is_empty() {
+            return Ok(());
+        }
+        let items = std::mem::take(&mut self.items);
+        let source = self.source.clone();
+        tx.send(Ok(DataSourceMessage::new(source, items)))
+            .await
+            .map_err(|e| DataStoreError::send_error(name, self.source.to_string(), e))
+    }
 }
@@ -0,0 +1,233 @@
+use crate::datastore::error::*;
+use crate::datastore::*;
+use crate::joins::KeyFn;
+use futures_util::stream::{self, StreamExt};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt::Debug;
+use tokio::sync::mpsc::Sender;
+
+/// Reads the inputs one after the other, each one is only started once the previous one is
+/// done.  The stats are the sum of the stats of the inputs
+pub struct Concat<T> {
+    pub inputs: Vec<Box<dyn DataSource<T>>>,
+}
+
+impl<T> Concat<T> {
+    pub fn new(inputs: Vec<Box<dyn DataSource<T>>>) -> Self {
+        Concat { inputs }
+    }
+}
+
+impl<T: Debug + Send + Sync + 'static> DataSource<T> for Concat<T> {
+    fn name(&self) -> String {
+        format!("Concat-{}", names(&self.inputs))
+    }
+
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<T>, DataStoreError> {
+        use tokio::sync::mpsc::channel;
+        use tokio::task::JoinHandle;
+        let (tx, rx) = channel(channels::buffer_size());
+        let name = self.name();
+        let inputs = self.inputs;
+        // the inputs are started from the task, in the scope of the caller
+        let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = channels::spawn(async move {
+            let mut stats = DataSourceStats::default();
+            for input in inputs {
+                let input_name = input.name();
+                let (mut input_rx, input_jh) = match input.start_stream() {
+                    Ok(task) => task,
+                    Err(er) => {
+                        let _ = tx.send(Err(er.clone())).await;
+                        return Err(er);
+                    }
+                };
+                while let Some(message) = input_rx.recv().await {
+                    tx.send(message)
+                        .await
+                        .map_err(|e| DataStoreError::send_error(&name, &input_name, e))?;
+                }
+                add(&mut stats, input_jh.await??);
+            }
+            Ok(stats)
+        });
+        Ok((rx, jh))
+    }
+}
+
+/// Reads all of the inputs at the same time, sending the items as they arrive.  The items of
+/// one input keep their order
+pub struct Merge<T> {
+    pub inputs: Vec<Box<dyn DataSource<T>>>,
+}
+
+impl<T> Merge<T> {
+    pub fn new(inputs: Vec<Box<dyn DataSource<T>>>) -> Self {
+        Merge { inputs }
+    }
+}
+
+impl<T: Debug + Send + Sync + 'static> DataSource<T> for Merge<T> {
+    fn name(&self) -> String {
+        format!("Merge-{}", names(&self.inputs))
+    }
+
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<T>, DataStoreError> {
+        use tokio::sync::mpsc::channel;
+        use tokio::task::JoinHandle;
+        let (tx, rx) = channel(channels::buffer_size());
+        let name = self.name();
+        let (receivers, handles) = start_all(self.inputs)?;
+        let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
+            let mut merged = stream::select_all(
+                receivers
+                    .into_iter()
+                    .map(|mut rx| Box::pin(stream::poll_fn(move |cx| rx.poll_recv(cx)))),
+            );
+            while let Some(message) = merged.next().await {
+                tx.send(message)
+                    .await
+                    .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+            }
+            join_all(handles).await
+        });
+        Ok((rx, jh))
+    }
+}
+
+/// Merges inputs which are sorted by key into one sorted stream.  On equal keys the items of
+/// the earlier input go first.  Keys going backwards on an input stop the merge with an error
+pub struct OrderedMerge<T, K> {
+    pub inputs: Vec<Box<dyn DataSource<T>>>,
+    pub key: KeyFn<T, K>,
+}
+
+impl<T, K> OrderedMerge<T, K> {
+    pub fn new<F>(inputs: Vec<Box<dyn DataSource<T>>>, key: F) -> Self
+    where
+        F: Fn(&T) -> K + Send + Sync + 'static,
+    {
+        OrderedMerge {
+            inputs,
+            key: Box::new(key),
+        }
+    }
+}
+
+impl<T, K> DataSource<T> for OrderedMerge<T, K>
+where
+    T: Debug + Send + Sync + 'static,
+    K: Ord + Debug + Send + Sync + 'static,
+{
+    fn name(&self) -> String {
+        format!("OrderedMerge-{}", names(&self.inputs))
+    }
+
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<T>, DataStoreError> {
+        use tokio::sync::mpsc::channel;
+        use tokio::task::JoinHandle;
+        let (tx, rx) = channel(channels::buffer_size());
+        let name = self.name();
+        let key = self.key;
+        let (receivers, handles) = start_all(self.inputs)?;
+        let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
+            let mut inputs = receivers;
+            let mut heads = Vec::with_capacity(inputs.len());
+            let mut heap = BinaryHeap::with_capacity(inputs.len());
+            for (idx, input) in inputs.iter_mut().enumerate() {
+                let head = next(input, &tx, &name).await?;
+                if let Some((_, item)) = &head {
+                    heap.push(Reverse((key(item), idx)));
+                }
+                heads.push(head);
+            }
+            while let Some(Reverse((last_key, idx))) = heap.pop() {
+                if let Some((source, item)) = heads[idx].take() {
+                    tx.send(Ok(DataSourceMessage::new(source, item)))
+                        .await
+                        .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+                }
+                if let Some((source, item)) = next(&mut inputs[idx], &tx, &name).await? {
+                    let next_key = key(&item);
+                    if next_key < last_key {
+                        let er = DataStoreError::Generic(format!(
+                            "OrderedMerge input {} is not sorted, key {:?} came after {:?}",
+                            idx, next_key, last_key
+                        ))
+                        .at(source);
+                        let _ = tx.send(Err(er.clone())).await;
+                        return Err(er);
+                    }
+                    heap.push(Reverse((next_key, idx)));
+                    heads[idx] = Some((source, item));
+                }
+            }
+            join_all(handles).await
+        });
+        Ok((rx, jh))
+    }
+}
+
+fn names<T: Debug + Send + 'static>(inputs: &[Box<dyn DataSource<T>>]) -> String {
+    inputs
+        .iter()
+        .map(|input| input.name())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn add(total: &mut DataSourceStats, stats: DataSourceStats) {
+    total.lines_scanned += stats.lines_scanned;
+    total.duplicates += stats.duplicates;
+}
+
+fn start_all<T: Debug + Send + 'static>(
+    inputs: Vec<Box<dyn DataSource<T>>>,
+) -> Result<(Vec<DataSourceRx<T>>, Vec<DataSourceJoinHandle>), DataStoreError> {
+    let mut receivers = Vec::with_capacity(inputs.len());
+    let mut handles = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let (rx, jh) = input.start_stream()?;
+        receivers.push(rx);
+        handles.push(jh);
+    }
+    Ok((receivers, handles))
+}
+
+/// Waits for every input, the first error is returned
+async fn join_all(handles: Vec<DataSourceJoinHandle>) -> Result<DataSourceStats, DataStoreError> {
+    let mut stats = DataSourceStats::default();
+    let mut error = None;
+    for handle in handles {
+        match handle.await? {
+            Ok(input_stats) => add(&mut stats, input_stats),
+            Err(er) => {
+                error.get_or_insert(er);
+            }
+        }
+    }
+    match error {
+        Some(er) => Err(er),
+        None => Ok(stats),
+    }
+}
+
+/// The next item of the input, errors are forwarded to `tx` as they come
+async fn next<T: Debug + Send>(
+    rx: &mut DataSourceRx<T>,
+    tx: &Sender<Result<DataSourceMessage<T>, DataStoreError>>,
+    name: &str,
+) -> Result<Option<(Provenance, T)>, DataStoreError> {
+    loop {
+        match rx.recv().await {
+            Some(Ok(DataSourceMessage::Data { source, content })) => {
+                return Ok(Some((source, content)))
+            }
+            Some(Err(er)) => tx
+                .send(Err(er))
+                .await
+                .map_err(|e| DataStoreError::send_error(name, "", e))?,
+            None => return Ok(None),
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use std::future::Future;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::task::JoinHandle;
+
+tokio::task_local! {
+    static CHANNELS: ChannelConfig;
+}
+
+/// Sizes of the channels created by the built-in stages.  A stage uses the config of the scope
+/// its stream is started in, see [ChannelConfig::scope], or the default outside of one.
+/// `etl_job::job::JobRunner` starts its streams in the scope of
+/// `etl_job::job::JobRunnerConfig::channels`, streams started by hand (like the outputs of a
+/// [crate::splitter::Partitioner]) have to be started in a scope to use another config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelConfig {
+    /// number of messages a channel between two stages holds before the sender waits
+    pub buffer_size: usize,
+    /// number of items stages send at once on the channels between them, see [chunk_channel]
+    pub chunk_size: usize,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        ChannelConfig {
+            buffer_size: 1,
+            chunk_size: 1,
+        }
+    }
+}
+
+impl ChannelConfig {
+    /// The config of the scope the caller runs in
+    pub fn current() -> Self {
+        CHANNELS.try_with(|config| *config).unwrap_or_default()
+    }
+
+    /// Runs `f` with this config, the streams it starts use it
+    pub async fn scope<F: Future>(self, f: F) -> F::Output {
+        CHANNELS.scope(self.checked(), f).await
+    }
+
+    /// Like [ChannelConfig::scope], for streams started outside of an async block
+    pub fn sync_scope<F: FnOnce() -> R, R>(self, f: F) -> R {
+        CHANNELS.sync_scope(self.checked(), f)
+    }
+
+    fn checked(self) -> Self {
+        ChannelConfig {
+            buffer_size: self.buffer_size.max(1),
+            chunk_size: self.chunk_size.max(1),
+        }
+    }
+}
+
+/// Buffer size of the channels between stages
+pub fn buffer_size() -> usize {
+    ChannelConfig::current().buffer_size
+}
+
+/// Number of items sent at once on chunked channels
+pub fn chunk_size() -> usize {
+    ChannelConfig::current().chunk_size
+}
+
+/// Spawns the task of a stage with the config of the caller, for tasks which start streams or
+/// create channels themselves
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(ChannelConfig::current().scope(future))
+}
+
+/// Creates a channel which passes items in chunks of the configured [chunk_size], so the cost of
+/// a send (like waking up the receiver) is paid once per chunk.  The built-in stages pass chunks
+/// to each other with it, see `DataSource::start_chunk_stream`.
+pub fn chunk_channel<T>() -> (ChunkSender<T>, ChunkReceiver<T>) {
+    let (tx, rx) = channel(buffer_size());
+    let chunk_size = chunk_size();
+    (
+        ChunkSender {
+            tx: ChunkTx::Chunks(tx),
+            chunk: Vec::with_capacity(chunk_size),
+            chunk_size,
+        },
+        ChunkReceiver {
+            rx: ChunkRx::Chunks(rx),
+            chunk: Vec::new().into_iter(),
+            chunk_size,
+        },
+    )
+}
+
+enum ChunkTx<T> {
+    Chunks(Sender<Vec<T>>),
+    Items(Sender<T>),
+}
+
+/// Sending half of a [chunk_channel], or of a plain channel when made from a `Sender` so a
+/// stage can send to either.  Items are held until the chunk is full, call `flush` (or
+/// `blocking_flush`) whenever the stage waits for its input and once done sending, since the
+/// items of a partial chunk are lost when it is dropped.
+pub struct ChunkSender<T> {
+    tx: ChunkTx<T>,
+    chunk: Vec<T>,
+    chunk_size: usize,
+}
+
+impl<T> From<Sender<T>> for ChunkSender<T> {
+    fn from(tx: Sender<T>) -> Self {
+        ChunkSender {
+            tx: ChunkTx::Items(tx),
+            chunk: Vec::new(),
+            chunk_size: 1,
+        }
+    }
+}
+
+impl<T> ChunkSender<T> {
+    pub async fn send(&mut self, item: T) -> Result<(), SendError<()>> {
+        match &self.tx {
+            ChunkTx::Items(tx) => tx.send(item).await.map_err(|_| SendError(())),
+            ChunkTx::Chunks(_) => {
+                self.chunk.push(item);
+                if self.chunk.len() >= self.chunk_size {
+                    self.flush().await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn flush(&mut self) -> Result<(), SendError<()>> {
+        if let (ChunkTx::Chunks(tx), false) = (&self.tx, self.chunk.is_empty()) {
+            let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(self.chunk_size));
+            tx.send(chunk).await.map_err(|_| SendError(()))?;
+        }
+        Ok(())
+    }
+
+    /// Like `send`, for use outside of the runtime, for example in a `spawn_blocking` thread
+    pub fn blocking_send(&mut self, item: T) -> Result<(), SendError<()>> {
+        match &self.tx {
+            ChunkTx::Items(tx) => tx.blocking_send(item).map_err(|_| SendError(())),
+            ChunkTx::Chunks(_) => {
+                self.chunk.push(item);
+                if self.chunk.len() >= self.chunk_size {
+                    self.blocking_flush()?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn blocking_flush(&mut self) -> Result<(), SendError<()>> {
+        if let (ChunkTx::Chunks(tx), false) = (&self.tx, self.chunk.is_empty()) {
+            let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(self.chunk_size));
+            tx.blocking_send(chunk).map_err(|_| SendError(()))?;
+        }
+        Ok(())
+    }
+}
+
+enum ChunkRx<T> {
+    Chunks(Receiver<Vec<T>>),
+    Items(Receiver<T>),
+}
+
+/// Receiving half of a [chunk_channel], or of a plain channel when made from a `Receiver`.
+/// Hands out the items one at a time or a chunk at a time
+pub struct ChunkReceiver<T> {
+    rx: ChunkRx<T>,
+    chunk: std::vec::IntoIter<T>,
+    chunk_size: usize,
+}
+
+impl<T> From<Receiver<T>> for ChunkReceiver<T> {
+    fn from(rx: Receiver<T>) -> Self {
+        ChunkReceiver {
+            rx: ChunkRx::Items(rx),
+            chunk: Vec::new().into_iter(),
+            chunk_size: chunk_size(),
+        }
+    }
+}
+
+impl<T> ChunkReceiver<T> {
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            if let Some(item) = self.chunk.next() {
+                return Some(item);
+            }
+            match &mut self.rx {
+                ChunkRx::Chunks(rx) => self.chunk = rx.recv().await?.into_iter(),
+                ChunkRx::Items(rx) => return rx.recv().await,
+            }
+        }
+    }
+
+    pub fn blocking_recv(&mut self) -> Option<T> {
+        loop {
+            if let Some(item) = self.chunk.next() {
+                return Some(item);
+            }
+            match &mut self.rx {
+                ChunkRx::Chunks(rx) => self.chunk = rx.blocking_recv()?.into_iter(),
+                ChunkRx::Items(rx) => return rx.blocking_recv(),
+            }
+        }
+    }
+
+    /// Waits for the next chunk.  Items sent one at a time are gathered, up to [chunk_size], as
+    /// long as more of them are ready
+    pub async fn recv_chunk(&mut self) -> Option<Vec<T>> {
+        if !self.chunk.as_slice().is_empty() {
+            return Some(std::mem::replace(&mut self.chunk, Vec::new().into_iter()).collect());
+        }
+        match &mut self.rx {
+            ChunkRx::Chunks(rx) => rx.recv().await,
+            ChunkRx::Items(rx) => {
+                let mut chunk = vec![rx.recv().await?];
+                while chunk.len() < self.chunk_size {
+                    match rx.try_recv() {
+                        Ok(item) => chunk.push(item),
+                        Err(_) => break,
+                    }
+                }
+                Some(chunk)
+            }
+        }
+    }
+
+    /// The receiver of a plain channel, or the ChunkReceiver itself when the items come in
+    /// chunks
+    pub fn into_items(self) -> Result<Receiver<T>, Self> {
+        match self.rx {
+            ChunkRx::Items(rx) if self.chunk.as_slice().is_empty() => Ok(rx),
+            rx => Err(ChunkReceiver { rx, ..self }),
+        }
+    }
+
+    /// Sends every item received to `tx` until the sending half is dropped, returns the number
+    /// of items forwarded
+    pub async fn forward(&mut self, tx: &Sender<T>) -> Result<usize, SendError<T>> {
+        let mut forwarded = 0_usize;
+        while let Some(item) = self.recv().await {
+            tx.send(item).await?;
+            forwarded += 1;
+        }
+        Ok(forwarded)
+    }
+}
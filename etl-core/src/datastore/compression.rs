@@ -0,0 +1,135 @@
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
+use bytes::Bytes;
+use std::path::Path;
+use tokio::io::{AsyncBufRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Compression applied to the content of a file.  Sources and outputs which work with files
+/// detect it from the file extension unless it is given explicitly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    /// `.gz` or `.gzip`
+    Gzip,
+    /// `.zst` or `.zstd`
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("gz") | Some("gzip") => Compression::Gzip,
+            Some("zst") | Some("zstd") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Uses the given compression, otherwise detects it from the path
+    pub fn or_from_path<P: AsRef<Path>>(compression: Option<Compression>, path: P) -> Self {
+        match compression {
+            Some(c) => c,
+            None => Compression::from_path(path),
+        }
+    }
+
+    /// Wraps a reader so the decompressed content can be read from it
+    pub fn decompress_reader<'a, R>(&self, reader: R) -> Box<dyn AsyncBufRead + Unpin + Send + 'a>
+    where
+        R: AsyncBufRead + Unpin + Send + 'a,
+    {
+        match self {
+            Compression::None => Box::new(reader),
+            Compression::Gzip => {
+                let mut decoder = GzipDecoder::new(reader);
+                // concatenated gzip files are valid gzip files
+                decoder.multiple_members(true);
+                Box::new(BufReader::new(decoder))
+            }
+            Compression::Zstd => {
+                let mut decoder = ZstdDecoder::new(reader);
+                decoder.multiple_members(true);
+                Box::new(BufReader::new(decoder))
+            }
+        }
+    }
+
+    /// Wraps a writer so everything written to it is compressed.  Make sure to call shutdown on
+    /// the returned writer, otherwise the end of the compressed stream is not written.
+    pub fn compress_writer<'a, W>(&self, writer: W) -> Box<dyn AsyncWrite + Unpin + Send + 'a>
+    where
+        W: AsyncWrite + Unpin + Send + 'a,
+    {
+        match self {
+            Compression::None => Box::new(writer),
+            Compression::Gzip => Box::new(GzipEncoder::new(writer)),
+            Compression::Zstd => Box::new(ZstdEncoder::new(writer)),
+        }
+    }
+}
+
+/// Compresses bytes in memory for outputs which upload the content in chunks, like S3 multipart
+/// uploads.  The compressed bytes are taken out with [BytesEncoder::take] as they become
+/// available.
+pub enum BytesEncoder {
+    None(Vec<u8>),
+    Gzip(GzipEncoder<Vec<u8>>),
+    Zstd(ZstdEncoder<Vec<u8>>),
+}
+
+impl BytesEncoder {
+    pub fn new(compression: Compression) -> Self {
+        match compression {
+            Compression::None => BytesEncoder::None(Vec::new()),
+            Compression::Gzip => BytesEncoder::Gzip(GzipEncoder::new(Vec::new())),
+            Compression::Zstd => BytesEncoder::Zstd(ZstdEncoder::new(Vec::new())),
+        }
+    }
+
+    pub async fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        match self {
+            BytesEncoder::None(buf) => {
+                buf.extend_from_slice(data);
+                Ok(())
+            }
+            BytesEncoder::Gzip(encoder) => encoder.write_all(data).await,
+            BytesEncoder::Zstd(encoder) => encoder.write_all(data).await,
+        }
+    }
+
+    fn buf_mut(&mut self) -> &mut Vec<u8> {
+        match self {
+            BytesEncoder::None(buf) => buf,
+            BytesEncoder::Gzip(encoder) => encoder.get_mut(),
+            BytesEncoder::Zstd(encoder) => encoder.get_mut(),
+        }
+    }
+
+    /// number of compressed bytes ready to be taken
+    pub fn len(&self) -> usize {
+        match self {
+            BytesEncoder::None(buf) => buf.len(),
+            BytesEncoder::Gzip(encoder) => encoder.get_ref().len(),
+            BytesEncoder::Zstd(encoder) => encoder.get_ref().len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// takes the compressed bytes produced so far
+    pub fn take(&mut self) -> Bytes {
+        Bytes::from(std::mem::take(self.buf_mut()))
+    }
+
+    /// finishes the compressed stream and returns the remaining bytes
+    pub async fn finish(mut self) -> std::io::Result<Bytes> {
+        match &mut self {
+            BytesEncoder::None(_) => {}
+            BytesEncoder::Gzip(encoder) => encoder.shutdown().await?,
+            BytesEncoder::Zstd(encoder) => encoder.shutdown().await?,
+        };
+        Ok(self.take())
+    }
+}
@@ -3,13 +3,31 @@ use futures_core::future::BoxFuture;
 use serde::de::DeserializeOwned;
 use std::time::Duration;
 
+pub type BoxedEnumerateFn<S, O> = Box<dyn Fn(&'_ S, usize) -> DataOutputItemResult<O> + Send + Sync>;
+
 pub struct EnumerateStream<S, O> {
     pub name: String,
     /// generates maximum elements, otherwise it is unlimited
     pub max: Option<usize>,
     pub pause: Option<Duration>,
     pub state: S,
-    pub create: fn(&'_ S, usize) -> DataOutputItemResult<O>,
+    pub create: BoxedEnumerateFn<S, O>,
+}
+
+impl<S, O> EnumerateStream<S, O> {
+    pub fn with_max<N, F>(name: N, max: usize, state: S, create_func: F) -> Self
+    where
+        N: Into<String>,
+        F: Fn(&'_ S, usize) -> DataOutputItemResult<O> + Send + Sync + 'static,
+    {
+        EnumerateStream {
+            name: name.into(),
+            max: Some(max),
+            pause: None,
+            state,
+            create: Box::new(create_func),
+        }
+    }
 }
 
 impl<S: Send + Sync + 'static, O: DeserializeOwned + Debug + Send + Sync + 'static> DataSource<O>
@@ -21,7 +39,7 @@ impl<S: Send + Sync + 'static, O: DeserializeOwned + Debug + Send + Sync + 'stat
 
     fn start_stream(self: Box<Self>) -> Result<DataSourceTask<O>, DataStoreError> {
         use tokio::sync::mpsc::channel;
-        let (tx, rx): (_, Receiver<Result<DataSourceMessage<O>, DataStoreError>>) = channel(1);
+        let (tx, rx): (_, Receiver<Result<DataSourceMessage<O>, DataStoreError>>) = channel(channels::buffer_size());
         let create_func = self.create;
         let name = self.name;
         let maybe_pause = self.pause;
@@ -37,7 +55,10 @@ impl<S: Send + Sync + 'static, O: DeserializeOwned + Debug + Send + Sync + 'stat
                 }
                 match create_func(&state, lines_scanned) {
                     Ok(output_item) => {
-                        tx.send(Ok(DataSourceMessage::new(&name, output_item)))
+                        tx.send(Ok(DataSourceMessage::new(
+                            Provenance::from(&name).with_line(lines_scanned + 1),
+                            output_item,
+                        )))
                             .await
                             .map_err(|e| DataStoreError::send_error(&name, &name, e))?;
                     }
@@ -52,7 +73,7 @@ impl<S: Send + Sync + 'static, O: DeserializeOwned + Debug + Send + Sync + 'stat
                 }
                 lines_scanned += 1;
             }
-            Ok(DataSourceStats { lines_scanned })
+            Ok(DataSourceStats::new(lines_scanned))
         });
         Ok((rx, jh))
     }
@@ -97,7 +118,7 @@ impl<S: Send + Sync + 'static, O: DeserializeOwned + Debug + Send + Sync + 'stat
 
     fn start_stream(self: Box<Self>) -> Result<DataSourceTask<O>, DataStoreError> {
         use tokio::sync::mpsc::channel;
-        let (tx, rx): (_, Receiver<Result<DataSourceMessage<O>, DataStoreError>>) = channel(1);
+        let (tx, rx): (_, Receiver<Result<DataSourceMessage<O>, DataStoreError>>) = channel(channels::buffer_size());
         let create_func = self.create;
         let name = self.name;
         let maybe_pause = self.pause;
@@ -113,7 +134,10 @@ impl<S: Send + Sync + 'static, O: DeserializeOwned + Debug + Send + Sync + 'stat
                 }
                 match create_func(&state, lines_scanned).await {
                     Ok(output_item) => {
-                        tx.send(Ok(DataSourceMessage::new(&name, output_item)))
+                        tx.send(Ok(DataSourceMessage::new(
+                            Provenance::from(&name).with_line(lines_scanned + 1),
+                            output_item,
+                        )))
                             .await
                             .map_err(|e| DataStoreError::send_error(&name, &name, e))?;
                     }
@@ -128,7 +152,7 @@ impl<S: Send + Sync + 'static, O: DeserializeOwned + Debug + Send + Sync + 'stat
                 }
                 lines_scanned += 1;
             }
-            Ok(DataSourceStats { lines_scanned })
+            Ok(DataSourceStats::new(lines_scanned))
         });
         Ok((rx, jh))
     }
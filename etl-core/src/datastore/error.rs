@@ -34,6 +34,14 @@ pub enum DataStoreError {
     JoinError(String),
     #[error("Shutting down.  JobManager sent a global TooManyErrors message.")]
     TooManyErrors,
+    /// An error caused by a single record, with the provenance of that record
+    #[error("{error} (at `{provenance}`)")]
+    Record {
+        provenance: Provenance,
+        error: Box<DataStoreError>,
+        /// the record as it was received, when the error does not keep it itself
+        raw: Option<String>,
+    },
 }
 
 impl DataStoreError {
@@ -48,6 +56,53 @@ impl DataStoreError {
             reason: reason.to_string(),
         }
     }
+
+    /// Attaches the provenance of the record which caused the error.  Errors which already have
+    /// one keep it, since it points closer to the input
+    pub fn at<P: Into<Provenance>>(self, provenance: P) -> Self {
+        self.at_record(provenance, None)
+    }
+
+    /// Like [DataStoreError::at], also keeping the record which caused the error, like the
+    /// item a transform failed on as JSON
+    pub fn at_record<P: Into<Provenance>>(self, provenance: P, raw: Option<String>) -> Self {
+        match self {
+            DataStoreError::Record {
+                provenance,
+                error,
+                raw: None,
+            } => DataStoreError::Record {
+                provenance,
+                error,
+                raw,
+            },
+            DataStoreError::Record { .. } => self,
+            error => DataStoreError::Record {
+                provenance: provenance.into(),
+                error: Box::new(error),
+                raw,
+            },
+        }
+    }
+
+    /// The provenance of the record which caused the error, if it is known
+    pub fn provenance(&self) -> Option<&Provenance> {
+        match self {
+            DataStoreError::Record { provenance, .. } => Some(provenance),
+            _ => None,
+        }
+    }
+
+    /// The record which caused the error, if the error kept it
+    pub fn raw(&self) -> Option<&str> {
+        match self {
+            DataStoreError::Record { error, raw, .. } => raw.as_deref().or_else(|| error.raw()),
+            DataStoreError::Deserialize {
+                attempted_string, ..
+            } => Some(attempted_string),
+            _ => None,
+        }
+    }
 }
 
 use tokio::task::JoinError;
@@ -1,7 +1,8 @@
 use super::error::*;
+use crate::datastore::compression::Compression;
 use crate::datastore::simple::SimpleStore;
 use crate::datastore::{
-    DataSource, DataSourceStats, DataSourceTask, DataSourceMessage,
+    channels, DataSource, DataSourceStats, DataSourceTask, DataSourceMessage, Provenance,
     DataOutput, DataOutputMessage, DataOutputStats, DataOutputTask, DataOutputTx,
 };
 use crate::queue::QueueClient;
@@ -10,14 +11,161 @@ use bytes::Bytes;
 use log;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::collections::HashSet;
 use std::hash::Hash;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 use tokio::task::JoinHandle;
 
+pub use regex::Regex;
+
 pub struct LocalFs {
     pub files: Vec<String>,
     pub home: String,
     pub output_name: Option<String>,
+    /// When not set, compression is detected from the extension of each file (`.gz`, `.zst`)
+    pub compression: Option<Compression>,
+    /// Find files inside `home` to read after the ones listed in `files`
+    pub discover: Option<FileDiscovery>,
+    /// When used as a DataOutput, start a new file once the current one is large enough
+    pub rotate: Option<FileRotation>,
+    /// When used as a DataSource, send the content of each file as a single message instead of
+    /// line by line.  Needed by binary formats like Parquet
+    pub whole_files: bool,
+}
+
+/// Splits the output of a [LocalFs] into several files.  A new file is started before writing an
+/// item which would go over either limit.
+#[derive(Debug, Clone, Default)]
+pub struct FileRotation {
+    /// maximum number of bytes per file, before compression
+    pub max_bytes: Option<usize>,
+    /// maximum number of items per file
+    pub max_lines: Option<usize>,
+    /// Name of each file relative to `home`, where `{index}` is replaced with the zero padded
+    /// file number like `part-{index}.jsonl` becomes `part-00001.jsonl`.  When not set, the
+    /// number is added to the `output_name` before its extension
+    pub name_template: Option<String>,
+}
+
+impl FileRotation {
+    pub fn file_name(&self, output_name: &str, index: usize) -> String {
+        let index = format!("{:05}", index);
+        match &self.name_template {
+            Some(template) => template.replace("{index}", &index),
+            None => {
+                let p = Path::new(output_name);
+                match (p.file_stem(), p.extension()) {
+                    (Some(stem), Some(ext)) => p
+                        .with_file_name(format!(
+                            "{}-{}.{}",
+                            stem.to_string_lossy(),
+                            index,
+                            ext.to_string_lossy()
+                        ))
+                        .to_string_lossy()
+                        .to_string(),
+                    _ => format!("{}-{}", output_name, index),
+                }
+            }
+        }
+    }
+
+    fn is_full(&self, bytes_written: usize, lines_written: usize, next_len: usize) -> bool {
+        if lines_written == 0 {
+            return false;
+        }
+        self.max_lines.is_some_and(|max| lines_written >= max)
+            || self
+                .max_bytes
+                .is_some_and(|max| bytes_written + next_len > max)
+    }
+}
+
+/// Describes which files in the `home` of a [LocalFs] are read and in what order.  All paths
+/// are relative to `home` and use `/` as the separator.
+#[derive(Debug, Clone, Default)]
+pub struct FileDiscovery {
+    /// glob patterns like `*.csv` or `extracts/**/*.json.gz`, a file is read if it matches any
+    /// of them.  When empty every file is matched
+    pub globs: Vec<String>,
+    /// look inside sub directories as well
+    pub recursive: bool,
+    /// only read files where the path also matches
+    pub regex: Option<Regex>,
+    pub order: FileOrder,
+    /// paths which are never read, for example inputs which a previous run of the job already
+    /// processed.  See etl_job::job::JobRunner::processed_files
+    pub skip: HashSet<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileOrder {
+    /// by the relative path
+    #[default]
+    Name,
+    /// oldest modified first
+    Modified,
+}
+
+impl FileDiscovery {
+    /// Walks the home directory and returns the relative paths of matching files
+    pub async fn find_files(&self, home: &str) -> Result<Vec<String>, DataStoreError> {
+        use glob::{MatchOptions, Pattern};
+        let patterns = self
+            .globs
+            .iter()
+            .map(|g| Pattern::new(g))
+            .collect::<Result<Vec<Pattern>, _>>()
+            .map_err(|e| DataStoreError::Generic(format!("Invalid glob pattern: {}", e)))?;
+        let match_options = MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        let home_path = Path::new(home);
+        let mut found: Vec<(String, SystemTime)> = Vec::new();
+        let mut dirs: Vec<PathBuf> = vec![home_path.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = tokio::fs::read_dir(&dir).await.map_err(|e| {
+                DataStoreError::NotExist {
+                    key: format!("{:?}", &dir),
+                    error: e.to_string(),
+                }
+            })?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let metadata = entry.metadata().await?;
+                if metadata.is_dir() {
+                    if self.recursive {
+                        dirs.push(path);
+                    }
+                    continue;
+                }
+                let relative = path
+                    .strip_prefix(home_path)
+                    .unwrap_or(&path)
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                let is_match = (patterns.is_empty()
+                    || patterns
+                        .iter()
+                        .any(|p| p.matches_with(&relative, match_options)))
+                    && self.regex.as_ref().is_none_or(|re| re.is_match(&relative))
+                    && !self.skip.contains(&relative);
+                if is_match {
+                    found.push((relative, metadata.modified()?));
+                }
+            }
+        }
+        match self.order {
+            FileOrder::Name => found.sort_by(|a, b| a.0.cmp(&b.0)),
+            FileOrder::Modified => found.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0))),
+        };
+        Ok(found.into_iter().map(|(f, _)| f).collect())
+    }
 }
 
 impl Default for LocalFs {
@@ -26,6 +174,10 @@ impl Default for LocalFs {
             files: Vec::new(),
             home: "".to_string(),
             output_name: Some("output".to_string()),
+            compression: None,
+            discover: None,
+            rotate: None,
+            whole_files: false,
         }
     }
 }
@@ -37,13 +189,15 @@ impl DataSource<Bytes> for LocalFs {
 
     fn start_stream(self: Box<Self>) -> Result<DataSourceTask<Bytes>, DataStoreError> {
         use tokio::fs::File;
-        use tokio::io::{AsyncBufReadExt, BufReader};
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
         use tokio::sync::mpsc::channel;
-        let (tx, rx) = channel(1);
-        let files = self.files.clone();
+        let (tx, rx) = channel(channels::buffer_size());
         let home = self.home.clone();
+        let compression = self.compression;
+        let whole_files = self.whole_files;
         let name = self.name();
         let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
+            let files = self.list_files().await?;
             let mut lines_scanned = 0_usize;
             for fname in files {
                 //TODO: would prefer this to fail higher, but the test is not handled properly
@@ -52,19 +206,46 @@ impl DataSource<Bytes> for LocalFs {
                     .await
                     .expect("File not found");
                 // 68 mb in size
-                let mut lines = BufReader::with_capacity(1 << 26, file).lines();
+                let reader = BufReader::with_capacity(1 << 26, file);
+                let mut reader =
+                    Compression::or_from_path(compression, &fname).decompress_reader(reader);
+                if whole_files {
+                    let mut content = Vec::new();
+                    reader.read_to_end(&mut content).await?;
+                    lines_scanned += 1;
+                    tx.send(Ok(DataSourceMessage::new(
+                        Provenance::new(&name, &fname),
+                        Bytes::from(content),
+                    )))
+                    .await
+                    .map_err(|er| DataStoreError::send_error(&name, &fname, er))?;
+                    continue;
+                }
+                let mut line = String::new();
+                let mut line_number = 0_usize;
+                let mut offset = 0_u64;
                 loop {
-                    if let Some(line) = lines.next_line().await? {
-                        lines_scanned += 1;
-                        tx.send(Ok(DataSourceMessage::new(&fname, Bytes::from(line))))
-                            .await
-                            .map_err(|er| DataStoreError::send_error(&name, &fname, er))?;
-                    } else {
+                    line.clear();
+                    let len = reader.read_line(&mut line).await?;
+                    if len == 0 {
                         break;
                     }
+                    lines_scanned += 1;
+                    line_number += 1;
+                    let provenance = Provenance::new(&name, &fname)
+                        .with_line(line_number)
+                        .with_offset(offset);
+                    offset += len as u64;
+                    let content = line.trim_end_matches('\n').trim_end_matches('\r');
+                    tx.send(Ok(DataSourceMessage::new(
+                        provenance,
+                        Bytes::from(content.to_string()),
+                    )))
+                    .await
+                    .map_err(|er| DataStoreError::send_error(&name, &fname, er))?;
                 }
             }
-            Ok(DataSourceStats { lines_scanned })
+            Ok(DataSourceStats::new(lines_scanned))
         });
         Ok((rx, jh))
     }
@@ -148,6 +329,20 @@ impl<T: Hash + Serialize + DeserializeOwned + std::fmt::Debug + Send + Sync + 's
 }
 
 impl LocalFs {
+    /// The files which are read when used as a DataSource, the listed `files` followed by any
+    /// discovered ones
+    pub async fn list_files(&self) -> Result<Vec<String>, DataStoreError> {
+        let mut files = self.files.clone();
+        if let Some(discover) = &self.discover {
+            for f in discover.find_files(&self.home).await? {
+                if !files.contains(&f) {
+                    files.push(f);
+                }
+            }
+        }
+        Ok(files)
+    }
+
     pub async fn load_toml<T>(p: &str, autocreate: bool) -> anyhow::Result<T>
     where
         T: Serialize + DeserializeOwned + std::fmt::Debug + Default,
@@ -180,48 +375,94 @@ impl LocalFs {
     }
 }
 
+/// Creates (or truncates) a file inside home for writing, the content is compressed depending on
+/// the file extension unless compression is given
+async fn create_output_file(
+    home: &str,
+    filename: &str,
+    compression: Option<Compression>,
+) -> Result<(Box<dyn AsyncWrite + Unpin + Send>, String), DataStoreError> {
+    use tokio::fs::OpenOptions;
+    let full_path_str = format!("{}/{}", home, filename);
+    let full_path = Path::new(home).join(filename);
+    log::info!("Writing to file {:?}", &full_path);
+    if let Some(parent_folder) = full_path.parent() {
+        tokio::fs::create_dir_all(parent_folder).await?;
+        log::info!("Writing to folder {:?}", &parent_folder);
+    } else {
+        tokio::fs::create_dir_all(Path::new(home)).await?;
+        log::info!("Writing to folder {}", home);
+    }
+    let file = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(&full_path)
+        .await
+        .map_err(|e| {
+            DataStoreError::FatalIO(format!(
+                "LocalFs ran into an error trying to open the file: {:?} caused by error: {}",
+                &full_path, e
+            ))
+        })?;
+    Ok((
+        Compression::or_from_path(compression, &full_path).compress_writer(file),
+        full_path_str,
+    ))
+}
+
 #[async_trait]
 impl DataOutput<Bytes> for LocalFs {
     async fn start_stream(
         self: Box<Self>,
     ) -> anyhow::Result<DataOutputTask<Bytes>> {
-        use tokio::fs::OpenOptions;
-        use tokio::io::AsyncWriteExt;
         use tokio::sync::mpsc::channel;
         let filename = match self.output_name {
             Some(n) => n,
             None => "output".to_string(),
         };
-        let full_path_str = format!("{}/{}", &self.home, &filename);
-        let full_path = Path::new(&self.home).join(&filename);
-        log::info!("Writing to file {:?}", &full_path);
-        if let Some(parent_folder) = full_path.parent() {
-            tokio::fs::create_dir_all(parent_folder).await?;
-            log::info!("Writing to folder {:?}", &parent_folder);
-        } else {
-            tokio::fs::create_dir_all(Path::new(&self.home)).await?;
-            log::info!("Writing to folder {}", &self.home);
-        }
-        let mut file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .create(true)
-            .open(&full_path)
-            .await
-            .map_err(|e| {
-                DataStoreError::FatalIO(format!(
-                    "LocalFs ran into an error trying to open the file: {:?} caused by error: {}",
-                    &full_path, e
-                ))
-            })?;
-        let (tx, mut rx): (DataOutputTx<Bytes>, _) = channel(1);
+        let home = self.home;
+        let compression = self.compression;
+        let rotate = self.rotate;
+        let mut part_index = 1_usize;
+        let mut part_name = match &rotate {
+            Some(rotate) => rotate.file_name(&filename, part_index),
+            None => filename.clone(),
+        };
+        let (mut file, mut full_path_str) =
+            create_output_file(&home, &part_name, compression).await?;
+        let (tx, mut rx): (DataOutputTx<Bytes>, _) = channel(channels::buffer_size());
         let jh: JoinHandle<anyhow::Result<DataOutputStats>> = tokio::spawn(async move {
             let mut num_lines_sent = 0_usize;
+            let mut part_lines = 0_usize;
+            let mut part_bytes = 0_usize;
+            let mut parts = Vec::new();
             loop {
                 match rx.recv().await {
                     Some(DataOutputMessage::Data(item)) => {
+                        if let Some(rotate) = &rotate {
+                            if rotate.is_full(part_bytes, part_lines, item.len()) {
+                                file.shutdown().await?;
+                                parts.push(DataOutputStats {
+                                    name: part_name,
+                                    key: Some(full_path_str),
+                                    lines_written: part_lines,
+                                    ..Default::default()
+                                });
+                                part_index += 1;
+                                part_name = rotate.file_name(&filename, part_index);
+                                let (f, p) =
+                                    create_output_file(&home, &part_name, compression).await?;
+                                file = f;
+                                full_path_str = p;
+                                part_lines = 0;
+                                part_bytes = 0;
+                            }
+                        }
                         file.write_all(&item).await?;
                         num_lines_sent += 1;
+                        part_lines += 1;
+                        part_bytes += item.len();
                     }
                     Some(DataOutputMessage::NoMoreData) => {
                         break;
@@ -231,11 +472,30 @@ impl DataOutput<Bytes> for LocalFs {
                     }
                 }
             }
-            Ok(DataOutputStats {
-                name: filename,
-                key: Some(full_path_str),
-                lines_written: num_lines_sent,
-            })
+            // also writes the end of the compressed stream
+            file.shutdown().await?;
+            match rotate {
+                Some(_) => {
+                    parts.push(DataOutputStats {
+                        name: part_name,
+                        key: Some(full_path_str),
+                        lines_written: part_lines,
+                        ..Default::default()
+                    });
+                    Ok(DataOutputStats {
+                        name: filename,
+                        key: Some(home),
+                        lines_written: num_lines_sent,
+                        parts,
+                    })
+                }
+                None => Ok(DataOutputStats {
+                    name: filename,
+                    key: Some(full_path_str),
+                    lines_written: num_lines_sent,
+                    ..Default::default()
+                }),
+            }
         });
         Ok((tx, jh))
     }
@@ -0,0 +1,201 @@
+use super::*;
+use futures_core::future::BoxFuture;
+use futures_core::stream::Stream;
+use futures_util::sink::Sink;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc::OwnedPermit;
+
+pub type DataSourceItem<T> = Result<DataSourceMessage<T>, DataStoreError>;
+
+/// A started [DataSource] viewed as a `Stream`, so the `futures` combinators can be used on it.
+/// Once the messages run out the result of the DataSource task is checked, an error it returned
+/// is the last item of the stream.
+pub struct DataSourceStream<T: Send> {
+    rx: DataSourceRx<T>,
+    jh: Option<DataSourceJoinHandle>,
+}
+
+impl<T: Debug + Send + 'static> DataSourceStream<T> {
+    pub fn new(ds: Box<dyn DataSource<T>>) -> Result<Self, DataStoreError> {
+        Ok(DataSourceStream::from(ds.start_stream()?))
+    }
+}
+
+impl<T: Send> From<DataSourceTask<T>> for DataSourceStream<T> {
+    fn from((rx, jh): DataSourceTask<T>) -> Self {
+        DataSourceStream { rx, jh: Some(jh) }
+    }
+}
+
+impl<T: Send> Stream for DataSourceStream<T> {
+    type Item = DataSourceItem<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(item) = futures_core::ready!(self.rx.poll_recv(cx)) {
+            return Poll::Ready(Some(item));
+        }
+        let result = match self.jh.as_mut() {
+            Some(jh) => futures_core::ready!(Pin::new(jh).poll(cx)),
+            None => return Poll::Ready(None),
+        };
+        self.jh = None;
+        match result {
+            Ok(Ok(_)) => Poll::Ready(None),
+            Ok(Err(er)) => Poll::Ready(Some(Err(er))),
+            Err(er) => Poll::Ready(Some(Err(er.into()))),
+        }
+    }
+}
+
+/// Any `Stream` of messages used as a [DataSource], for example a [DataSourceStream] after
+/// applying some combinators, so it can be handed to the JobRunner
+pub struct StreamDataSource<S> {
+    name: String,
+    // only so the DataSource is Sync, most combinators are not
+    stream: std::sync::Mutex<S>,
+}
+
+impl<S> StreamDataSource<S> {
+    pub fn new<N: Into<String>>(name: N, stream: S) -> Self {
+        StreamDataSource {
+            name: name.into(),
+            stream: std::sync::Mutex::new(stream),
+        }
+    }
+}
+
+impl<T, S> DataSource<T> for StreamDataSource<S>
+where
+    T: Debug + Send + 'static,
+    S: Stream<Item = DataSourceItem<T>> + Send + 'static,
+{
+    fn name(&self) -> String {
+        format!("StreamDataSource-{}", &self.name)
+    }
+
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<T>, DataStoreError> {
+        use futures_util::stream::StreamExt;
+        use tokio::sync::mpsc::channel;
+        use tokio::task::JoinHandle;
+        let (tx, rx) = channel(channels::buffer_size());
+        let name = self.name;
+        let stream = self.stream.into_inner().unwrap_or_else(|er| er.into_inner());
+        let mut stream = Box::pin(stream);
+        let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
+            let mut lines_scanned = 0_usize;
+            while let Some(item) = stream.next().await {
+                lines_scanned += 1;
+                tx.send(item)
+                    .await
+                    .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+            }
+            Ok(DataSourceStats::new(lines_scanned))
+        });
+        Ok((rx, jh))
+    }
+}
+
+/// A started [DataOutput] viewed as a `Sink`.  Closing the sink finishes the output, after which
+/// its stats are available with [DataOutputSink::stats]
+pub struct DataOutputSink<T: Debug + Send + Sync + 'static> {
+    tx: Option<DataOutputTx<T>>,
+    reserve: Option<BoxFuture<'static, Option<OwnedPermit<DataOutputMessage<T>>>>>,
+    permit: Option<OwnedPermit<DataOutputMessage<T>>>,
+    jh: Option<DataOutputJoinHandle>,
+    stats: Option<DataOutputStats>,
+}
+
+impl<T: Debug + Send + Sync + 'static> DataOutputSink<T> {
+    pub async fn new(output: Box<dyn DataOutput<T>>) -> Result<Self, DataStoreError> {
+        Ok(DataOutputSink::from(output.start_stream().await?))
+    }
+
+    /// Stats of the output, available once the sink is closed
+    pub fn stats(&self) -> Option<&DataOutputStats> {
+        self.stats.as_ref()
+    }
+
+    fn closed() -> DataStoreError {
+        DataStoreError::send_error("DataOutputSink", "DataOutput", "the output stopped")
+    }
+}
+
+impl<T: Debug + Send + Sync + 'static> From<DataOutputTask<T>> for DataOutputSink<T> {
+    fn from((tx, jh): DataOutputTask<T>) -> Self {
+        DataOutputSink {
+            tx: Some(tx),
+            reserve: None,
+            permit: None,
+            jh: Some(jh),
+            stats: None,
+        }
+    }
+}
+
+impl<T: Debug + Send + Sync + 'static> Sink<T> for DataOutputSink<T> {
+    type Error = DataStoreError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.permit.is_some() {
+            return Poll::Ready(Ok(()));
+        }
+        if self.reserve.is_none() {
+            let tx = match &self.tx {
+                Some(tx) => tx.clone(),
+                None => return Poll::Ready(Err(Self::closed())),
+            };
+            self.reserve = Some(Box::pin(async move { tx.reserve_owned().await.ok() }));
+        }
+        let permit = match self.reserve.as_mut() {
+            Some(reserve) => futures_core::ready!(reserve.as_mut().poll(cx)),
+            None => None,
+        };
+        self.reserve = None;
+        match permit {
+            Some(permit) => {
+                self.permit = Some(permit);
+                Poll::Ready(Ok(()))
+            }
+            None => Poll::Ready(Err(Self::closed())),
+        }
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        match self.permit.take() {
+            Some(permit) => {
+                permit.send(DataOutputMessage::new(item));
+                Ok(())
+            }
+            None => Err(DataStoreError::Generic(String::from(
+                "DataOutputSink::start_send called before poll_ready",
+            ))),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // items are handed to the output as they are sent
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // the output finishes once every sender is dropped
+        self.permit = None;
+        self.reserve = None;
+        self.tx = None;
+        let result = match self.jh.as_mut() {
+            Some(jh) => futures_core::ready!(Pin::new(jh).poll(cx)),
+            None => return Poll::Ready(Ok(())),
+        };
+        self.jh = None;
+        match result {
+            Ok(Ok(stats)) => {
+                self.stats = Some(stats);
+                Poll::Ready(Ok(()))
+            }
+            Ok(Err(er)) => Poll::Ready(Err(er.into())),
+            Err(er) => Poll::Ready(Err(er.into())),
+        }
+    }
+}
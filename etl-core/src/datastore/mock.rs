@@ -33,7 +33,7 @@ impl<T: Serialize + Debug + Send + Sync + 'static> DataOutput<T> for MockJsonDat
         self: Box<Self>,
     ) -> anyhow::Result<DataOutputTask<T>> {
         use tokio::sync::mpsc::channel;
-        let (tx, mut rx): (DataOutputTx<T>, _) = channel(1);
+        let (tx, mut rx): (DataOutputTx<T>, _) = channel(channels::buffer_size());
         let sleep_duration = self.sleep_duration;
         let name = self.name.clone();
         let jh: JoinHandle<anyhow::Result<DataOutputStats>> = tokio::spawn(async move {
@@ -104,15 +104,16 @@ impl<T: Serialize + DeserializeOwned + Debug + Send + Sync + 'static> DataSource
         use tokio::sync::mpsc::channel;
         use tokio::task::JoinHandle;
         // could make this configurable
-        let (tx, rx) = channel(1);
+        let (tx, rx) = channel(channels::buffer_size());
         let name = String::from("MockJsonDataSource");
         let lines = self.lines.clone();
         let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
             let mut lines_scanned = 0_usize;
-            for line in lines {
+            for (idx, line) in lines.into_iter().enumerate() {
+                let provenance = Provenance::from(name.as_str()).with_line(idx + 1);
                 match serde_json::from_str::<T>(&line) {
                     Ok(r) => {
-                        tx.send(Ok(DataSourceMessage::new("MockJsonDataSource", r)))
+                        tx.send(Ok(DataSourceMessage::new(provenance, r)))
                             .await
                             .map_err(|e| DataStoreError::send_error(&name, "", e))?;
                         lines_scanned += 1;
@@ -122,7 +123,8 @@ impl<T: Serialize + DeserializeOwned + Debug + Send + Sync + 'static> DataSource
                             .send(Err(DataStoreError::Deserialize {
                                 message: val.to_string(),
                                 attempted_string: line.to_string(),
-                            }))
+                            }
+                            .at(provenance)))
                             .await
                         {
                             Ok(_) => {
@@ -135,7 +137,7 @@ impl<T: Serialize + DeserializeOwned + Debug + Send + Sync + 'static> DataSource
                     }
                 };
             }
-            Ok(DataSourceStats { lines_scanned })
+            Ok(DataSourceStats::new(lines_scanned))
         });
         Ok((rx, jh))
     }
@@ -39,7 +39,7 @@ impl<T: Serialize + DeserializeOwned + std::fmt::Debug + Send + Sync + 'static>
             comment,
         } = self.csv_options;
         // could make this configurable
-        let (tx, rx) = channel(1);
+        let (tx, rx) = channel(channels::buffer_size());
         let name = String::from("MockJsonDataSource");
         let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> =
             tokio::spawn(async move {
@@ -66,12 +66,12 @@ impl<T: Serialize + DeserializeOwned + std::fmt::Debug + Send + Sync + 'static>
                             .comment(comment)
                             .from_reader(data.as_bytes());
                         let mut iter = rdr.into_deserialize::<T>();
+                        // the first line holds the headers
+                        let provenance =
+                            Provenance::from(name.as_str()).with_line(lines_scanned + 2);
                         match iter.next() {
                             Some(Ok(item)) => {
-                                tx.send(Ok(DataSourceMessage::new(
-                                    "MockJsonDataSource",
-                                    item,
-                                )))
+                                tx.send(Ok(DataSourceMessage::new(provenance, item)))
                                 .await
                                 .map_err(|e| DataStoreError::send_error(&name, "", e))?;
                                 lines_scanned += 1;
@@ -80,7 +80,8 @@ impl<T: Serialize + DeserializeOwned + std::fmt::Debug + Send + Sync + 'static>
                                 tx.send(Err(DataStoreError::Deserialize {
                                     message: er.to_string(),
                                     attempted_string: line.to_string(),
-                                }))
+                                }
+                                .at(provenance)))
                                 .await
                                 .map_err(|e| DataStoreError::send_error(&name, "", e))?;
                                 lines_scanned += 1;
@@ -91,7 +92,7 @@ impl<T: Serialize + DeserializeOwned + std::fmt::Debug + Send + Sync + 'static>
                         }
                     }
                 }
-                Ok(DataSourceStats { lines_scanned })
+                Ok(DataSourceStats::new(lines_scanned))
             });
         Ok((rx, jh))
     }
@@ -10,10 +10,16 @@ use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::task::JoinHandle;
 
 //pub mod bytes_source;
+/// Pipeline-wide channel sizes and channels which pass items in chunks
+pub mod channels;
+/// gzip and zstd support for file based sources and outputs
+pub mod compression;
 /// creates generated data sources
 pub mod enumerate;
 /// Local file system data stores
 pub mod fs;
+/// `futures` Stream and Sink views of DataSources and DataOutputs
+pub mod interop;
 /// various data stores used for testing
 pub mod mock;
 pub mod sources;
@@ -31,6 +37,14 @@ pub type DataSourceTask<T> = (
     DataSourceRx<T>,
     JoinHandle<Result<DataSourceStats, DataStoreError>>,
 );
+/// The messages of a stream started with [DataSource::start_chunk_stream]
+pub type DataSourceChunkRx<T> =
+    channels::ChunkReceiver<Result<DataSourceMessage<T>, DataStoreError>>;
+pub type DataSourceChunkTx<T> = channels::ChunkSender<Result<DataSourceMessage<T>, DataStoreError>>;
+pub type DataSourceChunkTask<T> = (
+    DataSourceChunkRx<T>,
+    JoinHandle<Result<DataSourceStats, DataStoreError>>,
+);
 pub type DataOutputTx<T> = Sender<DataOutputMessage<T>>;
 pub type DataOutputRx<T> = Receiver<DataOutputMessage<T>>;
 pub type DataOutputTask<T> = (DataOutputTx<T>, JoinHandle<anyhow::Result<DataOutputStats>>);
@@ -38,8 +52,20 @@ pub type DataOutputTask<T> = (DataOutputTx<T>, JoinHandle<anyhow::Result<DataOut
 pub type DataOutputJoinHandle = JoinHandle<anyhow::Result<DataOutputStats>>;
 pub type DataSourceJoinHandle = JoinHandle<Result<DataSourceStats, DataStoreError>>;
 
+#[derive(Debug, Clone, Default)]
 pub struct DataSourceStats {
     pub lines_scanned: usize,
+    /// Items dropped because they were seen before, see [crate::dedup::Dedup]
+    pub duplicates: usize,
+}
+
+impl DataSourceStats {
+    pub fn new(lines_scanned: usize) -> Self {
+        DataSourceStats {
+            lines_scanned,
+            duplicates: 0,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -47,15 +73,36 @@ pub struct DataOutputStats {
     pub name: String,
     pub key: Option<String>,
     pub lines_written: usize,
+    /// When the output was written to several destinations (like rotated files), the stats of
+    /// each one of them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<DataOutputStats>,
+}
+
+impl DataOutputStats {
+    /// The stats of every destination which was written to; the parts (and their own parts) if
+    /// there are any, otherwise the stats themselves
+    pub fn into_parts(self) -> Vec<DataOutputStats> {
+        if self.parts.is_empty() {
+            vec![self]
+        } else {
+            self.parts
+                .into_iter()
+                .flat_map(DataOutputStats::into_parts)
+                .collect()
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum DataSourceMessage<T: Send> {
-    Data { source: String, content: T },
+    Data { source: Provenance, content: T },
 }
 
 impl<T: Send> DataSourceMessage<T> {
-    pub fn new<S: Into<String>>(s: S, data: T) -> Self {
+    /// `s` is either the [Provenance] of the record being passed along, or for new records the
+    /// name of the file (or key) it was read from
+    pub fn new<S: Into<Provenance>>(s: S, data: T) -> Self {
         DataSourceMessage::Data {
             source: s.into(),
             content: data,
@@ -63,6 +110,80 @@ impl<T: Send> DataSourceMessage<T> {
     }
 }
 
+/// Where a record came from.  DataSources fill it in and the built-in decoders and combinators
+/// pass it along with the records derived from it, so errors can point at the input row.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Provenance {
+    /// name of the DataSource which read the record
+    pub origin: String,
+    /// file, key or table the record was read from
+    pub key: String,
+    /// line (or record number) inside the key, starting at 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// byte offset inside the key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+}
+
+impl Provenance {
+    pub fn new<O: Into<String>, K: Into<String>>(origin: O, key: K) -> Self {
+        Provenance {
+            origin: origin.into(),
+            key: key.into(),
+            line: None,
+            offset: None,
+        }
+    }
+
+    pub fn with_line(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+}
+
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.key)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, "@{}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&str> for Provenance {
+    fn from(key: &str) -> Self {
+        Provenance::new(key, key)
+    }
+}
+
+impl From<String> for Provenance {
+    fn from(key: String) -> Self {
+        Provenance::new(key.clone(), key)
+    }
+}
+
+impl From<&String> for Provenance {
+    fn from(key: &String) -> Self {
+        Provenance::from(key.as_str())
+    }
+}
+
+impl From<&Provenance> for Provenance {
+    fn from(p: &Provenance) -> Self {
+        p.clone()
+    }
+}
+
 #[derive(Debug)]
 pub enum DataOutputMessage<T: Debug + Send + Sync> {
     Data(T),
@@ -99,6 +220,14 @@ pub trait DataSource<T: Debug + 'static + Send>: Sync + Send {
 
     fn start_stream(self: Box<Self>) -> Result<DataSourceTask<T>, DataStoreError>;
 
+    /// Like [DataSource::start_stream], with messages which may come in chunks, see
+    /// [channels::chunk_channel].  The built-in stages read their inputs with it, so the ones
+    /// which support it pass chunks to each other.  By default the messages come one at a time
+    fn start_chunk_stream(self: Box<Self>) -> Result<DataSourceChunkTask<T>, DataStoreError> {
+        let (rx, jh) = self.start_stream()?;
+        Ok((rx.into(), jh))
+    }
+
     /*
     /// TODO: this is not integrated yet because this doesn't get the JobManagerChannel because I'm
     /// not completely convinced this is necessary.  After all, JobRunner can close the rx end of
@@ -134,6 +263,42 @@ pub trait DataSource<T: Debug + 'static + Send>: Sync + Send {
     */
 }
 
+impl<T: 'static + Debug + Send> DataSource<T> for Box<dyn DataSource<T>> {
+    fn name(&self) -> String {
+        (**self).name()
+    }
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<T>, DataStoreError> {
+        (*self).start_stream()
+    }
+    fn start_chunk_stream(self: Box<Self>) -> Result<DataSourceChunkTask<T>, DataStoreError> {
+        (*self).start_chunk_stream()
+    }
+}
+
+/// The messages of a chunk stream one at a time, for the [DataSource::start_stream] of stages
+/// which pass chunks.  A task forwards the messages unless they already come one at a time
+pub fn unchunk_stream<T: Debug + Send + 'static>(
+    task: DataSourceChunkTask<T>,
+) -> DataSourceTask<T> {
+    use tokio::sync::mpsc::channel;
+    let (chunk_rx, chunk_jh) = task;
+    match chunk_rx.into_items() {
+        Ok(rx) => (rx, chunk_jh),
+        Err(mut chunk_rx) => {
+            let (tx, rx) = channel(channels::buffer_size());
+            let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> =
+                tokio::spawn(async move {
+                    chunk_rx
+                        .forward(&tx)
+                        .await
+                        .map_err(|e| DataStoreError::send_error("ChunkReceiver", "", e))?;
+                    chunk_jh.await?
+                });
+            (rx, jh)
+        }
+    }
+}
+
 impl<T: 'static + Debug + Send> DataSource<T> for DataSourceTask<T> {
     fn name(&self) -> String {
         String::from("DataSourceTask")
@@ -199,7 +364,7 @@ impl<T: Debug + 'static + Sync + Send> DataSource<T> for DataSourceRx<T> {
     fn start_stream(mut self: Box<Self>) -> Result<DataSourceTask<T>, DataStoreError> {
         use tokio::sync::mpsc::channel;
         use tokio::task::JoinHandle;
-        let (tx, rx) = channel(1);
+        let (tx, rx) = channel(channels::buffer_size());
         let name = self.name();
         let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
             let mut lines_scanned = 0_usize;
@@ -207,9 +372,11 @@ impl<T: Debug + 'static + Sync + Send> DataSource<T> for DataSourceRx<T> {
                 match self.recv().await {
                     Some(Ok(DataSourceMessage::Data {source, content})) => {
                         lines_scanned += 1;
-                        tx.send(Ok(DataSourceMessage::new(&name, content)))
+                        tx.send(Ok(DataSourceMessage::new(&source, content)))
                             .await
-                            .map_err(|e| DataStoreError::send_error(&name, &source, e))?;
+                            .map_err(|e| {
+                                DataStoreError::send_error(&name, source.to_string(), e)
+                            })?;
                     }
                     Some(Err(e)) => {
                         return Err(e);
@@ -217,7 +384,7 @@ impl<T: Debug + 'static + Sync + Send> DataSource<T> for DataSourceRx<T> {
                     None => break,
                 }
             }
-            Ok(DataSourceStats { lines_scanned })
+            Ok(DataSourceStats::new(lines_scanned))
         });
         Ok((rx, jh))
     }
@@ -232,7 +399,7 @@ impl<T: Debug + 'static + Sync + Send> DataSource<T> for Receiver<DataSourceMess
     fn start_stream(mut self: Box<Self>) -> Result<DataSourceTask<T>, DataStoreError> {
         use tokio::sync::mpsc::channel;
         use tokio::task::JoinHandle;
-        let (tx, rx) = channel(1);
+        let (tx, rx) = channel(channels::buffer_size());
         let name = self.name();
         let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
             let mut lines_scanned = 0_usize;
@@ -240,14 +407,16 @@ impl<T: Debug + 'static + Sync + Send> DataSource<T> for Receiver<DataSourceMess
                 match self.recv().await {
                     Some(DataSourceMessage::Data {source, content}) => {
                         lines_scanned += 1;
-                        tx.send(Ok(DataSourceMessage::new(&name, content)))
+                        tx.send(Ok(DataSourceMessage::new(&source, content)))
                             .await
-                            .map_err(|e| DataStoreError::send_error(&name, &source, e))?;
+                            .map_err(|e| {
+                                DataStoreError::send_error(&name, source.to_string(), e)
+                            })?;
                     }
                     None => break,
                 }
             }
-            Ok(DataSourceStats { lines_scanned })
+            Ok(DataSourceStats::new(lines_scanned))
         });
         Ok((rx, jh))
     }
@@ -8,21 +8,21 @@ impl DataSource<Bytes> for String {
     }
 
     fn start_stream(self: Box<Self>) -> Result<DataSourceTask<Bytes>, DataStoreError> {
-        let (tx, rx) = channel(1);
+        let (tx, rx) = channel(channels::buffer_size());
         let name = self.name();
         let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
             let mut lines_scanned = 0_usize;
             for line in self.lines() {
                 lines_scanned += 1;
                 tx.send(Ok(DataSourceMessage::new(
-                    &name,
+                    Provenance::from(&name).with_line(lines_scanned),
                     Bytes::from(line.to_owned()),
                 )))
                 .await
                 .map_err(|er| DataStoreError::send_error(&name, "", er))?;
             }
             log::info!("{} finished", &name);
-            Ok(DataSourceStats { lines_scanned })
+            Ok(DataSourceStats::new(lines_scanned))
         });
         Ok((rx, jh))
     }
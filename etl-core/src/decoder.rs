@@ -5,9 +5,18 @@ use crate::datastore::*;
 use bytes::Bytes;
 use serde::de::DeserializeOwned;
 use std::fmt::Debug;
+use std::io::Read;
+use std::marker::PhantomData;
+use tokio::sync::mpsc::error::{SendError, TryRecvError};
 
+/// Avro object container files read whole, see [crate::decoder::avro::AvroDecoder]
+pub mod avro;
 pub mod csv;
 pub mod json;
+/// JSON arrays, possibly nested in a document, decoded element by element
+pub mod json_array;
+/// Parquet files read whole, see [crate::decoder::parquet::ParquetDecoder]
+pub mod parquet;
 pub mod string;
 
 pub trait DecodeStream<T: Debug + 'static + Send>: Sync + Send {
@@ -17,7 +26,7 @@ pub trait DecodeStream<T: Debug + 'static + Send>: Sync + Send {
 /// Helper wrapper for specific decoders to return so you do not have to construct them manually
 pub struct DecodedSource<T: Debug + 'static + Send + Send> {
     source_name: String,
-    ds_task_result: Result<DataSourceTask<T>, DataStoreError>,
+    ds_task_result: Result<DataSourceChunkTask<T>, DataStoreError>,
 }
 
 impl<T: Debug + Send + Sync + 'static> DataSource<T> for DecodedSource<T> {
@@ -25,6 +34,179 @@ impl<T: Debug + Send + Sync + 'static> DataSource<T> for DecodedSource<T> {
         format!("DecodedSource-{}", &self.source_name)
     }
     fn start_stream(self: Box<Self>) -> Result<DataSourceTask<T>, DataStoreError> {
+        self.ds_task_result.map(unchunk_stream)
+    }
+    fn start_chunk_stream(self: Box<Self>) -> Result<DataSourceChunkTask<T>, DataStoreError> {
         self.ds_task_result
     }
 }
+
+/// Decodes the source once the stream is started rather than when the decoder is created, so
+/// the channels of the decoder are made with the config of the pipeline starting it, see
+/// [channels::ChannelConfig::scope]
+pub struct LazyDecodedSource<D, T> {
+    decoder: D,
+    source: Box<dyn DataSource<Bytes>>,
+    item: PhantomData<fn() -> T>,
+}
+
+impl<D, T> LazyDecodedSource<D, T>
+where
+    D: DecodeStream<T> + 'static,
+    T: Debug + Send + Sync + 'static,
+{
+    pub fn boxed(decoder: D, source: Box<dyn DataSource<Bytes>>) -> Box<dyn DataSource<T>> {
+        Box::new(LazyDecodedSource {
+            decoder,
+            source,
+            item: PhantomData,
+        })
+    }
+}
+
+impl<D, T> DataSource<T> for LazyDecodedSource<D, T>
+where
+    D: DecodeStream<T> + 'static,
+    T: Debug + Send + Sync + 'static,
+{
+    fn name(&self) -> String {
+        format!("DecodedSource-{}", self.source.name())
+    }
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<T>, DataStoreError> {
+        self.decoder.decode_source(self.source).start_stream()
+    }
+    fn start_chunk_stream(self: Box<Self>) -> Result<DataSourceChunkTask<T>, DataStoreError> {
+        self.decoder.decode_source(self.source).start_chunk_stream()
+    }
+}
+
+type FlushFn = Box<dyn FnMut() -> Result<(), SendError<()>>>;
+
+/// Turns the lines received from a `DataSource<Bytes>` back into a continuous byte stream so a
+/// single reader (like the csv one) can be kept for the whole source.  Reaching a line from a
+/// different source (for example the next file of a LocalFs) is reported as the end of the
+/// stream, the line is held back so the next reader starts with it.
+pub(crate) struct SourceLines {
+    rx: DataSourceRx<Bytes>,
+    /// called before waiting for the next line, see [SourceLines::with_flush]
+    flush: Option<FlushFn>,
+    /// provenance of the first line of the current source
+    pub source: Provenance,
+    line: Bytes,
+    /// the newline stripped by the upstream source still has to be written
+    needs_newline: bool,
+    next_source: Option<(Provenance, Bytes)>,
+    pub error: Option<DataStoreError>,
+    finished: bool,
+}
+
+impl SourceLines {
+    pub fn new(rx: DataSourceRx<Bytes>) -> Self {
+        SourceLines {
+            rx,
+            flush: None,
+            source: Provenance::default(),
+            line: Bytes::new(),
+            needs_newline: false,
+            next_source: None,
+            error: None,
+            finished: false,
+        }
+    }
+
+    /// Calls `flush` whenever no line is ready and the reader has to wait for the source, so
+    /// the decoder can send the records it holds back, like a partial chunk
+    pub fn with_flush<F>(self, flush: F) -> Self
+    where
+        F: FnMut() -> Result<(), SendError<()>> + 'static,
+    {
+        SourceLines {
+            flush: Some(Box::new(flush)),
+            ..self
+        }
+    }
+
+    fn recv(
+        &mut self,
+    ) -> std::io::Result<Option<Result<DataSourceMessage<Bytes>, DataStoreError>>> {
+        match self.rx.try_recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(TryRecvError::Empty) => {
+                if let Some(flush) = self.flush.as_mut() {
+                    flush().map_err(|e| {
+                        std::io::Error::new(std::io::ErrorKind::BrokenPipe, e.to_string())
+                    })?;
+                }
+                Ok(self.rx.blocking_recv())
+            }
+            Err(TryRecvError::Disconnected) => Ok(None),
+        }
+    }
+
+    /// Starts reading the next source, returns false when there is nothing left to read
+    pub fn start_next_source(&mut self) -> bool {
+        if self.next_source.is_none() && !self.finished && self.error.is_none() {
+            match self.recv() {
+                Ok(Some(Ok(DataSourceMessage::Data { source, content }))) => {
+                    self.next_source = Some((source, content));
+                }
+                Ok(Some(Err(er))) => self.error = Some(er),
+                Ok(None) => self.finished = true,
+                Err(er) => self.error = Some(DataStoreError::FatalIO(er.to_string())),
+            }
+        }
+        match self.next_source.take() {
+            Some((source, content)) => {
+                self.source = source;
+                self.line = content;
+                self.needs_newline = true;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Provenance of a record found at `line` (starting at 1) and byte `offset` of a source whose
+/// first line has the provenance `start`
+pub(crate) fn provenance_at(start: &Provenance, line: usize, offset: u64) -> Provenance {
+    Provenance {
+        origin: start.origin.clone(),
+        key: start.key.clone(),
+        line: Some(start.line.unwrap_or(1) + line - 1),
+        offset: Some(start.offset.unwrap_or(0) + offset),
+    }
+}
+
+impl Read for SourceLines {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.line.is_empty() && !self.needs_newline {
+            if self.next_source.is_some() || self.finished || self.error.is_some() {
+                return Ok(0);
+            }
+            match self.recv()? {
+                Some(Ok(DataSourceMessage::Data { source, content })) => {
+                    if source.key == self.source.key && source.origin == self.source.origin {
+                        self.line = content;
+                        self.needs_newline = true;
+                    } else {
+                        self.next_source = Some((source, content));
+                    }
+                }
+                Some(Err(er)) => self.error = Some(er),
+                None => self.finished = true,
+            }
+        }
+        if self.line.is_empty() {
+            buf[0] = b'\n';
+            self.needs_newline = false;
+            return Ok(1);
+        }
+        let len = std::cmp::min(buf.len(), self.line.len());
+        buf[..len].copy_from_slice(&self.line.split_to(len));
+        Ok(len)
+    }
+}
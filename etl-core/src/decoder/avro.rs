@@ -0,0 +1,401 @@
+use super::*;
+use avro_schema::file::FileMetadata;
+use avro_schema::read::fallible_streaming_iterator::FallibleStreamingIterator;
+use avro_schema::schema::{Enum, Field, Record, Schema};
+use serde_json::{Map, Number, Value};
+use std::convert::TryFrom;
+use std::io::Read;
+use crate::datastore::channels::ChunkSender;
+
+/// Decodes Avro object container files into rows.  Every message of the source must hold a whole
+/// file, so use it with a source that has `whole_files` turned on like
+/// [crate::datastore::fs::LocalFs::whole_files].  The writer schema is read from the file, each
+/// record is converted to JSON and then deserialized into `T` so `serde_json::Value` works as well.
+#[derive(Default)]
+pub struct AvroDecoder {}
+
+impl AvroDecoder {
+    pub fn new<T>(source: Box<dyn DataSource<Bytes>>) -> Box<dyn DataSource<T>>
+    where
+        T: DeserializeOwned + Debug + Send + Sync + 'static,
+    {
+        LazyDecodedSource::boxed(AvroDecoder {}, source)
+    }
+}
+
+/// Reads a single datum written with the given schema
+pub fn read_datum(reader: &mut &[u8], schema: &Schema) -> Result<Value, String> {
+    Ok(match schema {
+        Schema::Null => Value::Null,
+        Schema::Boolean => Value::Bool(read_bytes(reader, 1)?[0] != 0),
+        Schema::Int(_) => {
+            let n = read_long(reader)?;
+            match i32::try_from(n) {
+                Ok(n) => Value::from(n),
+                Err(_) => return Err(format!("{} is out of range for an int", n)),
+            }
+        }
+        Schema::Long(_) => Value::from(read_long(reader)?),
+        Schema::Float => {
+            let b = read_bytes(reader, 4)?;
+            float_value(f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+        }
+        Schema::Double => {
+            let mut b = [0_u8; 8];
+            b.copy_from_slice(read_bytes(reader, 8)?);
+            float_value(f64::from_le_bytes(b))
+        }
+        Schema::Bytes(_) => {
+            let len = read_len(reader)?;
+            Value::from(read_bytes(reader, len)?.to_vec())
+        }
+        Schema::Fixed(fixed) => Value::from(read_bytes(reader, fixed.size)?.to_vec()),
+        Schema::String(_) => {
+            let len = read_len(reader)?;
+            Value::from(String::from_utf8_lossy(read_bytes(reader, len)?).to_string())
+        }
+        Schema::Record(Record { fields, .. }) => {
+            let mut map = Map::new();
+            for field in fields {
+                map.insert(field.name.clone(), read_datum(reader, &field.schema)?);
+            }
+            Value::Object(map)
+        }
+        Schema::Enum(Enum { symbols, .. }) => {
+            let index = read_long(reader)?;
+            match symbols.get(index as usize) {
+                Some(symbol) => Value::from(symbol.clone()),
+                None => return Err(format!("enum index {} is out of range", index)),
+            }
+        }
+        Schema::Array(items) => {
+            let mut values = Vec::new();
+            read_blocks(reader, |reader| {
+                values.push(read_datum(reader, items)?);
+                Ok(())
+            })?;
+            Value::Array(values)
+        }
+        Schema::Map(values) => {
+            let mut map = Map::new();
+            read_blocks(reader, |reader| {
+                let len = read_len(reader)?;
+                let key = String::from_utf8_lossy(read_bytes(reader, len)?).to_string();
+                map.insert(key, read_datum(reader, values)?);
+                Ok(())
+            })?;
+            Value::Object(map)
+        }
+        Schema::Union(schemas) => {
+            let index = read_long(reader)?;
+            match schemas.get(index as usize) {
+                Some(schema) => read_datum(reader, schema)?,
+                None => return Err(format!("union index {} is out of range", index)),
+            }
+        }
+    })
+}
+
+/// Writes a single datum using the given schema
+pub fn write_datum(buf: &mut Vec<u8>, value: &Value, schema: &Schema) -> Result<(), String> {
+    let mismatch = || format!("{} does not match the schema {:?}", value, schema);
+    match (schema, value) {
+        (Schema::Null, Value::Null) => {}
+        (Schema::Boolean, Value::Bool(b)) => buf.push(*b as u8),
+        (Schema::Int(_), Value::Number(n)) => {
+            let n = n.as_i64().ok_or_else(mismatch)?;
+            i32::try_from(n).map_err(|_| mismatch())?;
+            write_long(buf, n)
+        }
+        (Schema::Long(_), Value::Number(n)) => write_long(buf, n.as_i64().ok_or_else(mismatch)?),
+        (Schema::Float, Value::Number(n)) => {
+            buf.extend_from_slice(&(n.as_f64().ok_or_else(mismatch)? as f32).to_le_bytes())
+        }
+        (Schema::Double, Value::Number(n)) => {
+            buf.extend_from_slice(&n.as_f64().ok_or_else(mismatch)?.to_le_bytes())
+        }
+        (Schema::Bytes(_), Value::String(s)) | (Schema::String(_), Value::String(s)) => {
+            write_long(buf, s.len() as i64);
+            buf.extend_from_slice(s.as_bytes());
+        }
+        (Schema::Bytes(_), Value::Array(_)) | (Schema::Fixed(_), Value::Array(_)) => {
+            let bytes: Vec<u8> = serde_json::from_value(value.clone()).map_err(|_| mismatch())?;
+            if let Schema::Bytes(_) = schema {
+                write_long(buf, bytes.len() as i64);
+            }
+            buf.extend_from_slice(&bytes);
+        }
+        (Schema::Record(Record { fields, .. }), Value::Object(map)) => {
+            for field in fields {
+                write_datum(buf, map.get(&field.name).unwrap_or(&Value::Null), &field.schema)?;
+            }
+        }
+        (Schema::Enum(Enum { symbols, .. }), Value::String(s)) => {
+            let index = symbols.iter().position(|sym| sym == s).ok_or_else(mismatch)?;
+            write_long(buf, index as i64);
+        }
+        (Schema::Array(items), Value::Array(values)) => {
+            if !values.is_empty() {
+                write_long(buf, values.len() as i64);
+                for v in values {
+                    write_datum(buf, v, items)?;
+                }
+            }
+            buf.push(0);
+        }
+        (Schema::Map(schema), Value::Object(map)) => {
+            if !map.is_empty() {
+                write_long(buf, map.len() as i64);
+                for (k, v) in map {
+                    write_long(buf, k.len() as i64);
+                    buf.extend_from_slice(k.as_bytes());
+                    write_datum(buf, v, schema)?;
+                }
+            }
+            buf.push(0);
+        }
+        (Schema::Union(schemas), _) => {
+            let index = schemas
+                .iter()
+                .position(|s| matches_schema(value, s))
+                .ok_or_else(mismatch)?;
+            write_long(buf, index as i64);
+            write_datum(buf, value, &schemas[index])?;
+        }
+        _ => return Err(mismatch()),
+    };
+    Ok(())
+}
+
+/// Derives a schema from a record, every field is nullable since a single record can not tell
+/// if a field is optional.  Nested records are named after the path of their field, like
+/// `name_address_city`, so their names are unique
+pub fn derive_schema(name: &str, value: &Value) -> Schema {
+    match value {
+        Value::Null => Schema::Null,
+        Value::Bool(_) => Schema::Boolean,
+        Value::Number(n) if n.is_f64() => Schema::Double,
+        Value::Number(_) => Schema::Long(None),
+        Value::String(_) => Schema::String(None),
+        Value::Array(values) => Schema::Array(Box::new(match values.first() {
+            Some(first) => derive_schema(name, first),
+            None => Schema::String(None),
+        })),
+        Value::Object(map) => Schema::Record(Record::new(
+            name,
+            map.iter()
+                .map(|(k, v)| {
+                    let schema = match derive_schema(&format!("{}_{}", name, k), v) {
+                        // nothing is known about the type, so assume a string
+                        Schema::Null => Schema::String(None),
+                        schema => schema,
+                    };
+                    Field::new(k, Schema::Union(vec![Schema::Null, schema]))
+                })
+                .collect(),
+        )),
+    }
+}
+
+fn matches_schema(value: &Value, schema: &Schema) -> bool {
+    match (schema, value) {
+        (Schema::Null, Value::Null) => true,
+        (Schema::Boolean, Value::Bool(_)) => true,
+        (Schema::Int(_), Value::Number(n)) => {
+            n.as_i64().is_some_and(|n| i32::try_from(n).is_ok())
+        }
+        (Schema::Long(_), Value::Number(n)) => n.is_i64(),
+        (Schema::Float, Value::Number(_)) | (Schema::Double, Value::Number(_)) => true,
+        (Schema::String(_), Value::String(_)) | (Schema::Enum(_), Value::String(_)) => true,
+        (Schema::Bytes(_), Value::String(_)) => true,
+        (Schema::Bytes(_), Value::Array(_)) | (Schema::Fixed(_), Value::Array(_)) => true,
+        (Schema::Array(_), Value::Array(_)) => true,
+        (Schema::Record(_), Value::Object(_)) | (Schema::Map(_), Value::Object(_)) => true,
+        _ => false,
+    }
+}
+
+fn float_value(f: f64) -> Value {
+    Number::from_f64(f).map(Value::Number).unwrap_or(Value::Null)
+}
+
+fn read_bytes<'a>(reader: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+    if reader.len() < len {
+        return Err(String::from("unexpected end of the avro block"));
+    }
+    let (bytes, rest) = reader.split_at(len);
+    *reader = rest;
+    Ok(bytes)
+}
+
+fn read_long(reader: &mut &[u8]) -> Result<i64, String> {
+    let mut n = 0_u64;
+    let mut shift = 0;
+    loop {
+        let b = read_bytes(reader, 1)?[0];
+        n |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift > 63 {
+            return Err(String::from("invalid variable length integer"));
+        }
+    }
+    Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+}
+
+fn read_len(reader: &mut &[u8]) -> Result<usize, String> {
+    let len = read_long(reader)?;
+    if len < 0 {
+        return Err(format!("invalid length {}", len));
+    }
+    Ok(len as usize)
+}
+
+/// Arrays and maps are written in blocks, a negative count is followed by the block size
+fn read_blocks<F>(reader: &mut &[u8], mut read_item: F) -> Result<(), String>
+where
+    F: FnMut(&mut &[u8]) -> Result<(), String>,
+{
+    loop {
+        let count = match read_long(reader)? {
+            0 => return Ok(()),
+            count if count < 0 => {
+                read_long(reader)?;
+                count
+                    .checked_neg()
+                    .ok_or_else(|| format!("invalid block count {}", count))?
+            }
+            count => count,
+        };
+        for _ in 0..count {
+            read_item(reader)?;
+        }
+    }
+}
+
+fn write_long(buf: &mut Vec<u8>, n: i64) {
+    let mut z = ((n << 1) ^ (n >> 63)) as u64;
+    while z > 0x7f {
+        buf.push((z & 0x7f) as u8 | 0x80);
+        z >>= 7;
+    }
+    buf.push(z as u8);
+}
+
+/// Sends every record of an avro file, returns the number of records read.  The records are
+/// numbered in the line of their provenance
+fn decode_file<T: DeserializeOwned + Debug + Send + Sync + 'static>(
+    tx: &mut ChunkSender<Result<DataSourceMessage<T>, DataStoreError>>,
+    source: &Provenance,
+    content: Bytes,
+) -> Result<usize, DataStoreError> {
+    let file_error = |message: String| {
+        Err(DataStoreError::Deserialize {
+            message,
+            attempted_string: format!("avro file {}", source.key),
+        }
+        .at(source))
+    };
+    let send = |tx: &mut ChunkSender<_>, message| {
+        tx.blocking_send(message)
+            .map_err(|e| DataStoreError::send_error(source.to_string(), "AvroDecoder", e))
+    };
+    let mut reader: &[u8] = &content;
+    let FileMetadata {
+        record,
+        compression,
+        marker,
+    } = match avro_schema::read::read_metadata(&mut reader) {
+        Ok(metadata) => metadata,
+        Err(er) => {
+            send(tx, file_error(format!("Invalid avro header: {}", er)))?;
+            return Ok(0);
+        }
+    };
+    let schema = Schema::Record(record);
+    let mut blocks = avro_schema::read::block_iterator(reader.by_ref(), compression, marker);
+    let mut lines_scanned = 0_usize;
+    loop {
+        let block = match blocks.next() {
+            Ok(Some(block)) => block,
+            Ok(None) => break,
+            Err(er) => {
+                send(tx, file_error(format!("Invalid avro block: {}", er)))?;
+                break;
+            }
+        };
+        let mut data: &[u8] = &block.data;
+        for _ in 0..block.number_of_rows {
+            lines_scanned += 1;
+            let provenance = source.clone().with_line(lines_scanned);
+            let message = match read_datum(&mut data, &schema) {
+                Ok(value) => match serde_json::from_value::<T>(value.clone()) {
+                    Ok(row) => Ok(DataSourceMessage::new(provenance, row)),
+                    Err(er) => Err(DataStoreError::Deserialize {
+                        message: er.to_string(),
+                        attempted_string: value.to_string(),
+                    }
+                    .at(provenance)),
+                },
+                Err(message) => {
+                    // the rest of the block can not be located after a bad record
+                    send(tx, file_error(message))?;
+                    break;
+                }
+            };
+            send(tx, message)?;
+        }
+    }
+    Ok(lines_scanned)
+}
+
+impl<T: DeserializeOwned + Debug + 'static + Send + Sync> DecodeStream<T> for AvroDecoder {
+    fn decode_source(self, source: Box<dyn DataSource<Bytes>>) -> Box<dyn DataSource<T>> {
+        use tokio::task::JoinHandle;
+        let source_name = source.name();
+
+        match source.start_stream() {
+            Ok((mut source_rx, source_stream_jh)) => {
+                let (mut chunk_tx, chunk_rx) = channels::chunk_channel();
+                let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> =
+                    tokio::spawn(async move {
+                        let mut lines_scanned = 0_usize;
+                        loop {
+                            match source_rx.recv().await {
+                                Some(Ok(DataSourceMessage::Data { source, content })) => {
+                                    // the records are handed back in chunks
+                                    let decode_jh = tokio::task::spawn_blocking(move || {
+                                        let records = decode_file(&mut chunk_tx, &source, content)?;
+                                        chunk_tx.blocking_flush().map_err(|e| {
+                                            DataStoreError::send_error("AvroDecoder", "", e)
+                                        })?;
+                                        Ok::<_, DataStoreError>((records, chunk_tx))
+                                    });
+                                    let (records, tx) = decode_jh.await??;
+                                    lines_scanned += records;
+                                    chunk_tx = tx;
+                                }
+                                Some(Err(e)) => {
+                                    log::error!("An error happened in AvroDecoder: {}", e);
+                                    return Err(e);
+                                }
+                                None => break,
+                            }
+                        }
+                        source_stream_jh.await??;
+                        Ok(DataSourceStats::new(lines_scanned))
+                    });
+                Box::new(DecodedSource {
+                    source_name,
+                    ds_task_result: Ok((chunk_rx, jh)),
+                })
+            }
+            Err(er) => Box::new(DecodedSource {
+                source_name,
+                ds_task_result: Err(er),
+            }),
+        }
+    }
+}
@@ -1,5 +1,7 @@
-use ::csv::ReaderBuilder;
+use ::csv::{ByteRecord, ReaderBuilder};
 use super::*;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 pub struct CsvDecoder {
     pub csv_options: CsvReadOptions,
@@ -9,135 +11,193 @@ impl CsvDecoder {
     pub fn new<T>(
         csv_options: CsvReadOptions,
         source: Box<dyn DataSource<Bytes>>,
-    ) -> Box<dyn DataSource<T>> 
+    ) -> Box<dyn DataSource<T>>
         where T: DeserializeOwned + Debug + Send + Sync + 'static
     {
-        DecodeStream::decode_source(CsvDecoder { csv_options }, source)
+        LazyDecodedSource::boxed(CsvDecoder { csv_options }, source)
     }
 }
 
+fn create_reader_builder(csv_options: &CsvReadOptions) -> ReaderBuilder {
+    let mut builder = ReaderBuilder::new();
+    builder
+        .delimiter(csv_options.delimiter)
+        .has_headers(csv_options.has_headers)
+        .flexible(csv_options.flexible)
+        .terminator(csv_options.terminator)
+        .quote(csv_options.quote)
+        .escape(csv_options.escape)
+        .double_quote(csv_options.double_quote)
+        .quoting(csv_options.quoting)
+        .comment(csv_options.comment);
+    builder
+}
+
 impl<T: DeserializeOwned + Debug + 'static + Send + Sync> DecodeStream<T> for CsvDecoder {
     fn decode_source(
         self,
         source: Box<dyn DataSource<Bytes>>,
         //) -> DecodedSource<T> {
     ) -> Box<dyn DataSource<T>> {
-        use tokio::sync::mpsc::channel;
         use tokio::task::JoinHandle;
-        let (tx, rx) = channel(1);
 
         let source_name = source.name();
 
-        //let (mut source_rx, source_stream_jh) = source.start_stream().await?;
-
         match source.start_stream() {
-            Ok((mut source_rx, source_stream_jh)) => {
-                let CsvReadOptions {
-                    delimiter,
-                    has_headers,
-                    flexible,
-                    terminator,
-                    quote,
-                    escape,
-                    double_quote,
-                    quoting,
-                    comment,
-                } = self.csv_options;
+            Ok((source_rx, source_stream_jh)) => {
+                let (chunk_tx, chunk_rx) = channels::chunk_channel();
+                let csv_options = self.csv_options;
                 let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> =
                     tokio::spawn(async move {
-                        let mut headers_str = String::from("");
-                        let mut lines_scanned = 0_usize;
-                        loop {
-                            match source_rx.recv().await {
-                                Some(Ok(DataSourceMessage::Data { source, content })) => {
-                                    if has_headers == true && lines_scanned == 0 {
-                                        headers_str =
-                                            std::string::String::from_utf8_lossy(&*content)
-                                                .to_string();
-                                    } else {
-                                        let line = std::string::String::from_utf8_lossy(&*content);
-                                        let data = match has_headers {
-                                            true => format!("{}\n{}", headers_str, line),
-                                            false => line.to_string(),
+                        // the csv reader is blocking, so it gets its own thread and pulls lines
+                        // from the source as it needs them.  The records are handed back in
+                        // chunks
+                        let decode_jh: JoinHandle<Result<DataSourceStats, DataStoreError>> =
+                            tokio::task::spawn_blocking(move || {
+                                let mut lines_scanned = 0_usize;
+                                let chunk_tx = Rc::new(RefCell::new(chunk_tx));
+                                let flush_tx = chunk_tx.clone();
+                                // the records held back are sent before waiting for more lines
+                                let mut source_lines = SourceLines::new(source_rx)
+                                    .with_flush(move || flush_tx.borrow_mut().blocking_flush());
+                                let mut record = ByteRecord::new();
+                                while source_lines.start_next_source() {
+                                    let source = source_lines.source.clone();
+                                    let mut rdr = create_reader_builder(&csv_options)
+                                        .from_reader(source_lines);
+                                    // headers are only parsed once per source
+                                    let headers = match csv_options.has_headers {
+                                        true => match rdr.byte_headers() {
+                                            Ok(headers) => Some(headers.clone()),
+                                            Err(er) if er.is_io_error() => {
+                                                return Err(DataStoreError::FatalIO(
+                                                    er.to_string(),
+                                                ));
+                                            }
+                                            Err(er) => {
+                                                chunk_tx
+                                                    .borrow_mut()
+                                                    .blocking_send(Err(
+                                                        DataStoreError::Deserialize {
+                                                            message: er.to_string(),
+                                                            attempted_string: format!(
+                                                                "headers of {}",
+                                                                &source.key
+                                                            ),
+                                                        }
+                                                        .at(&source),
+                                                    ))
+                                                    .map_err(|e| {
+                                                        DataStoreError::send_error(
+                                                            source.to_string(),
+                                                            "CsvDecoder",
+                                                            e,
+                                                        )
+                                                    })?;
+                                                None
+                                            }
+                                        },
+                                        false => None,
+                                    };
+                                    loop {
+                                        let result = match rdr.read_byte_record(&mut record) {
+                                            Ok(true) => record
+                                                .deserialize::<T>(headers.as_ref())
+                                                .map_err(|er| (er, &record)),
+                                            Ok(false) => break,
+                                            Err(er) if er.is_io_error() => {
+                                                return Err(DataStoreError::FatalIO(
+                                                    er.to_string(),
+                                                ));
+                                            }
+                                            Err(er) => Err((er, &record)),
                                         };
-                                        let rdr = ReaderBuilder::new()
-                                            .delimiter(delimiter)
-                                            .has_headers(has_headers)
-                                            .flexible(flexible)
-                                            .terminator(terminator)
-                                            .quote(quote)
-                                            .escape(escape)
-                                            .double_quote(double_quote)
-                                            .quoting(quoting)
-                                            .comment(comment)
-                                            .from_reader(data.as_bytes());
-                                        let mut iter = rdr.into_deserialize::<T>();
-                                        match iter.next() {
-                                            Some(result) => match result {
-                                                Ok(item) => {
-                                                    tx.send(Ok(DataSourceMessage::new(
-                                                        &source, item,
+                                        lines_scanned += 1;
+                                        let position = match &result {
+                                            Err((er, _)) if er.position().is_some() => {
+                                                er.position()
+                                            }
+                                            _ => record.position(),
+                                        };
+                                        let provenance = match position {
+                                            Some(pos) => {
+                                                provenance_at(&source, pos.line() as usize, pos.byte())
+                                            }
+                                            None => source.clone(),
+                                        };
+                                        match result {
+                                            Ok(item) => {
+                                                chunk_tx
+                                                    .borrow_mut()
+                                                    .blocking_send(Ok(DataSourceMessage::new(
+                                                        &provenance,
+                                                        item,
                                                     )))
-                                                    .await
                                                     .map_err(|e| {
                                                         DataStoreError::send_error(
-                                                            &source,
+                                                            provenance.to_string(),
                                                             "CsvDecoder",
                                                             e,
                                                         )
                                                     })?;
-                                                }
-                                                Err(er) => {
-                                                    match tx
-                                                        .send(Err(DataStoreError::Deserialize {
+                                            }
+                                            Err((er, record)) => {
+                                                // the error carries the record and line number
+                                                // relative to the start of the source
+                                                let attempted_string = record
+                                                    .iter()
+                                                    .map(String::from_utf8_lossy)
+                                                    .collect::<Vec<_>>()
+                                                    .join(
+                                                        &(csv_options.delimiter as char)
+                                                            .to_string(),
+                                                    );
+                                                chunk_tx
+                                                    .borrow_mut()
+                                                    .blocking_send(Err(
+                                                        DataStoreError::Deserialize {
                                                             message: er.to_string(),
-                                                            attempted_string: line.to_string(),
-                                                        }))
-                                                        .await
-                                                    {
-                                                        Ok(_) => {
-                                                            //sent_count += 1;
-                                                        }
-                                                        Err(e) => {
-                                                            return Err(
-                                                                DataStoreError::send_error(
-                                                                    &source, "", e,
-                                                                ),
-                                                            );
+                                                            attempted_string,
                                                         }
-                                                    }
-                                                }
-                                            },
-                                            None => {
-                                                break;
+                                                        .at(&provenance),
+                                                    ))
+                                                    .map_err(|e| {
+                                                        DataStoreError::send_error(
+                                                            provenance.to_string(),
+                                                            "CsvDecoder",
+                                                            e,
+                                                        )
+                                                    })?;
                                             }
                                         }
                                     }
-                                    lines_scanned += 1;
+                                    source_lines = rdr.into_inner();
                                 }
-                                Some(Err(e)) => {
-                                    println!("An error happened in CsvDecoder: {}", e);
+                                chunk_tx
+                                    .borrow_mut()
+                                    .blocking_flush()
+                                    .map_err(|e| DataStoreError::send_error("CsvDecoder", "", e))?;
+                                if let Some(e) = source_lines.error {
+                                    log::error!("An error happened in CsvDecoder: {}", e);
                                     // TODO: this error does not seem to stop the pipeline
                                     return Err(e);
                                 }
-                                None => {
-                                    break;
-                                }
-                            };
-                        }
+                                Ok(DataSourceStats::new(lines_scanned))
+                            });
+                        let stats = decode_jh.await??;
                         source_stream_jh.await??;
-                        Ok(DataSourceStats { lines_scanned })
+                        Ok(stats)
                     });
-                return Box::new(DecodedSource {
+                Box::new(DecodedSource {
                     source_name,
-                    ds_task_result: Ok((rx, jh)),
-                });
+                    ds_task_result: Ok((chunk_rx, jh)),
+                })
             }
             Err(er) => {
-                return Box::new(DecodedSource {
+                Box::new(DecodedSource {
                     source_name,
                     ds_task_result: Err(er),
-                });
+                })
             }
         }
     }
@@ -9,7 +9,7 @@ impl JsonDecoder {
     where
         T: DeserializeOwned + Debug + Send + Sync + 'static,
     {
-        DecodeStream::decode_source(JsonDecoder {}, source)
+        LazyDecodedSource::boxed(JsonDecoder {}, source)
     }
     pub fn with_datasource<T, O>(self, source: T) -> Box<dyn DataSource<O>>
     where
@@ -17,7 +17,7 @@ impl JsonDecoder {
         T: DataSource<Bytes> + 'static,
         O: DeserializeOwned + Debug + Send + Sync + 'static,
     {
-        DecodeStream::decode_source(self, Box::new(source) as Box<dyn DataSource<Bytes>>)
+        LazyDecodedSource::boxed(self, Box::new(source) as Box<dyn DataSource<Bytes>>)
     }
 }
 
@@ -25,7 +25,7 @@ impl<T: DeserializeOwned + Debug + 'static + Send + Sync> DecodeStream<T> for Js
     fn decode_source(self, source: Box<dyn DataSource<Bytes>>) -> Box<dyn DataSource<T>> {
         use tokio::sync::mpsc::channel;
         use tokio::task::JoinHandle;
-        let (tx, rx) = channel(1);
+        let (tx, rx) = channel(channels::buffer_size());
 
         //let (mut source_rx, source_stream_jh) = source.start_stream().await?;
         let source_name = source.name();
@@ -43,20 +43,25 @@ impl<T: DeserializeOwned + Debug + 'static + Send + Sync> DecodeStream<T> for Js
                                     lines_scanned += 1;
                                     match serde_json::from_slice::<T>(&content) {
                                         Ok(r) => {
-                                            tx.send(Ok(DataSourceMessage::new(&name, r)))
+                                            tx.send(Ok(DataSourceMessage::new(&source, r)))
                                                 .await
                                                 .map_err(|e| {
-                                                    DataStoreError::send_error(&name, &source, e)
+                                                    DataStoreError::send_error(
+                                                        &name,
+                                                        source.to_string(),
+                                                        e,
+                                                    )
                                                 })?;
                                             lines_scanned += 1;
                                         }
                                         Err(val) => {
-                                            log::error!("{}: {}", &name, val);
+                                            log::error!("{} {}: {}", &name, &source, val);
                                             match tx
                                                 .send(Err(DataStoreError::Deserialize {
                                                     message: val.to_string(),
                                                     attempted_string: format!("{:?}", content),
-                                                }))
+                                                }
+                                                .at(&source)))
                                                 .await
                                             {
                                                 Ok(_) => {
@@ -64,7 +69,9 @@ impl<T: DeserializeOwned + Debug + 'static + Send + Sync> DecodeStream<T> for Js
                                                 }
                                                 Err(e) => {
                                                     return Err(DataStoreError::send_error(
-                                                        &name, &source, e,
+                                                        &name,
+                                                        source.to_string(),
+                                                        e,
                                                     ));
                                                 }
                                             }
@@ -82,11 +89,11 @@ impl<T: DeserializeOwned + Debug + 'static + Send + Sync> DecodeStream<T> for Js
                             };
                         }
                         source_stream_jh.await??;
-                        Ok(DataSourceStats { lines_scanned })
+                        Ok(DataSourceStats::new(lines_scanned))
                     });
                 return Box::new(DecodedSource {
                     source_name: source_name.clone(),
-                    ds_task_result: Ok((rx, jh)),
+                    ds_task_result: Ok((rx.into(), jh)),
                 });
             }
             Err(er) => {
@@ -0,0 +1,410 @@
+use super::*;
+use std::cell::RefCell;
+use std::io::{BufRead, BufReader};
+use std::rc::Rc;
+
+/// Decodes documents holding a JSON array, like `[ {...}, {...} ]`, emitting every element as it
+/// is read so the whole document is never held in memory.  Unlike the
+/// [crate::decoder::json::JsonDecoder] elements may span several lines.  Each source (for example
+/// each file of a LocalFs) is a separate document.
+#[derive(Default)]
+pub struct JsonArrayDecoder {
+    /// JSON pointer to the array inside the document, like `/data/items`.  A pointer to something
+    /// other than an array emits that single value.  When not set the document is read as a
+    /// sequence of top-level values, like concatenated pretty printed objects, where the
+    /// elements of each array are emitted and every other value is emitted as it is
+    pub pointer: Option<String>,
+}
+
+impl JsonArrayDecoder {
+    pub fn new<T>(source: Box<dyn DataSource<Bytes>>) -> Box<dyn DataSource<T>>
+    where
+        T: DeserializeOwned + Debug + Send + Sync + 'static,
+    {
+        LazyDecodedSource::boxed(JsonArrayDecoder::default(), source)
+    }
+
+    pub fn with_pointer<T, P: Into<String>>(
+        pointer: P,
+        source: Box<dyn DataSource<Bytes>>,
+    ) -> Box<dyn DataSource<T>>
+    where
+        T: DeserializeOwned + Debug + Send + Sync + 'static,
+    {
+        let decoder = JsonArrayDecoder {
+            pointer: Some(pointer.into()),
+        };
+        LazyDecodedSource::boxed(decoder, source)
+    }
+}
+
+/// Where the scanner is inside the document
+enum ScanState {
+    /// the pointer has not been followed yet
+    Start,
+    /// the pointer refers to a single value
+    Value,
+    /// inside the array, the flag is set once an element was read
+    Array(bool),
+    Done,
+}
+
+/// Splits a JSON document into the raw bytes of its values without parsing them
+struct JsonScanner<R: Read> {
+    reader: BufReader<R>,
+    state: ScanState,
+    /// line (starting at 1) and byte offset of the next byte
+    line: usize,
+    offset: u64,
+    /// line and byte offset where the last value read starts
+    value_at: (usize, u64),
+}
+
+impl<R: Read> JsonScanner<R> {
+    fn new(reader: R) -> Self {
+        JsonScanner {
+            reader: BufReader::new(reader),
+            state: ScanState::Start,
+            line: 1,
+            offset: 0,
+            value_at: (1, 0),
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, String> {
+        let buf = self.reader.fill_buf().map_err(|er| er.to_string())?;
+        Ok(buf.first().copied())
+    }
+
+    fn next(&mut self) -> Result<u8, String> {
+        match self.peek()? {
+            Some(b) => {
+                self.reader.consume(1);
+                self.offset += 1;
+                if b == b'\n' {
+                    self.line += 1;
+                }
+                Ok(b)
+            }
+            None => Err(String::from("unexpected end of the document")),
+        }
+    }
+
+    /// Consumes the byte which was peeked
+    fn skip(&mut self) {
+        let _ = self.next();
+    }
+
+    /// Skips whitespace and returns the next byte without consuming it
+    fn peek_token(&mut self) -> Result<Option<u8>, String> {
+        while let Some(b) = self.peek()? {
+            if !b.is_ascii_whitespace() {
+                return Ok(Some(b));
+            }
+            self.skip();
+        }
+        Ok(None)
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), String> {
+        match self.peek_token()? {
+            Some(b) if b == expected => {
+                self.skip();
+                Ok(())
+            }
+            Some(b) => Err(format!(
+                "expected '{}' but found '{}'",
+                expected as char, b as char
+            )),
+            None => Err(format!(
+                "expected '{}' but the document ended",
+                expected as char
+            )),
+        }
+    }
+
+    fn read_string(&mut self, out: &mut Vec<u8>) -> Result<(), String> {
+        out.push(self.next()?);
+        loop {
+            let b = self.next()?;
+            out.push(b);
+            match b {
+                b'\\' => out.push(self.next()?),
+                b'"' => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Appends the raw bytes of the next value to `out`
+    fn read_value(&mut self, out: &mut Vec<u8>) -> Result<(), String> {
+        let token = self.peek_token()?;
+        self.value_at = (self.line, self.offset);
+        match token {
+            Some(b'"') => self.read_string(out),
+            Some(b'{') | Some(b'[') => {
+                let mut depth = 0_usize;
+                loop {
+                    match self.peek()? {
+                        Some(b'"') => {
+                            self.read_string(out)?;
+                            continue;
+                        }
+                        Some(b'{') | Some(b'[') => depth += 1,
+                        Some(b'}') | Some(b']') => depth -= 1,
+                        Some(_) => {}
+                        None => return Err(String::from("unexpected end of the document")),
+                    }
+                    out.push(self.next()?);
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+            }
+            Some(b) => {
+                // numbers, true, false and null end at the next delimiter
+                while let Some(b) = self.peek()? {
+                    if b.is_ascii_whitespace() || b == b',' || b == b']' || b == b'}' {
+                        break;
+                    }
+                    out.push(self.next()?);
+                }
+                match out.is_empty() {
+                    true => Err(format!("expected a value but found '{}'", b as char)),
+                    false => Ok(()),
+                }
+            }
+            None => Err(String::from("unexpected end of the document")),
+        }
+    }
+
+    fn skip_value(&mut self) -> Result<(), String> {
+        let mut ignored = Vec::new();
+        self.read_value(&mut ignored)
+    }
+
+    /// Returns the raw bytes of the next element of the array the pointer refers to
+    fn next_element(&mut self, pointer: &[String]) -> Result<Option<Vec<u8>>, String> {
+        let mut out = Vec::new();
+        match self.state {
+            ScanState::Start => {
+                if !self.find(pointer)? {
+                    self.state = ScanState::Done;
+                    return Err(String::from("the pointer does not refer to anything"));
+                }
+                self.state = match self.peek_token()? {
+                    Some(b'[') => {
+                        self.skip();
+                        ScanState::Array(false)
+                    }
+                    _ => ScanState::Value,
+                };
+                self.next_element(pointer)
+            }
+            ScanState::Value => {
+                self.state = ScanState::Done;
+                self.read_value(&mut out)?;
+                Ok(Some(out))
+            }
+            ScanState::Array(started) => match self.peek_token()? {
+                Some(b']') => {
+                    self.state = ScanState::Done;
+                    Ok(None)
+                }
+                Some(b',') if started => {
+                    self.skip();
+                    self.read_value(&mut out)?;
+                    Ok(Some(out))
+                }
+                _ if !started => {
+                    self.state = ScanState::Array(true);
+                    self.read_value(&mut out)?;
+                    Ok(Some(out))
+                }
+                _ => Err(String::from(
+                    "expected ',' or ']' between the array elements",
+                )),
+            },
+            // without a pointer the document may hold more values, like concatenated objects
+            ScanState::Done if pointer.is_empty() => match self.peek_token()? {
+                Some(_) => {
+                    self.state = ScanState::Start;
+                    self.next_element(pointer)
+                }
+                None => Ok(None),
+            },
+            ScanState::Done => Ok(None),
+        }
+    }
+
+    /// Moves to the value the pointer refers to, returns false when it does not exist
+    fn find(&mut self, pointer: &[String]) -> Result<bool, String> {
+        let (segment, rest) = match pointer.split_first() {
+            Some(split) => split,
+            None => return Ok(true),
+        };
+        match self.peek_token()? {
+            Some(b'{') => {
+                self.skip();
+                if self.peek_token()? == Some(b'}') {
+                    return Ok(false);
+                }
+                loop {
+                    let mut raw_key = Vec::new();
+                    match self.peek_token()? {
+                        Some(b'"') => self.read_string(&mut raw_key)?,
+                        _ => return Err(String::from("expected an object key")),
+                    }
+                    let key: String =
+                        serde_json::from_slice(&raw_key).map_err(|er| er.to_string())?;
+                    self.expect(b':')?;
+                    if &key == segment {
+                        return self.find(rest);
+                    }
+                    self.skip_value()?;
+                    match self.peek_token()? {
+                        Some(b',') => self.skip(),
+                        _ => return Ok(false),
+                    }
+                }
+            }
+            Some(b'[') => {
+                let index: usize = match segment.parse() {
+                    Ok(index) => index,
+                    Err(_) => return Ok(false),
+                };
+                self.skip();
+                if self.peek_token()? == Some(b']') {
+                    return Ok(false);
+                }
+                for _ in 0..index {
+                    self.skip_value()?;
+                    match self.peek_token()? {
+                        Some(b',') => self.skip(),
+                        _ => return Ok(false),
+                    }
+                }
+                self.find(rest)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+fn send<T: Debug + Send + 'static>(
+    tx: &mut channels::ChunkSender<Result<DataSourceMessage<T>, DataStoreError>>,
+    source: &Provenance,
+    message: Result<DataSourceMessage<T>, DataStoreError>,
+) -> Result<(), DataStoreError> {
+    tx.blocking_send(message)
+        .map_err(|e| DataStoreError::send_error(source.to_string(), "JsonArrayDecoder", e))
+}
+
+impl<T: DeserializeOwned + Debug + 'static + Send + Sync> DecodeStream<T> for JsonArrayDecoder {
+    fn decode_source(self, source: Box<dyn DataSource<Bytes>>) -> Box<dyn DataSource<T>> {
+        use tokio::task::JoinHandle;
+        let source_name = source.name();
+        let pointer = pointer_segments(self.pointer.as_deref().unwrap_or(""));
+
+        match source.start_stream() {
+            Ok((source_rx, source_stream_jh)) => {
+                let (chunk_tx, chunk_rx) = channels::chunk_channel();
+                let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> =
+                    tokio::spawn(async move {
+                        // the scanner reads from a blocking reader, so it gets its own thread which
+                        // hands the elements back in chunks
+                        let decode_jh: JoinHandle<Result<DataSourceStats, DataStoreError>> =
+                            tokio::task::spawn_blocking(move || {
+                                let mut lines_scanned = 0_usize;
+                                let chunk_tx = Rc::new(RefCell::new(chunk_tx));
+                                let flush_tx = chunk_tx.clone();
+                                // the records held back are sent before waiting for more lines
+                                let mut source_lines = SourceLines::new(source_rx)
+                                    .with_flush(move || flush_tx.borrow_mut().blocking_flush());
+                                while source_lines.start_next_source() {
+                                    let source = source_lines.source.clone();
+                                    let mut scanner = JsonScanner::new(source_lines);
+                                    loop {
+                                        let message = match scanner.next_element(&pointer) {
+                                            Ok(Some(raw)) => {
+                                                let (line, offset) = scanner.value_at;
+                                                let provenance =
+                                                    provenance_at(&source, line, offset);
+                                                match serde_json::from_slice::<T>(&raw) {
+                                                    Ok(item) => {
+                                                        Ok(DataSourceMessage::new(provenance, item))
+                                                    }
+                                                    Err(er) => Err(DataStoreError::Deserialize {
+                                                        message: er.to_string(),
+                                                        attempted_string: String::from_utf8_lossy(
+                                                            &raw,
+                                                        )
+                                                        .to_string(),
+                                                    }
+                                                    .at(provenance)),
+                                                }
+                                            }
+                                            Ok(None) => break,
+                                            Err(message) => {
+                                                // the rest of this document can not be trusted
+                                                send(
+                                                    &mut chunk_tx.borrow_mut(),
+                                                    &source,
+                                                    Err(DataStoreError::Deserialize {
+                                                        message,
+                                                        attempted_string: format!(
+                                                            "json array of {}",
+                                                            &source.key
+                                                        ),
+                                                    }
+                                                    .at(provenance_at(
+                                                        &source,
+                                                        scanner.line,
+                                                        scanner.offset,
+                                                    ))),
+                                                )?;
+                                                break;
+                                            }
+                                        };
+                                        lines_scanned += 1;
+                                        send(&mut chunk_tx.borrow_mut(), &source, message)?;
+                                    }
+                                    source_lines = scanner.reader.into_inner();
+                                    // skip whatever is left of the document after a scan error
+                                    // or after the value the pointer refers to
+                                    std::io::copy(&mut source_lines, &mut std::io::sink())?;
+                                }
+                                chunk_tx.borrow_mut().blocking_flush().map_err(|e| {
+                                    DataStoreError::send_error("JsonArrayDecoder", "", e)
+                                })?;
+                                if let Some(e) = source_lines.error {
+                                    log::error!("An error happened in JsonArrayDecoder: {}", e);
+                                    return Err(e);
+                                }
+                                Ok(DataSourceStats::new(lines_scanned))
+                            });
+                        let stats = decode_jh.await??;
+                        source_stream_jh.await??;
+                        Ok(stats)
+                    });
+                Box::new(DecodedSource {
+                    source_name,
+                    ds_task_result: Ok((chunk_rx, jh)),
+                })
+            }
+            Err(er) => Box::new(DecodedSource {
+                source_name,
+                ds_task_result: Err(er),
+            }),
+        }
+    }
+}
+
+/// Splits a JSON pointer into its unescaped segments
+fn pointer_segments(pointer: &str) -> Vec<String> {
+    pointer
+        .split('/')
+        .skip(1)
+        .map(|s| s.replace("~1", "/").replace("~0", "~"))
+        .collect()
+}
@@ -0,0 +1,139 @@
+use super::*;
+use arrow_json::LineDelimitedWriter;
+use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use crate::datastore::channels::ChunkSender;
+
+/// Decodes Parquet files into rows.  Every message of the source must hold a whole file, so use
+/// it with a source that has `whole_files` turned on like
+/// [crate::datastore::fs::LocalFs::whole_files].  Each row is converted to JSON and then
+/// deserialized into `T`, so any type that works with the
+/// [crate::decoder::json::JsonDecoder] works here as well.
+pub struct ParquetDecoder {
+    /// number of rows decoded at a time
+    pub batch_size: usize,
+}
+
+impl Default for ParquetDecoder {
+    fn default() -> Self {
+        ParquetDecoder { batch_size: 1024 }
+    }
+}
+
+impl ParquetDecoder {
+    pub fn new<T>(source: Box<dyn DataSource<Bytes>>) -> Box<dyn DataSource<T>>
+    where
+        T: DeserializeOwned + Debug + Send + Sync + 'static,
+    {
+        LazyDecodedSource::boxed(ParquetDecoder::default(), source)
+    }
+}
+
+/// Sends every row of a parquet file, returns the number of rows read.  The rows are numbered in
+/// the line of their provenance
+fn decode_file<T: DeserializeOwned + Debug + Send + Sync + 'static>(
+    tx: &mut ChunkSender<Result<DataSourceMessage<T>, DataStoreError>>,
+    source: &Provenance,
+    content: Bytes,
+    batch_size: usize,
+) -> Result<usize, DataStoreError> {
+    let reader = match ParquetRecordBatchReaderBuilder::try_new(content)
+        .and_then(|builder| builder.with_batch_size(batch_size).build())
+    {
+        Ok(reader) => reader,
+        Err(er) => {
+            tx.blocking_send(Err(DataStoreError::Deserialize {
+                message: er.to_string(),
+                attempted_string: format!("parquet file {}", source.key),
+            }
+            .at(source)))
+            .map_err(|e| DataStoreError::send_error(source.to_string(), "ParquetDecoder", e))?;
+            return Ok(0);
+        }
+    };
+    let mut lines_scanned = 0_usize;
+    for batch in reader {
+        let batch = batch.map_err(|er| {
+            DataStoreError::FatalIO(format!("Could not read {}: {}", source, er))
+        })?;
+        let mut writer = LineDelimitedWriter::new(Vec::new());
+        writer
+            .write(&batch)
+            .and_then(|_| writer.finish())
+            .map_err(|er| DataStoreError::FatalIO(er.to_string()))?;
+        for line in writer.into_inner().split(|b| *b == b'\n') {
+            if line.is_empty() {
+                continue;
+            }
+            lines_scanned += 1;
+            let provenance = source.clone().with_line(lines_scanned);
+            let message = match serde_json::from_slice::<T>(line) {
+                Ok(row) => Ok(DataSourceMessage::new(&provenance, row)),
+                Err(er) => Err(DataStoreError::Deserialize {
+                    message: er.to_string(),
+                    attempted_string: String::from_utf8_lossy(line).to_string(),
+                }
+                .at(&provenance)),
+            };
+            tx.blocking_send(message).map_err(|e| {
+                DataStoreError::send_error(provenance.to_string(), "ParquetDecoder", e)
+            })?;
+        }
+    }
+    Ok(lines_scanned)
+}
+
+impl<T: DeserializeOwned + Debug + 'static + Send + Sync> DecodeStream<T> for ParquetDecoder {
+    fn decode_source(self, source: Box<dyn DataSource<Bytes>>) -> Box<dyn DataSource<T>> {
+        use tokio::task::JoinHandle;
+        let source_name = source.name();
+        let batch_size = self.batch_size;
+
+        match source.start_stream() {
+            Ok((mut source_rx, source_stream_jh)) => {
+                let (mut chunk_tx, chunk_rx) = channels::chunk_channel();
+                let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> =
+                    tokio::spawn(async move {
+                        let mut lines_scanned = 0_usize;
+                        loop {
+                            match source_rx.recv().await {
+                                Some(Ok(DataSourceMessage::Data { source, content })) => {
+                                    // decoding is cpu bound, so keep it off the runtime and
+                                    // hand the rows back in chunks
+                                    let decode_jh = tokio::task::spawn_blocking(move || {
+                                        let rows = decode_file(
+                                            &mut chunk_tx,
+                                            &source,
+                                            content,
+                                            batch_size,
+                                        )?;
+                                        chunk_tx.blocking_flush().map_err(|e| {
+                                            DataStoreError::send_error("ParquetDecoder", "", e)
+                                        })?;
+                                        Ok::<_, DataStoreError>((rows, chunk_tx))
+                                    });
+                                    let (rows, tx) = decode_jh.await??;
+                                    lines_scanned += rows;
+                                    chunk_tx = tx;
+                                }
+                                Some(Err(e)) => {
+                                    log::error!("An error happened in ParquetDecoder: {}", e);
+                                    return Err(e);
+                                }
+                                None => break,
+                            }
+                        }
+                        source_stream_jh.await??;
+                        Ok(DataSourceStats::new(lines_scanned))
+                    });
+                Box::new(DecodedSource {
+                    source_name,
+                    ds_task_result: Ok((chunk_rx, jh)),
+                })
+            }
+            Err(er) => Box::new(DecodedSource {
+                source_name,
+                ds_task_result: Err(er),
+            }),
+        }
+    }
+}
@@ -19,14 +19,14 @@ impl StringDecoder {
     where
         String: Debug + Send + Sync + 'static,
     {
-        DecodeStream::decode_source(self, source)
+        LazyDecodedSource::boxed(self, source)
     }
     pub fn with_datasource<T>(self, source: T) -> Box<dyn DataSource<String>>
     where
         String: Debug + Send + Sync + 'static,
         T: DataSource<Bytes> + 'static,
     {
-        DecodeStream::decode_source(self, Box::new(source) as Box<dyn DataSource<Bytes>>)
+        LazyDecodedSource::boxed(self, Box::new(source) as Box<dyn DataSource<Bytes>>)
     }
 }
 
@@ -37,7 +37,7 @@ where
     fn decode_source(self, source: Box<dyn DataSource<Bytes>>) -> Box<dyn DataSource<T>> {
         use tokio::sync::mpsc::channel;
         use tokio::task::JoinHandle;
-        let (tx, rx) = channel(1);
+        let (tx, rx) = channel(channels::buffer_size());
 
         let source_name = source.name();
         let name = source_name.clone();
@@ -53,10 +53,14 @@ where
                                 Some(Ok(DataSourceMessage::Data { source, content })) => {
                                     lines_scanned += 1;
                                     let s = String::from_utf8_lossy(&*content).to_string();
-                                    tx.send(Ok(DataSourceMessage::new(&name, s)))
+                                    tx.send(Ok(DataSourceMessage::new(&source, s)))
                                         .await
                                         .map_err(|e| {
-                                            DataStoreError::send_error(&name, &source, e)
+                                            DataStoreError::send_error(
+                                                &name,
+                                                source.to_string(),
+                                                e,
+                                            )
                                         })?;
                                     lines_scanned += 1;
                                 }
@@ -70,11 +74,11 @@ where
                             };
                         }
                         source_stream_jh.await??;
-                        Ok(DataSourceStats { lines_scanned })
+                        Ok(DataSourceStats::new(lines_scanned))
                     });
                 return Box::new(DecodedSource {
                     source_name: source_name.clone(),
-                    ds_task_result: Ok((rx, jh)),
+                    ds_task_result: Ok((rx.into(), jh)),
                 });
             }
             Err(er) => {
@@ -0,0 +1,290 @@
+use crate::datastore::error::*;
+use crate::datastore::simple::SimpleStore;
+use crate::datastore::*;
+use crate::joins::KeyFn;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Extension of the files written by [StoredSeenKeys]
+pub const SEEN_KEYS_EXT: &str = "seen.json";
+
+/// The keys a [Dedup] has already let through
+#[async_trait]
+pub trait SeenKeys<K>: Send + Sync {
+    /// Remembers the key, returns false if it was seen before
+    async fn insert(&mut self, key: K) -> Result<bool, DataStoreError>;
+}
+
+#[async_trait]
+impl<K: Hash + Eq + Send + Sync> SeenKeys<K> for HashSet<K> {
+    async fn insert(&mut self, key: K) -> Result<bool, DataStoreError> {
+        Ok(HashSet::insert(self, key))
+    }
+}
+
+/// Remembers keys in a fixed amount of memory.  Some keys which were not seen before are
+/// taken for seen ones, so a few items which are not duplicates are dropped
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u64,
+}
+
+impl BloomFilter {
+    /// Sized to hold `keys` keys, of which about `false_positive_rate` are wrongly taken for
+    /// duplicates
+    pub fn new(keys: usize, false_positive_rate: f64) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let keys = keys.max(1) as f64;
+        let rate = false_positive_rate.clamp(1e-9, 0.5);
+        let bits = (-keys * rate.ln() / (ln2 * ln2)).ceil().max(64.0);
+        let hashes = (bits / keys * ln2).round().clamp(1.0, 32.0);
+        BloomFilter {
+            bits: vec![0; (bits as usize).div_ceil(64)],
+            hashes: hashes as u64,
+        }
+    }
+}
+
+#[async_trait]
+impl<K: Hash + Send + 'static> SeenKeys<K> for BloomFilter {
+    async fn insert(&mut self, key: K) -> Result<bool, DataStoreError> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let h1 = hasher.finish();
+        h1.hash(&mut hasher);
+        let h2 = hasher.finish() | 1;
+        let len = self.bits.len() as u64 * 64;
+        let mut seen = true;
+        for i in 0..self.hashes {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % len;
+            let (word, mask) = ((bit / 64) as usize, 1 << (bit % 64));
+            if self.bits[word] & mask == 0 {
+                seen = false;
+                self.bits[word] |= mask;
+            }
+        }
+        Ok(!seen)
+    }
+}
+
+/// Keys kept in a [SimpleStore] between runs of a job, so items seen by previous runs are
+/// dropped as well.  The keys are loaded with the first item and held in memory.  The keys
+/// first seen by a run are only stored by [SeenKeysCommit::commit], to be called once the
+/// output of the stream succeeded, so a failed run does not hide its items from the next one.
+/// Each commit writes its keys to a file of its own, `{job_id}.{name}.{run}.seen.json`, and the
+/// number of runs to `{job_id}.{name}.seen.json`
+pub struct StoredSeenKeys<K>(Arc<Mutex<StoredKeys<K>>>);
+
+/// Stores the keys first seen by the run of a [StoredSeenKeys]
+pub struct SeenKeysCommit<K>(Arc<Mutex<StoredKeys<K>>>);
+
+struct StoredKeys<K> {
+    store: Box<dyn SimpleStore<serde_json::Value>>,
+    /// `{job_id}.{name}`, the files are named after it
+    prefix: String,
+    /// the keys of the previous runs, loaded with the first item
+    keys: Option<HashSet<K>>,
+    /// the number of runs whose keys were stored
+    runs: usize,
+    /// the keys first seen by this run
+    added: HashSet<K>,
+}
+
+impl<K> StoredSeenKeys<K> {
+    /// `job_id` is usually the id of the JobRunner, and `name` tells apart the Dedups of a job
+    pub fn new(store: Box<dyn SimpleStore<serde_json::Value>>, job_id: &str, name: &str) -> Self {
+        StoredSeenKeys(Arc::new(Mutex::new(StoredKeys {
+            store,
+            prefix: format!("{}.{}", job_id, name),
+            keys: None,
+            runs: 0,
+            added: HashSet::new(),
+        })))
+    }
+
+    pub fn commit_handle(&self) -> SeenKeysCommit<K> {
+        SeenKeysCommit(self.0.clone())
+    }
+}
+
+impl<K> StoredKeys<K>
+where
+    K: DeserializeOwned + Hash + Eq,
+{
+    fn index_path(&self) -> String {
+        format!("{}.{}", self.prefix, SEEN_KEYS_EXT)
+    }
+
+    fn run_path(&self, run: usize) -> String {
+        format!("{}.{}.{}", self.prefix, run, SEEN_KEYS_EXT)
+    }
+
+    /// Loads None if the file does not exist
+    async fn load_json<T: DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<Option<T>, DataStoreError> {
+        match self.store.load(path).await {
+            Ok(json) => {
+                serde_json::from_value(json)
+                    .map(Some)
+                    .map_err(|er| DataStoreError::Deserialize {
+                        message: format!("{} in {}", er, path),
+                        attempted_string: path.to_string(),
+                    })
+            }
+            Err(DataStoreError::NotExist { .. }) => Ok(None),
+            Err(er) => Err(er),
+        }
+    }
+
+    async fn load(&mut self) -> Result<(), DataStoreError> {
+        let runs: usize = self.load_json(&self.index_path()).await?.unwrap_or(0);
+        let mut keys = HashSet::new();
+        for run in 0..runs {
+            let path = self.run_path(run);
+            match self.load_json::<Vec<K>>(&path).await? {
+                Some(run_keys) => keys.extend(run_keys),
+                None => {
+                    return Err(DataStoreError::NotExist {
+                        key: path,
+                        error: format!("counted in {}", self.index_path()),
+                    })
+                }
+            }
+        }
+        self.keys = Some(keys);
+        self.runs = runs;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<K> SeenKeys<K> for StoredSeenKeys<K>
+where
+    K: Serialize + DeserializeOwned + Hash + Eq + Send + Sync + 'static,
+{
+    async fn insert(&mut self, key: K) -> Result<bool, DataStoreError> {
+        let mut stored = self.0.lock().await;
+        if stored.keys.is_none() {
+            stored.load().await?;
+        }
+        if stored.keys.as_ref().is_some_and(|keys| keys.contains(&key)) {
+            return Ok(false);
+        }
+        Ok(stored.added.insert(key))
+    }
+}
+
+impl<K> SeenKeysCommit<K>
+where
+    K: Serialize + DeserializeOwned + Hash + Eq + Send + Sync + 'static,
+{
+    /// Stores the keys seen since the last commit, as the keys of a new run
+    pub async fn commit(&self) -> Result<(), DataStoreError> {
+        let mut stored = self.0.lock().await;
+        if stored.added.is_empty() {
+            return Ok(());
+        }
+        let run = stored.runs;
+        let json = serde_json::to_value(stored.added.iter().collect::<Vec<_>>())
+            .map_err(|er| DataStoreError::FatalIO(er.to_string()))?;
+        stored.store.write(&stored.run_path(run), json).await?;
+        // the file of the run is only used once it is listed
+        stored
+            .store
+            .write(&stored.index_path(), serde_json::json!(run + 1))
+            .await?;
+        stored.runs = run + 1;
+        let added = std::mem::take(&mut stored.added);
+        if let Some(keys) = stored.keys.as_mut() {
+            keys.extend(added);
+        }
+        Ok(())
+    }
+}
+
+/// Drops the items whose key was seen before, keeping the first one.  The seen keys are kept in
+/// a HashSet unless changed with [Dedup::with_seen_keys].  The dropped items are counted in
+/// [DataSourceStats::duplicates]
+pub struct Dedup<T, K> {
+    pub input: Box<dyn DataSource<T>>,
+    pub key: KeyFn<T, K>,
+    pub seen: Box<dyn SeenKeys<K>>,
+}
+
+impl<T, K> Dedup<T, K>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+{
+    pub fn new<F>(input: Box<dyn DataSource<T>>, key: F) -> Self
+    where
+        F: Fn(&T) -> K + Send + Sync + 'static,
+    {
+        Dedup {
+            input,
+            key: Box::new(key),
+            seen: Box::new(HashSet::new()),
+        }
+    }
+
+    pub fn with_seen_keys<S: SeenKeys<K> + 'static>(self, seen: S) -> Self {
+        Dedup {
+            seen: Box::new(seen),
+            ..self
+        }
+    }
+}
+
+impl<T, K> DataSource<T> for Dedup<T, K>
+where
+    T: Debug + Send + Sync + 'static,
+    K: Send + 'static,
+{
+    fn name(&self) -> String {
+        format!("Dedup-{}", self.input.name())
+    }
+
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<T>, DataStoreError> {
+        use tokio::sync::mpsc::channel;
+        use tokio::task::JoinHandle;
+        let (tx, rx) = channel(channels::buffer_size());
+        let name = self.name();
+        let (mut input_rx, input_jh) = self.input.start_stream()?;
+        let key = self.key;
+        let mut seen = self.seen;
+        let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
+            let mut stats = DataSourceStats::default();
+            while let Some(message) = input_rx.recv().await {
+                let message = match message {
+                    Ok(DataSourceMessage::Data { source, content }) => {
+                        stats.lines_scanned += 1;
+                        if !seen
+                            .insert(key(&content))
+                            .await
+                            .map_err(|er| er.at(source.clone()))?
+                        {
+                            stats.duplicates += 1;
+                            continue;
+                        }
+                        Ok(DataSourceMessage::new(source, content))
+                    }
+                    Err(er) => Err(er),
+                };
+                tx.send(message)
+                    .await
+                    .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+            }
+            input_jh.await??;
+            Ok(stats)
+        });
+        Ok((rx, jh))
+    }
+}
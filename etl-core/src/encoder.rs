@@ -46,7 +46,7 @@ impl<T: Serialize + Debug + 'static + Sync + Send> DataOutput<T> for EncodedOutp
     ) -> anyhow::Result<DataOutputTask<T>> {
         // create a datasource for the encoder because it is needed to create the encoder
         // data sent to the data output will be forwarded here
-        let (data_source_tx, data_source_rx): (_, DataSourceRx<T>) = channel(1);
+        let (data_source_tx, data_source_rx): (_, DataSourceRx<T>) = channel(channels::buffer_size());
 
         let encoded_datasource = self
             .encoder
@@ -67,7 +67,7 @@ impl<T: Serialize + Debug + 'static + Sync + Send> DataOutput<T> for EncodedOutp
                                 .await
                                 .map_err(|e| {
                                     DataStoreError::send_error(
-                                        &source,
+                                        source.to_string(),
                                         "EncodedOutput:FinalizedOutput",
                                         e,
                                     )
@@ -92,7 +92,7 @@ impl<T: Serialize + Debug + 'static + Sync + Send> DataOutput<T> for EncodedOutp
         // which can be given to the encoder, but running into problem due to encode_source method
         // wants to consume the encoder but we are in a &mut environment here
         // pass the input_rx stream into the encoder
-        let (input_tx, mut input_rx) = channel(1);
+        let (input_tx, mut input_rx) = channel(channels::buffer_size());
         let output_name = String::from("EncodedOutput");
         let jh: JoinHandle<anyhow::Result<DataOutputStats>> = tokio::spawn(async move {
             loop {
@@ -141,7 +141,7 @@ pub mod csv_encoder {
             source: Box<dyn DataSource<I>>,
         ) -> Box<dyn DataSource<Bytes>> {
             use csv::WriterBuilder;
-            let (tx, rx) = channel(1);
+            let (tx, rx) = channel(channels::buffer_size());
             let source_name = source.name();
             match source.start_stream() {
                 Ok((mut source_rx, source_stream_jh)) => {
@@ -185,7 +185,11 @@ pub mod csv_encoder {
                                         tx.send(Ok(DataSourceMessage::new(&source, b)))
                                             .await
                                             .map_err(|e| {
-                                                DataStoreError::send_error(&source, "CsvDecoder", e)
+                                                DataStoreError::send_error(
+                                                    source.to_string(),
+                                                    "CsvDecoder",
+                                                    e,
+                                                )
                                             })?;
                                         lines_scanned += 1;
                                     }
@@ -199,7 +203,7 @@ pub mod csv_encoder {
                             }
 
                             source_stream_jh.await??;
-                            Ok(DataSourceStats { lines_scanned })
+                            Ok(DataSourceStats::new(lines_scanned))
                         });
                     return Box::new(EncodedSource {
                         source_name: source_name.clone(),
@@ -216,3 +220,480 @@ pub mod csv_encoder {
         }
     }
 }
+
+pub mod json_encoder {
+    use super::*;
+
+    /// Encodes each element as JSON.  By default every element is written on its own line
+    /// (NDJSON) so the output can be read back using the
+    /// [crate::decoder::json::JsonDecoder]
+    #[derive(Default)]
+    pub struct JsonLinesEncoder {
+        /// pretty print each element, note that the result is no longer line delimited
+        pub pretty: bool,
+        /// write a single JSON array document instead of one element per line
+        pub as_array: bool,
+    }
+
+    impl JsonLinesEncoder {
+        pub fn pretty() -> Self {
+            JsonLinesEncoder {
+                pretty: true,
+                ..Default::default()
+            }
+        }
+
+        pub fn array() -> Self {
+            JsonLinesEncoder {
+                as_array: true,
+                ..Default::default()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl<I: Serialize + Debug + 'static + Send> EncodeStream<I, Bytes> for JsonLinesEncoder {
+        async fn encode_source(
+            self: Box<Self>,
+            source: Box<dyn DataSource<I>>,
+        ) -> Box<dyn DataSource<Bytes>> {
+            let (tx, rx) = channel(channels::buffer_size());
+            let source_name = source.name();
+            let name = format!("JsonLinesEncoder:{}", &source_name);
+            match source.start_stream() {
+                Ok((mut source_rx, source_stream_jh)) => {
+                    let JsonLinesEncoder { pretty, as_array } = *self;
+                    let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> =
+                        tokio::spawn(async move {
+                            let mut lines_scanned = 0_usize;
+                            loop {
+                                match source_rx.recv().await {
+                                    Some(Ok(DataSourceMessage::Data { source, content })) => {
+                                        let json = match pretty {
+                                            true => serde_json::to_string_pretty(&content),
+                                            false => serde_json::to_string(&content),
+                                        }
+                                        .map_err(|er| DataStoreError::FatalIO(er.to_string()))?;
+                                        let line = match (as_array, lines_scanned) {
+                                            (true, 0) => format!("[\n{}", json),
+                                            (true, _) => format!(",\n{}", json),
+                                            (false, _) => format!("{}\n", json),
+                                        };
+                                        tx.send(Ok(DataSourceMessage::new(
+                                            &source,
+                                            Bytes::from(line),
+                                        )))
+                                        .await
+                                        .map_err(|e| {
+                                            DataStoreError::send_error(source.to_string(), &name, e)
+                                        })?;
+                                        lines_scanned += 1;
+                                    }
+                                    Some(Err(e)) => {
+                                        return Err(e);
+                                    }
+                                    None => {
+                                        break;
+                                    }
+                                }
+                            }
+                            if as_array {
+                                let end = match lines_scanned {
+                                    0 => "[]\n",
+                                    _ => "\n]\n",
+                                };
+                                tx.send(Ok(DataSourceMessage::new(&name, Bytes::from(end))))
+                                    .await
+                                    .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+                            }
+
+                            source_stream_jh.await??;
+                            Ok(DataSourceStats::new(lines_scanned))
+                        });
+                    Box::new(EncodedSource {
+                        source_name,
+                        ds_task_result: Ok((rx, jh)),
+                    })
+                }
+                Err(er) => Box::new(EncodedSource {
+                    source_name,
+                    ds_task_result: Err(er),
+                }),
+            }
+        }
+    }
+}
+
+pub mod parquet_encoder {
+    use super::*;
+    use ::parquet::arrow::ArrowWriter;
+    use ::parquet::basic::Compression;
+    use ::parquet::file::properties::WriterProperties;
+    use arrow_json::reader::{infer_json_schema_from_iterator, ReaderBuilder};
+    use arrow_schema::{Schema, SchemaRef};
+    use std::sync::Arc;
+
+    /// Encodes elements into a single Parquet file.  Elements are buffered until a row group is
+    /// full, then the row group is written and its bytes are sent to the output, so use it in an
+    /// [EncodedOutput] with an output that writes the bytes as they are, like
+    /// [crate::datastore::fs::LocalFs].  The schema is inferred from the first row group unless
+    /// one is given, and a later row with a field which is not in the inferred schema fails the
+    /// stream instead of losing the field.
+    pub struct ParquetEncoder {
+        /// number of rows in each row group
+        pub row_group_size: usize,
+        /// schema of the file, fields missing from it are not written
+        pub schema: Option<SchemaRef>,
+        pub compression: Compression,
+    }
+
+    impl Default for ParquetEncoder {
+        fn default() -> Self {
+            ParquetEncoder {
+                row_group_size: 1024 * 64,
+                schema: None,
+                compression: Compression::SNAPPY,
+            }
+        }
+    }
+
+    impl ParquetEncoder {
+        pub fn with_schema(schema: Schema) -> Self {
+            ParquetEncoder {
+                schema: Some(Arc::new(schema)),
+                ..Default::default()
+            }
+        }
+    }
+
+    fn to_fatal<E: std::fmt::Display>(er: E) -> DataStoreError {
+        DataStoreError::FatalIO(format!("ParquetEncoder: {}", er))
+    }
+
+    fn infer_schema<I: Serialize>(rows: &[I]) -> Result<SchemaRef, DataStoreError> {
+        let values = rows
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(to_fatal)?;
+        let schema = infer_json_schema_from_iterator(values.into_iter().map(Ok))
+            .map_err(to_fatal)?;
+        Ok(Arc::new(schema))
+    }
+
+    /// The file being written, which is created once the schema is known
+    struct ParquetFile {
+        writer: Option<ArrowWriter<Vec<u8>>>,
+        schema: Option<SchemaRef>,
+        /// rows must not have fields missing from the schema, when it is inferred
+        strict: bool,
+        props: WriterProperties,
+    }
+
+    impl ParquetFile {
+        /// Creates the writer, the schema is inferred from the rows when it was not given
+        fn create_writer<I: Serialize>(
+            &self,
+            rows: &[I],
+        ) -> Result<(ArrowWriter<Vec<u8>>, SchemaRef), DataStoreError> {
+            let schema = match &self.schema {
+                Some(schema) => schema.clone(),
+                None => infer_schema(rows)?,
+            };
+            let writer =
+                ArrowWriter::try_new(Vec::new(), schema.clone(), Some(self.props.clone()))
+                    .map_err(to_fatal)?;
+            Ok((writer, schema))
+        }
+
+        /// Writes the rows as a row group, returns the bytes of the file written so far
+        fn write_row_group<I: Serialize>(&mut self, rows: &[I]) -> Result<Bytes, DataStoreError> {
+            if self.writer.is_none() {
+                let (writer, schema) = self.create_writer(rows)?;
+                self.writer = Some(writer);
+                self.schema = Some(schema);
+            }
+            let strict = self.strict;
+            let (writer, schema) = match (self.writer.as_mut(), &self.schema) {
+                (Some(writer), Some(schema)) => (writer, schema.clone()),
+                _ => unreachable!("the writer and schema are created together"),
+            };
+            let mut decoder = ReaderBuilder::new(schema)
+                .with_batch_size(rows.len().max(1))
+                .with_strict_mode(strict)
+                .build_decoder()
+                .map_err(to_fatal)?;
+            let decode_error = |er: arrow_schema::ArrowError| match strict {
+                true => to_fatal(format!(
+                    "a row does not fit the schema inferred from the first row group: {}",
+                    er
+                )),
+                false => to_fatal(er),
+            };
+            decoder.serialize(rows).map_err(decode_error)?;
+            if let Some(batch) = decoder.flush().map_err(decode_error)? {
+                writer.write(&batch).map_err(to_fatal)?;
+            }
+            writer.flush().map_err(to_fatal)?;
+            Ok(Bytes::from(std::mem::take(writer.inner_mut())))
+        }
+
+        /// Writes the footer, an empty source still produces a valid file
+        fn finish(mut self) -> Result<Bytes, DataStoreError> {
+            let writer = match self.writer.take() {
+                Some(writer) => writer,
+                None => {
+                    if self.schema.is_none() {
+                        self.schema = Some(Arc::new(Schema::empty()));
+                    }
+                    let rows: &[serde_json::Value] = &[];
+                    self.create_writer(rows)?.0
+                }
+            };
+            Ok(Bytes::from(writer.into_inner().map_err(to_fatal)?))
+        }
+    }
+
+    #[async_trait]
+    impl<I: Serialize + Debug + 'static + Send> EncodeStream<I, Bytes> for ParquetEncoder {
+        async fn encode_source(
+            self: Box<Self>,
+            source: Box<dyn DataSource<I>>,
+        ) -> Box<dyn DataSource<Bytes>> {
+            let (tx, rx) = channel(channels::buffer_size());
+            let source_name = source.name();
+            let name = format!("ParquetEncoder:{}", &source_name);
+            match source.start_stream() {
+                Ok((mut source_rx, source_stream_jh)) => {
+                    let row_group_size = self.row_group_size.max(1);
+                    let mut file = ParquetFile {
+                        writer: None,
+                        strict: self.schema.is_none(),
+                        schema: self.schema,
+                        props: WriterProperties::builder()
+                            .set_compression(self.compression)
+                            .set_max_row_group_size(row_group_size)
+                            .build(),
+                    };
+                    let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> =
+                        tokio::spawn(async move {
+                            let mut rows = Vec::with_capacity(row_group_size);
+                            let mut lines_scanned = 0_usize;
+                            loop {
+                                match source_rx.recv().await {
+                                    Some(Ok(DataSourceMessage::Data { content, .. })) => {
+                                        rows.push(content);
+                                        lines_scanned += 1;
+                                        if rows.len() < row_group_size {
+                                            continue;
+                                        }
+                                    }
+                                    Some(Err(e)) => {
+                                        return Err(e);
+                                    }
+                                    None => break,
+                                }
+                                let b = file.write_row_group(&rows)?;
+                                rows.clear();
+                                tx.send(Ok(DataSourceMessage::new(&name, b)))
+                                    .await
+                                    .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+                            }
+                            let mut b = Vec::new();
+                            if !rows.is_empty() {
+                                b.extend_from_slice(&file.write_row_group(&rows)?);
+                            }
+                            b.extend_from_slice(&file.finish()?);
+                            tx.send(Ok(DataSourceMessage::new(&name, Bytes::from(b))))
+                                .await
+                                .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+
+                            source_stream_jh.await??;
+                            Ok(DataSourceStats::new(lines_scanned))
+                        });
+                    Box::new(EncodedSource {
+                        source_name,
+                        ds_task_result: Ok((rx, jh)),
+                    })
+                }
+                Err(er) => Box::new(EncodedSource {
+                    source_name,
+                    ds_task_result: Err(er),
+                }),
+            }
+        }
+    }
+}
+
+pub mod avro_encoder {
+    use super::*;
+    use crate::decoder::avro::{derive_schema, write_datum};
+    use avro_schema::file::{Block, CompressedBlock, Compression};
+    use avro_schema::schema::{Record, Schema};
+    use avro_schema::write::{compress, write_block, write_metadata};
+
+    /// Encodes elements into a single Avro object container file.  When no schema is given it is
+    /// derived from the first element, see [crate::decoder::avro::derive_schema].  Like the
+    /// [crate::encoder::parquet_encoder::ParquetEncoder] the bytes are sent as each block is written, so use an
+    /// output which writes them as they are.
+    pub struct AvroEncoder {
+        /// writer schema, the elements are serialized to JSON and written according to it
+        pub schema: Option<Record>,
+        /// number of elements in each block
+        pub block_size: usize,
+        pub compression: Option<Compression>,
+    }
+
+    impl Default for AvroEncoder {
+        fn default() -> Self {
+            AvroEncoder {
+                schema: None,
+                block_size: 1024,
+                compression: None,
+            }
+        }
+    }
+
+    impl AvroEncoder {
+        pub fn with_schema(schema: Record) -> Self {
+            AvroEncoder {
+                schema: Some(schema),
+                ..Default::default()
+            }
+        }
+    }
+
+    fn to_fatal<E: std::fmt::Debug>(er: E) -> DataStoreError {
+        DataStoreError::FatalIO(format!("AvroEncoder: {:?}", er))
+    }
+
+    fn header(
+        record: Record,
+        compression: Option<Compression>,
+    ) -> Result<Vec<u8>, DataStoreError> {
+        let mut buf = Vec::new();
+        write_metadata(&mut buf, record, compression).map_err(to_fatal)?;
+        Ok(buf)
+    }
+
+    fn block(
+        block: &mut Block,
+        compression: Option<Compression>,
+    ) -> Result<Bytes, DataStoreError> {
+        let mut compressed = CompressedBlock::default();
+        compress(block, &mut compressed, compression).map_err(to_fatal)?;
+        let mut buf = Vec::new();
+        write_block(&mut buf, &compressed).map_err(to_fatal)?;
+        block.data.clear();
+        block.number_of_rows = 0;
+        Ok(Bytes::from(buf))
+    }
+
+    /// The schema of the file as a whole is always a record
+    fn derive_record(value: &serde_json::Value) -> Result<Record, DataStoreError> {
+        match derive_schema("record", value) {
+            Schema::Record(record) => Ok(record),
+            _ => Err(DataStoreError::FatalIO(format!(
+                "AvroEncoder can only derive a schema from records, got {}",
+                value
+            ))),
+        }
+    }
+
+    #[async_trait]
+    impl<I: Serialize + Debug + 'static + Send> EncodeStream<I, Bytes> for AvroEncoder {
+        async fn encode_source(
+            self: Box<Self>,
+            source: Box<dyn DataSource<I>>,
+        ) -> Box<dyn DataSource<Bytes>> {
+            let (tx, rx) = channel(channels::buffer_size());
+            let source_name = source.name();
+            let name = format!("AvroEncoder:{}", &source_name);
+            match source.start_stream() {
+                Ok((mut source_rx, source_stream_jh)) => {
+                    let AvroEncoder {
+                        schema,
+                        block_size,
+                        compression,
+                    } = *self;
+                    let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> =
+                        tokio::spawn(async move {
+                            let mut record = schema;
+                            // set once the header is written
+                            let mut schema = None;
+                            let mut current = Block::new(0, Vec::new());
+                            let mut lines_scanned = 0_usize;
+                            loop {
+                                let value = match source_rx.recv().await {
+                                    Some(Ok(DataSourceMessage::Data { content, .. })) => {
+                                        serde_json::to_value(&content).map_err(|er| {
+                                            DataStoreError::FatalIO(er.to_string())
+                                        })?
+                                    }
+                                    Some(Err(e)) => {
+                                        return Err(e);
+                                    }
+                                    None => break,
+                                };
+                                if schema.is_none() {
+                                    let r = match record.take() {
+                                        Some(r) => r,
+                                        None => derive_record(&value)?,
+                                    };
+                                    schema = Some(Schema::Record(r.clone()));
+                                    tx.send(Ok(DataSourceMessage::new(
+                                        &name,
+                                        Bytes::from(header(r, compression)?),
+                                    )))
+                                    .await
+                                    .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+                                }
+                                if let Some(schema) = &schema {
+                                    write_datum(&mut current.data, &value, schema).map_err(
+                                        |message| {
+                                            DataStoreError::FatalIO(format!(
+                                                "AvroEncoder: {}",
+                                                message
+                                            ))
+                                        },
+                                    )?;
+                                }
+                                current.number_of_rows += 1;
+                                lines_scanned += 1;
+                                if current.number_of_rows >= block_size.max(1) {
+                                    let b = block(&mut current, compression)?;
+                                    tx.send(Ok(DataSourceMessage::new(&name, b)))
+                                        .await
+                                        .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+                                }
+                            }
+                            let mut b = Vec::new();
+                            if schema.is_none() {
+                                // an empty source still produces a valid file
+                                let record =
+                                    record.unwrap_or_else(|| Record::new("record", vec![]));
+                                b.extend_from_slice(&header(record, compression)?);
+                            }
+                            if current.number_of_rows > 0 {
+                                b.extend_from_slice(&block(&mut current, compression)?);
+                            }
+                            tx.send(Ok(DataSourceMessage::new(&name, Bytes::from(b))))
+                                .await
+                                .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+
+                            source_stream_jh.await??;
+                            Ok(DataSourceStats::new(lines_scanned))
+                        });
+                    Box::new(EncodedSource {
+                        source_name,
+                        ds_task_result: Ok((rx, jh)),
+                    })
+                }
+                Err(er) => Box::new(EncodedSource {
+                    source_name,
+                    ds_task_result: Err(er),
+                }),
+            }
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use crate::batch::Batcher;
+use crate::datastore::error::DataStoreError;
+use crate::datastore::interop::StreamDataSource;
+use crate::datastore::*;
+use serde::de::DeserializeOwned;
+use std::fmt::Debug;
+use tokio::sync::mpsc::Sender;
+use tokio::task::JoinHandle;
+
+/// Chainable combinators for every [DataSource], so pipelines read top-to-bottom:
+///
+/// ```ignore
+/// let ds = LocalFs { .. }
+///     .decode_json::<Person>()
+///     .filter(|p| p.active)
+///     .map(|p| p.name);
+/// ```
+///
+/// The items keep their provenance, errors from upstream are passed along untouched and are not
+/// counted by `take` and `skip`.
+pub trait DataSourceExt<T: Debug + Send + Sync + 'static>: DataSource<T> + Sized + 'static {
+    fn boxed(self) -> Box<dyn DataSource<T>> {
+        Box::new(self)
+    }
+
+    fn map<O, F>(self, f: F) -> Box<dyn DataSource<O>>
+    where
+        O: Debug + Send + Sync + 'static,
+        F: Fn(T) -> O + Send + Sync + 'static,
+    {
+        Step::boxed("map", self, move |item| Ok(Flow::Item(f(item))))
+    }
+
+    fn filter<F>(self, f: F) -> Box<dyn DataSource<T>>
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        Step::boxed("filter", self, move |item| match f(&item) {
+            true => Ok(Flow::Item(item)),
+            false => Ok(Flow::Skip),
+        })
+    }
+
+    fn filter_map<O, F>(self, f: F) -> Box<dyn DataSource<O>>
+    where
+        O: Debug + Send + Sync + 'static,
+        F: Fn(T) -> Option<O> + Send + Sync + 'static,
+    {
+        Step::boxed("filter_map", self, move |item| match f(item) {
+            Some(item) => Ok(Flow::Item(item)),
+            None => Ok(Flow::Skip),
+        })
+    }
+
+    /// Every item produced from an input item has the provenance of the input item
+    fn flat_map<O, I, F>(self, f: F) -> Box<dyn DataSource<O>>
+    where
+        O: Debug + Send + Sync + 'static,
+        I: IntoIterator<Item = O>,
+        F: Fn(T) -> I + Send + Sync + 'static,
+    {
+        Step::boxed("flat_map", self, move |item| {
+            Ok(Flow::Items(f(item).into_iter().collect()))
+        })
+    }
+
+    /// Groups the items with a [Batcher]
+    fn batch<F>(self, new_batch: F) -> Box<dyn DataSource<Vec<T>>>
+    where
+        F: Fn(&T, &Vec<T>) -> bool + Send + Sync + 'static,
+    {
+        Box::new(Batcher::new(Box::new(self), new_batch))
+    }
+
+    fn inspect<F>(self, f: F) -> Box<dyn DataSource<T>>
+    where
+        F: Fn(&T) + Send + Sync + 'static,
+    {
+        Step::boxed("inspect", self, move |item| {
+            f(&item);
+            Ok(Flow::Item(item))
+        })
+    }
+
+    /// Stops reading the input after `n` items, with `n == 0` the input is not started at all
+    fn take(self, n: usize) -> Box<dyn DataSource<T>> {
+        if n == 0 {
+            let name = format!("take-{}", self.name());
+            return Box::new(StreamDataSource::new(name, futures_util::stream::empty()));
+        }
+        let mut taken = 0_usize;
+        Step::boxed("take", self, move |item| {
+            taken += 1;
+            match taken {
+                t if t < n => Ok(Flow::Item(item)),
+                t if t == n => Ok(Flow::Last(item)),
+                _ => Ok(Flow::Stop),
+            }
+        })
+    }
+
+    fn skip(self, n: usize) -> Box<dyn DataSource<T>> {
+        let mut skipped = 0_usize;
+        Step::boxed("skip", self, move |item| match skipped < n {
+            true => {
+                skipped += 1;
+                Ok(Flow::Skip)
+            }
+            false => Ok(Flow::Item(item)),
+        })
+    }
+
+    /// Deserializes every item as a JSON document, works on any lines of bytes or strings
+    fn decode_json<O>(self) -> Box<dyn DataSource<O>>
+    where
+        T: AsRef<[u8]>,
+        O: DeserializeOwned + Debug + Send + Sync + 'static,
+    {
+        Step::boxed("decode_json", self, |item| {
+            serde_json::from_slice::<O>(item.as_ref())
+                .map(Flow::Item)
+                .map_err(|er| DataStoreError::Deserialize {
+                    message: er.to_string(),
+                    attempted_string: String::from_utf8_lossy(item.as_ref()).to_string(),
+                })
+        })
+    }
+}
+
+impl<T: Debug + Send + Sync + 'static, D: DataSource<T> + 'static> DataSourceExt<T> for D {}
+
+/// What a [Step] does with an item
+enum Flow<O> {
+    Item(O),
+    Items(Vec<O>),
+    Skip,
+    /// send the item and stop reading the input
+    Last(O),
+    Stop,
+}
+
+type StepFn<I, O> = Box<dyn FnMut(I) -> Result<Flow<O>, DataStoreError> + Send + Sync>;
+
+/// Applies a function to every item of the input, the base of the [DataSourceExt] combinators
+struct Step<I, O> {
+    name: &'static str,
+    input: Box<dyn DataSource<I>>,
+    step: StepFn<I, O>,
+}
+
+impl<I, O> Step<I, O>
+where
+    I: Debug + Send + Sync + 'static,
+    O: Debug + Send + Sync + 'static,
+{
+    fn boxed<D, F>(name: &'static str, input: D, step: F) -> Box<dyn DataSource<O>>
+    where
+        D: DataSource<I> + 'static,
+        F: FnMut(I) -> Result<Flow<O>, DataStoreError> + Send + Sync + 'static,
+    {
+        Box::new(Step {
+            name,
+            input: Box::new(input),
+            step: Box::new(step),
+        })
+    }
+}
+
+async fn send<O: Debug + Send>(
+    tx: &Sender<Result<DataSourceMessage<O>, DataStoreError>>,
+    name: &str,
+    source: &Provenance,
+    item: O,
+) -> Result<(), DataStoreError> {
+    tx.send(Ok(DataSourceMessage::new(source, item)))
+        .await
+        .map_err(|e| DataStoreError::send_error(name, source.to_string(), e))
+}
+
+impl<I, O> DataSource<O> for Step<I, O>
+where
+    I: Debug + Send + Sync + 'static,
+    O: Debug + Send + Sync + 'static,
+{
+    fn name(&self) -> String {
+        format!("{}-{}", self.name, self.input.name())
+    }
+
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<O>, DataStoreError> {
+        use tokio::sync::mpsc::channel;
+        let (tx, rx) = channel(channels::buffer_size());
+        let name = self.name();
+        let (mut input_rx, input_jh) = self.input.start_stream()?;
+        let mut step = self.step;
+        let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
+            let mut lines_scanned = 0_usize;
+            loop {
+                let (source, flow) = match input_rx.recv().await {
+                    Some(Ok(DataSourceMessage::Data { source, content })) => {
+                        lines_scanned += 1;
+                        match step(content) {
+                            Ok(flow) => (source, flow),
+                            Err(er) => {
+                                tx.send(Err(er.at(&source)))
+                                    .await
+                                    .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+                                continue;
+                            }
+                        }
+                    }
+                    Some(Err(er)) => {
+                        tx.send(Err(er))
+                            .await
+                            .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+                        continue;
+                    }
+                    None => break,
+                };
+                match flow {
+                    Flow::Item(item) => send(&tx, &name, &source, item).await?,
+                    Flow::Items(items) => {
+                        for item in items {
+                            send(&tx, &name, &source, item).await?;
+                        }
+                    }
+                    Flow::Skip => {}
+                    Flow::Last(item) => {
+                        send(&tx, &name, &source, item).await?;
+                        // the input stops once nobody reads it, so its result is not awaited
+                        return Ok(DataSourceStats::new(lines_scanned));
+                    }
+                    Flow::Stop => return Ok(DataSourceStats::new(lines_scanned)),
+                }
+            }
+            input_jh.await??;
+            Ok(DataSourceStats::new(lines_scanned))
+        });
+        Ok((rx, jh))
+    }
+}
@@ -0,0 +1,461 @@
+use crate::datastore::error::*;
+use crate::datastore::*;
+use crate::joins::KeyFn;
+use crate::spill::{SpillFiles, SpillReader, SpillWriter};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use tokio::sync::mpsc::Sender;
+
+/// Folds the items of a group into an accumulator.  Tuples of aggregators are aggregators
+/// themselves, so several can be computed at once:
+///
+/// ```ignore
+/// let aggregator = (Count, Sum(|o: &Order| o.amount), Max(|o: &Order| o.amount));
+/// ```
+pub trait Aggregator<T>: Send + Sync {
+    type Acc;
+    fn init(&self) -> Self::Acc;
+    fn fold(&self, acc: &mut Self::Acc, item: &T);
+}
+
+/// Number of items in the group
+pub struct Count;
+
+impl<T> Aggregator<T> for Count {
+    type Acc = usize;
+
+    fn init(&self) -> usize {
+        0
+    }
+
+    fn fold(&self, acc: &mut usize, _: &T) {
+        *acc += 1;
+    }
+}
+
+pub struct Sum<F>(pub F);
+
+impl<T, V, F> Aggregator<T> for Sum<F>
+where
+    V: Default + std::ops::AddAssign,
+    F: Fn(&T) -> V + Send + Sync,
+{
+    type Acc = V;
+
+    fn init(&self) -> V {
+        V::default()
+    }
+
+    fn fold(&self, acc: &mut V, item: &T) {
+        *acc += (self.0)(item);
+    }
+}
+
+pub struct Min<F>(pub F);
+
+impl<T, V, F> Aggregator<T> for Min<F>
+where
+    V: PartialOrd,
+    F: Fn(&T) -> V + Send + Sync,
+{
+    type Acc = Option<V>;
+
+    fn init(&self) -> Option<V> {
+        None
+    }
+
+    fn fold(&self, acc: &mut Option<V>, item: &T) {
+        let value = (self.0)(item);
+        match acc {
+            Some(min) if *min <= value => {}
+            _ => *acc = Some(value),
+        }
+    }
+}
+
+pub struct Max<F>(pub F);
+
+impl<T, V, F> Aggregator<T> for Max<F>
+where
+    V: PartialOrd,
+    F: Fn(&T) -> V + Send + Sync,
+{
+    type Acc = Option<V>;
+
+    fn init(&self) -> Option<V> {
+        None
+    }
+
+    fn fold(&self, acc: &mut Option<V>, item: &T) {
+        let value = (self.0)(item);
+        match acc {
+            Some(max) if *max >= value => {}
+            _ => *acc = Some(value),
+        }
+    }
+}
+
+/// Collects a value of every item of the group, in input order
+pub struct Collect<F>(pub F);
+
+impl<T, V, F> Aggregator<T> for Collect<F>
+where
+    F: Fn(&T) -> V + Send + Sync,
+{
+    type Acc = Vec<V>;
+
+    fn init(&self) -> Vec<V> {
+        Vec::new()
+    }
+
+    fn fold(&self, acc: &mut Vec<V>, item: &T) {
+        acc.push((self.0)(item));
+    }
+}
+
+/// A user defined aggregation, every group starts with a clone of `init`
+pub struct Fold<V, F> {
+    pub init: V,
+    pub fold: F,
+}
+
+impl<V, F> Fold<V, F> {
+    pub fn new(init: V, fold: F) -> Self {
+        Fold { init, fold }
+    }
+}
+
+impl<T, V, F> Aggregator<T> for Fold<V, F>
+where
+    V: Clone + Send + Sync,
+    F: Fn(&mut V, &T) + Send + Sync,
+{
+    type Acc = V;
+
+    fn init(&self) -> V {
+        self.init.clone()
+    }
+
+    fn fold(&self, acc: &mut V, item: &T) {
+        (self.fold)(acc, item)
+    }
+}
+
+macro_rules! tuple_aggregator {
+    ($($name:ident $idx:tt),+) => {
+        impl<T, $($name: Aggregator<T>),+> Aggregator<T> for ($($name,)+) {
+            type Acc = ($($name::Acc,)+);
+
+            fn init(&self) -> Self::Acc {
+                ($(self.$idx.init(),)+)
+            }
+
+            fn fold(&self, acc: &mut Self::Acc, item: &T) {
+                $(self.$idx.fold(&mut acc.$idx, item);)+
+            }
+        }
+    };
+}
+
+tuple_aggregator!(A 0);
+tuple_aggregator!(A 0, B 1);
+tuple_aggregator!(A 0, B 1, C 2);
+tuple_aggregator!(A 0, B 1, C 2, D 3);
+tuple_aggregator!(A 0, B 1, C 2, D 3, E 4);
+tuple_aggregator!(A 0, B 1, C 2, D 3, E 4, F 5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupByMode {
+    /// Groups are kept in a hash map, in no particular order.  Items of new groups beyond
+    /// `max_groups` are partitioned by key into files, and the partitions are grouped after
+    /// the groups in memory
+    Hash { max_groups: usize },
+    /// The input is sorted by key, so only one group is kept in memory and the groups are sent
+    /// in key order.  Keys going backwards stop the stream with an error
+    Sorted,
+}
+
+/// Number of files the items are partitioned into when spilling
+const SPILL_PARTITIONS: u64 = 16;
+
+/// Aggregates the items with the same key, sending a `(key, accumulator)` for every group.  The
+/// groups have the provenance of their first item
+pub struct GroupBy<T, K, A> {
+    pub input: Box<dyn DataSource<T>>,
+    pub key: KeyFn<T, K>,
+    pub aggregator: A,
+    pub mode: GroupByMode,
+    /// Where the partitions are written, the temp dir by default
+    pub spill_dir: Option<PathBuf>,
+}
+
+impl<T, K, A> GroupBy<T, K, A> {
+    /// Groups with a hash map
+    pub fn new<F>(input: Box<dyn DataSource<T>>, key: F, aggregator: A) -> Self
+    where
+        F: Fn(&T) -> K + Send + Sync + 'static,
+    {
+        GroupBy {
+            input,
+            key: Box::new(key),
+            aggregator,
+            mode: GroupByMode::Hash {
+                max_groups: 100_000,
+            },
+            spill_dir: None,
+        }
+    }
+
+    /// For input which is sorted by key
+    pub fn sorted(self) -> Self {
+        GroupBy {
+            mode: GroupByMode::Sorted,
+            ..self
+        }
+    }
+
+    pub fn with_max_groups(self, max_groups: usize) -> Self {
+        GroupBy {
+            mode: GroupByMode::Hash {
+                max_groups: max_groups.max(1),
+            },
+            ..self
+        }
+    }
+
+    pub fn with_spill_dir<P: Into<PathBuf>>(self, spill_dir: P) -> Self {
+        GroupBy {
+            spill_dir: Some(spill_dir.into()),
+            ..self
+        }
+    }
+}
+
+impl<T, K, A> DataSource<(K, A::Acc)> for GroupBy<T, K, A>
+where
+    T: Serialize + DeserializeOwned + Debug + Send + Sync + 'static,
+    K: Hash + Ord + Debug + Send + Sync + 'static,
+    A: Aggregator<T> + 'static,
+    A::Acc: Debug + Send + Sync + 'static,
+{
+    fn name(&self) -> String {
+        format!("GroupBy-{}", self.input.name())
+    }
+
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<(K, A::Acc)>, DataStoreError> {
+        use tokio::sync::mpsc::channel;
+        use tokio::task::JoinHandle;
+        let (tx, rx) = channel(channels::buffer_size());
+        let name = self.name();
+        let (input_rx, input_jh) = self.input.start_stream()?;
+        let group = Grouping {
+            name,
+            key: self.key,
+            aggregator: self.aggregator,
+            tx,
+        };
+        let mode = self.mode;
+        let spill_dir = self.spill_dir.unwrap_or_else(std::env::temp_dir);
+        let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
+            let lines_scanned = match mode {
+                GroupByMode::Hash { max_groups } => {
+                    let mut files = SpillFiles::new(spill_dir, "etl-group-by");
+                    let result = group.hash(input_rx, max_groups.max(1), &mut files).await;
+                    files.remove().await;
+                    result?
+                }
+                GroupByMode::Sorted => group.sorted(input_rx).await?,
+            };
+            input_jh.await??;
+            Ok(DataSourceStats::new(lines_scanned))
+        });
+        Ok((rx, jh))
+    }
+}
+
+struct Grouping<T, K, A: Aggregator<T>>
+where
+    K: Send,
+    A::Acc: Send,
+{
+    name: String,
+    key: KeyFn<T, K>,
+    aggregator: A,
+    tx: GroupsTx<K, A::Acc>,
+}
+
+type GroupsTx<K, Acc> = Sender<Result<DataSourceMessage<(K, Acc)>, DataStoreError>>;
+
+impl<T, K, A> Grouping<T, K, A>
+where
+    T: Serialize + DeserializeOwned + Debug + Send + Sync + 'static,
+    K: Hash + Ord + Debug + Send + Sync + 'static,
+    A: Aggregator<T> + 'static,
+    A::Acc: Debug + Send + Sync + 'static,
+{
+    async fn hash(
+        &self,
+        mut input_rx: DataSourceRx<T>,
+        max_groups: usize,
+        files: &mut SpillFiles,
+    ) -> Result<usize, DataStoreError> {
+        let mut lines_scanned = 0_usize;
+        let mut groups = HashGroups::new(0, max_groups);
+        while let Some(message) = input_rx.recv().await {
+            match message {
+                Ok(DataSourceMessage::Data { source, content }) => {
+                    lines_scanned += 1;
+                    groups.add(self, source, content, files).await?;
+                }
+                Err(er) => self.send(Err(er)).await?,
+            }
+        }
+        let mut partitions = groups.finish(self).await?;
+        while let Some((level, partition)) = partitions.pop() {
+            let mut groups = HashGroups::new(level + 1, max_groups);
+            let mut partition = SpillReader::open(partition).await?;
+            while let Some((source, item)) = partition.next::<T>().await? {
+                groups.add(self, source, item, files).await?;
+            }
+            partitions.extend(groups.finish(self).await?);
+        }
+        Ok(lines_scanned)
+    }
+
+    async fn sorted(&self, mut input_rx: DataSourceRx<T>) -> Result<usize, DataStoreError> {
+        let mut lines_scanned = 0_usize;
+        let mut group: Option<(K, Provenance, A::Acc)> = None;
+        while let Some(message) = input_rx.recv().await {
+            let (source, item) = match message {
+                Ok(DataSourceMessage::Data { source, content }) => (source, content),
+                Err(er) => {
+                    self.send(Err(er)).await?;
+                    continue;
+                }
+            };
+            lines_scanned += 1;
+            let key = (self.key)(&item);
+            match group.as_mut() {
+                Some((group_key, _, acc)) if *group_key == key => {
+                    self.aggregator.fold(acc, &item);
+                    continue;
+                }
+                Some((group_key, _, _)) if key < *group_key => {
+                    let er = DataStoreError::Generic(format!(
+                        "{} input is not sorted, key {:?} came after {:?}",
+                        self.name, key, group_key
+                    ))
+                    .at(source);
+                    let _ = self.tx.send(Err(er.clone())).await;
+                    return Err(er);
+                }
+                _ => {}
+            }
+            let mut acc = self.aggregator.init();
+            self.aggregator.fold(&mut acc, &item);
+            if let Some((group_key, group_source, acc)) = group.replace((key, source, acc)) {
+                self.send(Ok(DataSourceMessage::new(group_source, (group_key, acc))))
+                    .await?;
+            }
+        }
+        if let Some((group_key, group_source, acc)) = group {
+            self.send(Ok(DataSourceMessage::new(group_source, (group_key, acc))))
+                .await?;
+        }
+        Ok(lines_scanned)
+    }
+
+    async fn send(
+        &self,
+        message: Result<DataSourceMessage<(K, A::Acc)>, DataStoreError>,
+    ) -> Result<(), DataStoreError> {
+        self.tx
+            .send(message)
+            .await
+            .map_err(|e| DataStoreError::send_error(&self.name, "", e))
+    }
+}
+
+/// The groups of one pass over the items.  Items of new groups which do not fit are written
+/// to partitions, which are grouped in a later pass
+struct HashGroups<K, Acc> {
+    level: usize,
+    max_groups: usize,
+    groups: HashMap<K, (Provenance, Acc)>,
+    partitions: Vec<SpillWriter>,
+}
+
+impl<K: Hash + Eq, Acc> HashGroups<K, Acc> {
+    fn new(level: usize, max_groups: usize) -> Self {
+        HashGroups {
+            level,
+            max_groups,
+            groups: HashMap::new(),
+            partitions: Vec::new(),
+        }
+    }
+
+    async fn add<T, A>(
+        &mut self,
+        grouping: &Grouping<T, K, A>,
+        source: Provenance,
+        item: T,
+        files: &mut SpillFiles,
+    ) -> Result<(), DataStoreError>
+    where
+        T: Serialize,
+        K: Send,
+        A: Aggregator<T, Acc = Acc>,
+        Acc: Send,
+    {
+        let key = (grouping.key)(&item);
+        if let Some((_, acc)) = self.groups.get_mut(&key) {
+            grouping.aggregator.fold(acc, &item);
+        } else if self.groups.len() < self.max_groups {
+            let mut acc = grouping.aggregator.init();
+            grouping.aggregator.fold(&mut acc, &item);
+            self.groups.insert(key, (source, acc));
+        } else {
+            if self.partitions.is_empty() {
+                for _ in 0..SPILL_PARTITIONS {
+                    self.partitions.push(files.writer().await?);
+                }
+            }
+            // every pass partitions with a different hash, so the groups end up spread out
+            let mut hasher = DefaultHasher::new();
+            (self.level, &key).hash(&mut hasher);
+            let partition = (hasher.finish() % SPILL_PARTITIONS) as usize;
+            self.partitions[partition].write(&source, &item).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends the groups and returns the partitions to group next
+    async fn finish<T, A>(
+        self,
+        grouping: &Grouping<T, K, A>,
+    ) -> Result<Vec<(usize, PathBuf)>, DataStoreError>
+    where
+        K: Send,
+        A: Aggregator<T, Acc = Acc>,
+        Acc: Send,
+    {
+        for (key, (source, acc)) in self.groups {
+            grouping
+                .tx
+                .send(Ok(DataSourceMessage::new(source, (key, acc))))
+                .await
+                .map_err(|e| DataStoreError::send_error(&grouping.name, "", e))?;
+        }
+        let mut partitions = Vec::with_capacity(self.partitions.len());
+        for partition in self.partitions {
+            partitions.push((self.level, partition.finish().await?));
+        }
+        Ok(partitions)
+    }
+}
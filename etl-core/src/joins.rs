@@ -4,7 +4,11 @@ use futures_core::future::BoxFuture;
 use serde::de::DeserializeOwned;
 use std::collections::HashMap;
 use std::fmt::Debug;
-use tokio::sync::mpsc::Sender;
+
+pub mod hash_join;
+pub mod merge_join;
+pub use hash_join::*;
+pub use merge_join::*;
 
 pub type BoxedDataSourceResult<T> = anyhow::Result<Box<dyn DataSource<T>>>;
 pub type CreateDataSourceFn<'a, R> =
@@ -24,9 +28,9 @@ pub struct LeftJoin<'a, L, R> {
 }
 
 async fn fill_left_stack<L, R>(
-    v: &mut Vec<L>,
-    left_rx: &mut DataSourceRx<L>,
-    fw_tx: &Sender<Result<DataSourceMessage<(L, Option<R>)>, DataStoreError>>,
+    v: &mut Vec<(Provenance, L)>,
+    left_rx: &mut DataSourceChunkRx<L>,
+    fw_tx: &mut DataSourceChunkTx<(L, Option<R>)>,
 ) -> Result<usize, DataStoreError>
 where
     L: DeserializeOwned + Send + Debug,
@@ -35,9 +39,9 @@ where
     let mut num_read = 0;
     while v.capacity() > v.len() {
         match left_rx.recv().await {
-            Some(Ok(DataSourceMessage::Data { source: _, content })) => {
+            Some(Ok(DataSourceMessage::Data { source, content })) => {
                 num_read += 1;
-                v.push(content);
+                v.push((source, content));
             }
             Some(Err(er)) => {
                 fw_tx
@@ -56,8 +60,8 @@ where
 }
 
 async fn forward_matches<L, R>(
-    fw_tx: &Sender<Result<DataSourceMessage<(L, Option<R>)>, DataStoreError>>,
-    v: &Vec<L>,
+    fw_tx: &mut DataSourceChunkTx<(L, Option<R>)>,
+    v: &[(Provenance, L)],
     create_right_ds: &CreateDataSourceFn<'_, R>,
     is_match: &Box<dyn Fn(&L, &R) -> bool + Send + Sync>,
 ) -> Result<usize, DataStoreError>
@@ -75,15 +79,16 @@ where
             return Err(e.into());
         }
         Ok(right_ds) => {
-            let (mut right_rx, _) = right_ds.start_stream()?;
+            let (mut right_rx, _) = right_ds.start_chunk_stream()?;
             loop {
                 match right_rx.recv().await {
                     Some(Ok(DataSourceMessage::Data {
-                        source,
+                        source: _,
                         content: _c,
                     })) => {
                         num_read += 1;
-                        for (idx, elem) in v.iter().enumerate() {
+                        // the joined items keep the provenance of the left side
+                        for (idx, (source, elem)) in v.iter().enumerate() {
                             if is_match(elem, &_c) {
                                 let r = left_match_counts
                                     .get_mut(&idx)
@@ -91,14 +96,14 @@ where
                                 *r += 1;
                                 fw_tx
                                     .send(Ok(DataSourceMessage::new(
-                                        "LeftJoin::Matches",
+                                        source,
                                         (elem.clone(), Some(_c.clone())),
                                     )))
                                     .await
                                     .map_err(|e| {
                                         DataStoreError::send_error(
                                             "Join LeftJoin",
-                                            &source,
+                                            source.to_string(),
                                             e,
                                         )
                                     })?;
@@ -116,12 +121,12 @@ where
                 }
             }
             // send the non-matches
-            for (idx, elem) in v.iter().enumerate() {
+            for (idx, (source, elem)) in v.iter().enumerate() {
                 if let Some(count) = left_match_counts.get(&idx) {
                     if *count == 0 {
                         fw_tx
                             .send(Ok(DataSourceMessage::new(
-                                "LeftJoin::NonMatches",
+                                source,
                                 (elem.clone(), None),
                             )))
                             .await
@@ -136,50 +141,72 @@ where
     Ok(num_read)
 }
 
-impl<L, R> DataSource<(L, Option<R>)> for LeftJoin<'static, L, R>
+impl<L, R> LeftJoin<'static, L, R>
 where
     R: DeserializeOwned + Debug + Clone + 'static + Send + Sync,
     L: DeserializeOwned + Debug + Clone + 'static + Send + Sync,
 {
-    fn name(&self) -> String {
-        format!("LeftJoin-{}", self.left_ds.name())
-    }
-
-    fn start_stream(
-        self: Box<Self>,
-    ) -> Result<DataSourceTask<(L, Option<R>)>, DataStoreError> {
-        use tokio::sync::mpsc::channel;
+    /// The joined items of each batch of the left side are flushed once the batch is done
+    fn start(
+        self,
+        mut tx: DataSourceChunkTx<(L, Option<R>)>,
+    ) -> Result<DataSourceJoinHandle, DataStoreError> {
         use tokio::task::JoinHandle;
-        let (tx, rx) = channel(1);
-        let (mut left_rx, _) = self.left_ds.start_stream()?;
+        let (mut left_rx, _) = self.left_ds.start_chunk_stream()?;
 
         let max_left_len = self.left_buf_len;
         let matching_func = self.is_match;
         let create_right_ds = self.create_right_ds;
-        let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> =
-            tokio::spawn(async move {
-                let mut lines_scanned = 0;
-                let mut left_stack: Vec<L> = Vec::with_capacity(max_left_len);
-                loop {
-                    match fill_left_stack(&mut left_stack, &mut left_rx, &tx).await? {
-                        num_read if num_read == 0 => {
-                            break;
-                        }
-                        num_read => {
-                            forward_matches(
-                                &tx,
-                                &left_stack,
-                                &create_right_ds,
-                                &matching_func,
-                            )
+        // the right side is started from the task, in the scope of the caller
+        let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = channels::spawn(async move {
+            let mut lines_scanned = 0;
+            let mut left_stack: Vec<(Provenance, L)> = Vec::with_capacity(max_left_len);
+            loop {
+                match fill_left_stack(&mut left_stack, &mut left_rx, &mut tx).await? {
+                    num_read if num_read == 0 => {
+                        break;
+                    }
+                    num_read => {
+                        forward_matches(&mut tx, &left_stack, &create_right_ds, &matching_func)
                             .await?;
-                            lines_scanned += num_read;
-                            left_stack.clear();
-                        }
+                        tx.flush()
+                            .await
+                            .map_err(|e| DataStoreError::send_error("LeftJoin", "", e))?;
+                        lines_scanned += num_read;
+                        left_stack.clear();
                     }
                 }
-                Ok(DataSourceStats { lines_scanned })
-            });
+            }
+            tx.flush()
+                .await
+                .map_err(|e| DataStoreError::send_error("LeftJoin", "", e))?;
+            Ok(DataSourceStats::new(lines_scanned))
+        });
+        Ok(jh)
+    }
+}
+
+impl<L, R> DataSource<(L, Option<R>)> for LeftJoin<'static, L, R>
+where
+    R: DeserializeOwned + Debug + Clone + 'static + Send + Sync,
+    L: DeserializeOwned + Debug + Clone + 'static + Send + Sync,
+{
+    fn name(&self) -> String {
+        format!("LeftJoin-{}", self.left_ds.name())
+    }
+
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<(L, Option<R>)>, DataStoreError> {
+        use tokio::sync::mpsc::channel;
+        let (tx, rx) = channel(channels::buffer_size());
+        let jh = (*self).start(tx.into())?;
+        Ok((rx, jh))
+    }
+
+    fn start_chunk_stream(
+        self: Box<Self>,
+    ) -> Result<DataSourceChunkTask<(L, Option<R>)>, DataStoreError> {
+        let (tx, rx) = channels::chunk_channel();
+        let jh = (*self).start(tx)?;
         Ok((rx, jh))
     }
 }
@@ -0,0 +1,398 @@
+use crate::datastore::error::*;
+use crate::datastore::*;
+use crate::spill::{SpillDir, SpillReader, SpillWriter};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use tokio::sync::mpsc::Sender;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JoinType {
+    Inner,
+    /// every item of the left side, with `None` when nothing on the right matched
+    Left,
+    /// every item of the right side, with `None` when nothing on the left matched
+    Right,
+    FullOuter,
+}
+
+impl JoinType {
+    pub(crate) fn keeps_left(&self) -> bool {
+        matches!(self, JoinType::Left | JoinType::FullOuter)
+    }
+
+    pub(crate) fn keeps_right(&self) -> bool {
+        matches!(self, JoinType::Right | JoinType::FullOuter)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JoinSide {
+    Left,
+    Right,
+}
+
+pub type KeyFn<T, K> = Box<dyn Fn(&T) -> K + Send + Sync>;
+/// Either side is `None` when the item of the other side had no match
+pub type Joined<L, R> = (Option<L>, Option<R>);
+
+/// Joins two DataSources on equal keys.  The build side is read into an index in memory, then
+/// the other side is streamed through it, so the build side should be the smaller one.  When the
+/// build side has more than `memory_budget` items, both sides are partitioned by key into files
+/// and the partitions are joined one at a time.
+/// The joined items have the provenance of the left item, unless there is none
+pub struct HashJoin<L, R, K> {
+    pub left_ds: Box<dyn DataSource<L>>,
+    pub right_ds: Box<dyn DataSource<R>>,
+    pub left_key: KeyFn<L, K>,
+    pub right_key: KeyFn<R, K>,
+    pub join_type: JoinType,
+    pub build_side: JoinSide,
+    /// Number of build side items to hold in memory before spilling to disk
+    pub memory_budget: usize,
+    /// Number of files each side is partitioned into when spilling.  A partition of the build
+    /// side is loaded in memory as a whole
+    pub spill_partitions: usize,
+    /// Where the partitions are written, the temp dir by default
+    pub spill_dir: Option<PathBuf>,
+}
+
+impl<L, R, K> HashJoin<L, R, K> {
+    /// An inner join which builds the index from the right side
+    pub fn new<F, G>(
+        left_ds: Box<dyn DataSource<L>>,
+        right_ds: Box<dyn DataSource<R>>,
+        left_key: F,
+        right_key: G,
+    ) -> Self
+    where
+        F: Fn(&L) -> K + Send + Sync + 'static,
+        G: Fn(&R) -> K + Send + Sync + 'static,
+    {
+        HashJoin {
+            left_ds,
+            right_ds,
+            left_key: Box::new(left_key),
+            right_key: Box::new(right_key),
+            join_type: JoinType::Inner,
+            build_side: JoinSide::Right,
+            memory_budget: 100_000,
+            spill_partitions: 16,
+            spill_dir: None,
+        }
+    }
+
+    pub fn with_join_type(self, join_type: JoinType) -> Self {
+        HashJoin { join_type, ..self }
+    }
+
+    pub fn with_build_side(self, build_side: JoinSide) -> Self {
+        HashJoin { build_side, ..self }
+    }
+
+    pub fn with_memory_budget(self, memory_budget: usize) -> Self {
+        HashJoin {
+            memory_budget: memory_budget.max(1),
+            ..self
+        }
+    }
+
+    pub fn with_spill_dir<P: Into<PathBuf>>(self, spill_dir: P) -> Self {
+        HashJoin {
+            spill_dir: Some(spill_dir.into()),
+            ..self
+        }
+    }
+}
+
+impl<L, R, K> DataSource<Joined<L, R>> for HashJoin<L, R, K>
+where
+    L: Serialize + DeserializeOwned + Debug + Clone + Send + Sync + 'static,
+    R: Serialize + DeserializeOwned + Debug + Clone + Send + Sync + 'static,
+    K: Hash + Eq + Send + Sync + 'static,
+{
+    fn name(&self) -> String {
+        format!("HashJoin-{}-{}", self.left_ds.name(), self.right_ds.name())
+    }
+
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<Joined<L, R>>, DataStoreError> {
+        use tokio::sync::mpsc::channel;
+        use tokio::task::JoinHandle;
+        let (tx, rx) = channel(channels::buffer_size());
+        let name = self.name();
+        let (left_rx, left_jh) = self.left_ds.start_stream()?;
+        let (right_rx, right_jh) = self.right_ds.start_stream()?;
+        let spill = SpillConfig {
+            memory_budget: self.memory_budget.max(1),
+            partitions: self.spill_partitions.max(1),
+            dir: self.spill_dir.unwrap_or_else(std::env::temp_dir),
+        };
+        let (join_type, build_side) = (self.join_type, self.build_side);
+        let (left_key, right_key) = (self.left_key, self.right_key);
+        let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
+            let lines_scanned = match build_side {
+                JoinSide::Left => {
+                    let join = Join {
+                        name,
+                        build_key: left_key,
+                        probe_key: right_key,
+                        keep_build: join_type.keeps_left(),
+                        keep_probe: join_type.keeps_right(),
+                        build_is_left: true,
+                        pair: |left, right| (left, right),
+                        tx,
+                    };
+                    join.run(left_rx, right_rx, &spill).await?
+                }
+                JoinSide::Right => {
+                    let join = Join {
+                        name,
+                        build_key: right_key,
+                        probe_key: left_key,
+                        keep_build: join_type.keeps_right(),
+                        keep_probe: join_type.keeps_left(),
+                        build_is_left: false,
+                        pair: |right, left| (left, right),
+                        tx,
+                    };
+                    join.run(right_rx, left_rx, &spill).await?
+                }
+            };
+            left_jh.await??;
+            right_jh.await??;
+            Ok(DataSourceStats::new(lines_scanned))
+        });
+        Ok((rx, jh))
+    }
+}
+
+struct SpillConfig {
+    memory_budget: usize,
+    partitions: usize,
+    dir: PathBuf,
+}
+
+/// An item of the build side, remembering if anything on the probe side matched it
+struct BuildItem<B> {
+    source: Provenance,
+    item: B,
+    matched: bool,
+}
+
+type Index<K, B> = HashMap<K, Vec<BuildItem<B>>>;
+
+/// The join seen from the build side, so both build sides share the same code
+struct Join<B, P, K, L: Send, R: Send> {
+    name: String,
+    build_key: KeyFn<B, K>,
+    probe_key: KeyFn<P, K>,
+    keep_build: bool,
+    keep_probe: bool,
+    build_is_left: bool,
+    /// puts the items back in left and right order
+    pair: fn(Option<B>, Option<P>) -> Joined<L, R>,
+    tx: Sender<Result<DataSourceMessage<Joined<L, R>>, DataStoreError>>,
+}
+
+impl<B, P, K, L, R> Join<B, P, K, L, R>
+where
+    B: Serialize + DeserializeOwned + Debug + Clone + Send + Sync + 'static,
+    P: Serialize + DeserializeOwned + Debug + Clone + Send + Sync + 'static,
+    K: Hash + Eq + Send + Sync + 'static,
+    L: Debug + Send + Sync + 'static,
+    R: Debug + Send + Sync + 'static,
+{
+    async fn run(
+        &self,
+        mut build_rx: DataSourceRx<B>,
+        mut probe_rx: DataSourceRx<P>,
+        config: &SpillConfig,
+    ) -> Result<usize, DataStoreError> {
+        let mut lines_scanned = 0_usize;
+        let mut index: Index<K, B> = HashMap::new();
+        let mut index_len = 0_usize;
+        let mut spill: Option<Spill> = None;
+        while let Some(message) = build_rx.recv().await {
+            let (source, item) = match message {
+                Ok(DataSourceMessage::Data { source, content }) => (source, content),
+                Err(er) => {
+                    self.send(Err(er)).await?;
+                    continue;
+                }
+            };
+            lines_scanned += 1;
+            let key = (self.build_key)(&item);
+            if let Some(spill) = spill.as_mut() {
+                let partition = spill.partition(&key);
+                spill.build[partition].write(&source, &item).await?;
+                continue;
+            }
+            index.entry(key).or_default().push(BuildItem {
+                source,
+                item,
+                matched: false,
+            });
+            index_len += 1;
+            if index_len > config.memory_budget {
+                let mut new_spill = Spill::create(config).await?;
+                for (key, items) in index.drain() {
+                    let partition = new_spill.partition(&key);
+                    for BuildItem { source, item, .. } in items {
+                        new_spill.build[partition].write(&source, &item).await?;
+                    }
+                }
+                spill = Some(new_spill);
+            }
+        }
+        match spill {
+            None => {
+                while let Some(message) = probe_rx.recv().await {
+                    match message {
+                        Ok(DataSourceMessage::Data { source, content }) => {
+                            lines_scanned += 1;
+                            self.probe(&mut index, source, content).await?;
+                        }
+                        Err(er) => self.send(Err(er)).await?,
+                    }
+                }
+                self.send_unmatched(index).await?;
+            }
+            Some(mut spill) => {
+                while let Some(message) = probe_rx.recv().await {
+                    match message {
+                        Ok(DataSourceMessage::Data { source, content }) => {
+                            lines_scanned += 1;
+                            let partition = spill.partition(&(self.probe_key)(&content));
+                            spill.probe[partition].write(&source, &content).await?;
+                        }
+                        Err(er) => self.send(Err(er)).await?,
+                    }
+                }
+                let Spill { dir, build, probe } = spill;
+                let result = self.join_partitions(build, probe).await;
+                dir.remove().await;
+                result?;
+            }
+        }
+        Ok(lines_scanned)
+    }
+
+    async fn join_partitions(
+        &self,
+        build: Vec<SpillWriter>,
+        probe: Vec<SpillWriter>,
+    ) -> Result<(), DataStoreError> {
+        for (build, probe) in build.into_iter().zip(probe) {
+            let mut index: Index<K, B> = HashMap::new();
+            let mut build = SpillReader::open(build.finish().await?).await?;
+            while let Some((source, item)) = build.next::<B>().await? {
+                index
+                    .entry((self.build_key)(&item))
+                    .or_default()
+                    .push(BuildItem {
+                        source,
+                        item,
+                        matched: false,
+                    });
+            }
+            let mut probe = SpillReader::open(probe.finish().await?).await?;
+            while let Some((source, item)) = probe.next::<P>().await? {
+                self.probe(&mut index, source, item).await?;
+            }
+            self.send_unmatched(index).await?;
+        }
+        Ok(())
+    }
+
+    async fn probe(
+        &self,
+        index: &mut Index<K, B>,
+        source: Provenance,
+        item: P,
+    ) -> Result<(), DataStoreError> {
+        match index.get_mut(&(self.probe_key)(&item)) {
+            Some(build_items) => {
+                for build_item in build_items.iter_mut() {
+                    build_item.matched = true;
+                    let source = match self.build_is_left {
+                        true => &build_item.source,
+                        false => &source,
+                    };
+                    let joined = (self.pair)(Some(build_item.item.clone()), Some(item.clone()));
+                    self.send(Ok(DataSourceMessage::new(source, joined)))
+                        .await?;
+                }
+            }
+            None if self.keep_probe => {
+                self.send(Ok(DataSourceMessage::new(
+                    source,
+                    (self.pair)(None, Some(item)),
+                )))
+                .await?;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    async fn send_unmatched(&self, index: Index<K, B>) -> Result<(), DataStoreError> {
+        if !self.keep_build {
+            return Ok(());
+        }
+        for build_item in index.into_values().flatten() {
+            if !build_item.matched {
+                let joined = (self.pair)(Some(build_item.item), None);
+                self.send(Ok(DataSourceMessage::new(build_item.source, joined)))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        message: Result<DataSourceMessage<Joined<L, R>>, DataStoreError>,
+    ) -> Result<(), DataStoreError> {
+        self.tx
+            .send(message)
+            .await
+            .map_err(|e| DataStoreError::send_error(&self.name, "", e))
+    }
+}
+
+/// Both sides partitioned by the hash of their keys, each partition is a file.  The files are
+/// removed with `dir` when the join stops on an error
+struct Spill {
+    dir: SpillDir,
+    build: Vec<SpillWriter>,
+    probe: Vec<SpillWriter>,
+}
+
+impl Spill {
+    async fn create(config: &SpillConfig) -> Result<Self, DataStoreError> {
+        let dir = SpillDir::create(&config.dir, "etl-hash-join").await?;
+        let mut build = Vec::with_capacity(config.partitions);
+        let mut probe = Vec::with_capacity(config.partitions);
+        for partition in 0..config.partitions {
+            build.push(
+                dir.writer(&format!("build-{}.jsonl.zst", partition))
+                    .await?,
+            );
+            probe.push(
+                dir.writer(&format!("probe-{}.jsonl.zst", partition))
+                    .await?,
+            );
+        }
+        Ok(Spill { dir, build, probe })
+    }
+
+    fn partition<K: Hash>(&self, key: &K) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.build.len() as u64) as usize
+    }
+}
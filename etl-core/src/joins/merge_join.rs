@@ -0,0 +1,217 @@
+use super::hash_join::{JoinType, Joined, KeyFn};
+use crate::datastore::error::*;
+use crate::datastore::*;
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use tokio::sync::mpsc::Sender;
+
+/// Joins two DataSources which are both sorted by key, reading each of them once.  Items with
+/// the same key are joined with every item of the other side which has that key.  Keys going
+/// backwards on either side stop the join with an error.
+/// The joined items have the provenance of the left item, unless there is none
+pub struct MergeJoin<L, R, K> {
+    pub left_ds: Box<dyn DataSource<L>>,
+    pub right_ds: Box<dyn DataSource<R>>,
+    pub left_key: KeyFn<L, K>,
+    pub right_key: KeyFn<R, K>,
+    pub join_type: JoinType,
+}
+
+impl<L, R, K> MergeJoin<L, R, K> {
+    /// An inner join
+    pub fn new<F, G>(
+        left_ds: Box<dyn DataSource<L>>,
+        right_ds: Box<dyn DataSource<R>>,
+        left_key: F,
+        right_key: G,
+    ) -> Self
+    where
+        F: Fn(&L) -> K + Send + Sync + 'static,
+        G: Fn(&R) -> K + Send + Sync + 'static,
+    {
+        MergeJoin {
+            left_ds,
+            right_ds,
+            left_key: Box::new(left_key),
+            right_key: Box::new(right_key),
+            join_type: JoinType::Inner,
+        }
+    }
+
+    pub fn with_join_type(self, join_type: JoinType) -> Self {
+        MergeJoin { join_type, ..self }
+    }
+}
+
+impl<L, R, K> DataSource<Joined<L, R>> for MergeJoin<L, R, K>
+where
+    L: Debug + Clone + Send + Sync + 'static,
+    R: Debug + Clone + Send + Sync + 'static,
+    K: Ord + Debug + Send + Sync + 'static,
+{
+    fn name(&self) -> String {
+        format!("MergeJoin-{}-{}", self.left_ds.name(), self.right_ds.name())
+    }
+
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<Joined<L, R>>, DataStoreError> {
+        use tokio::sync::mpsc::channel;
+        use tokio::task::JoinHandle;
+        let (tx, rx) = channel(channels::buffer_size());
+        let name = self.name();
+        let (left_rx, left_jh) = self.left_ds.start_stream()?;
+        let (right_rx, right_jh) = self.right_ds.start_stream()?;
+        let mut left = SortedSide::new("left", left_rx, self.left_key);
+        let mut right = SortedSide::new("right", right_rx, self.right_key);
+        let join_type = self.join_type;
+        let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
+            let send = |message| {
+                let tx = &tx;
+                let name = &name;
+                async move {
+                    tx.send(message)
+                        .await
+                        .map_err(|e| DataStoreError::send_error(name, "", e))
+                }
+            };
+            let mut left_group = left.next_group(&tx).await?;
+            let mut right_group = right.next_group(&tx).await?;
+            loop {
+                let order = match (&left_group, &right_group) {
+                    (None, None) => break,
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (Some(l), Some(r)) => l.key.cmp(&r.key),
+                };
+                match order {
+                    Ordering::Less => {
+                        if let Some(group) = left_group.take() {
+                            if join_type.keeps_left() {
+                                for (source, item) in group.items {
+                                    send(Ok(DataSourceMessage::new(source, (Some(item), None))))
+                                        .await?;
+                                }
+                            }
+                        }
+                        left_group = left.next_group(&tx).await?;
+                    }
+                    Ordering::Greater => {
+                        if let Some(group) = right_group.take() {
+                            if join_type.keeps_right() {
+                                for (source, item) in group.items {
+                                    send(Ok(DataSourceMessage::new(source, (None, Some(item)))))
+                                        .await?;
+                                }
+                            }
+                        }
+                        right_group = right.next_group(&tx).await?;
+                    }
+                    Ordering::Equal => {
+                        if let (Some(l), Some(r)) = (left_group.take(), right_group.take()) {
+                            for (source, left_item) in l.items.iter() {
+                                for (_, right_item) in r.items.iter() {
+                                    let joined =
+                                        (Some(left_item.clone()), Some(right_item.clone()));
+                                    send(Ok(DataSourceMessage::new(source, joined))).await?;
+                                }
+                            }
+                        }
+                        left_group = left.next_group(&tx).await?;
+                        right_group = right.next_group(&tx).await?;
+                    }
+                }
+            }
+            left_jh.await??;
+            right_jh.await??;
+            Ok(DataSourceStats::new(
+                left.lines_scanned + right.lines_scanned,
+            ))
+        });
+        Ok((rx, jh))
+    }
+}
+
+/// Consecutive items of one side with the same key
+struct Group<T, K> {
+    key: K,
+    items: Vec<(Provenance, T)>,
+}
+
+/// One side of a [MergeJoin], read a group at a time
+struct SortedSide<T: Send, K> {
+    name: &'static str,
+    rx: DataSourceRx<T>,
+    key: KeyFn<T, K>,
+    /// the first item of the next group
+    peeked: Option<(K, Provenance, T)>,
+    lines_scanned: usize,
+}
+
+impl<T, K> SortedSide<T, K>
+where
+    T: Debug + Send + 'static,
+    K: Ord + Debug,
+{
+    fn new(name: &'static str, rx: DataSourceRx<T>, key: KeyFn<T, K>) -> Self {
+        SortedSide {
+            name,
+            rx,
+            key,
+            peeked: None,
+            lines_scanned: 0,
+        }
+    }
+
+    /// Errors of the input are forwarded to `tx` as they come.  Input which is not sorted is
+    /// an error which is both forwarded and returned, since the join can not continue
+    async fn next_group<O: Debug + Send>(
+        &mut self,
+        tx: &Sender<Result<DataSourceMessage<O>, DataStoreError>>,
+    ) -> Result<Option<Group<T, K>>, DataStoreError> {
+        let (key, source, item) = match self.peeked.take() {
+            Some(first) => first,
+            None => match self.next(tx).await? {
+                Some(first) => first,
+                None => return Ok(None),
+            },
+        };
+        let mut items = vec![(source, item)];
+        while let Some((next_key, source, item)) = self.next(tx).await? {
+            match next_key.cmp(&key) {
+                Ordering::Equal => items.push((source, item)),
+                Ordering::Greater => {
+                    self.peeked = Some((next_key, source, item));
+                    break;
+                }
+                Ordering::Less => {
+                    let er = DataStoreError::Generic(format!(
+                        "MergeJoin {} input is not sorted, key {:?} came after {:?}",
+                        self.name, next_key, key
+                    ))
+                    .at(source);
+                    let _ = tx.send(Err(er.clone())).await;
+                    return Err(er);
+                }
+            }
+        }
+        Ok(Some(Group { key, items }))
+    }
+
+    async fn next<O: Debug + Send>(
+        &mut self,
+        tx: &Sender<Result<DataSourceMessage<O>, DataStoreError>>,
+    ) -> Result<Option<(K, Provenance, T)>, DataStoreError> {
+        loop {
+            match self.rx.recv().await {
+                Some(Ok(DataSourceMessage::Data { source, content })) => {
+                    self.lines_scanned += 1;
+                    return Ok(Some(((self.key)(&content), source, content)));
+                }
+                Some(Err(er)) => tx
+                    .send(Err(er))
+                    .await
+                    .map_err(|e| DataStoreError::send_error("MergeJoin", self.name, e))?,
+                None => return Ok(None),
+            }
+        }
+    }
+}
@@ -32,12 +32,30 @@ pub mod deps {
     pub use log;
     pub use bytes;
     pub use futures_core;
+    pub use futures_util;
     pub use chrono;
+    pub use arrow_schema;
+    pub use avro_schema;
+    pub use parquet;
 }
 /// Perform joins between two [crate::datastore::DataSource]s
 pub mod joins;
 /// for splitting streams into many identical DataSources
 pub mod splitter;
 pub mod transformer;
+/// Chainable combinators like `map` and `filter` for every [crate::datastore::DataSource]
+pub mod ext;
 /// take in a stream and output same items but as batches
 pub mod batch;
+/// Sorts streams which do not fit in memory
+pub mod sort;
+/// Aggregates the items of a stream per key
+pub mod group_by;
+/// Temporary files for the combinators which spill to disk
+pub(crate) mod spill;
+/// Drops items whose key was seen before, in this stream or in previous runs
+pub mod dedup;
+/// Combines several DataSources of the same type into one
+pub mod combine;
+/// Writes a stream to several DataOutputs
+pub mod multi_output;
@@ -0,0 +1,197 @@
+use crate::datastore::*;
+use crate::joins::KeyFn;
+use crate::splitter::PredicateFn;
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::hash::Hash;
+use tokio::task::JoinHandle;
+
+/// Which items a child of a [MultiOutput] receives
+pub enum Route<T, K> {
+    All,
+    If(PredicateFn<T>),
+    /// The items whose key, see [MultiOutput::by_key], is one of these
+    Keys(HashSet<K>),
+}
+
+/// A child of a [MultiOutput] with the items it receives
+pub type RoutedOutput<T, K> = (Route<T, K>, Box<dyn DataOutput<T>>);
+
+/// The key a [MultiOutput] routes the items by, [NoKey] unless made with [MultiOutput::by_key]
+pub trait RouteKey<T>: Send + Sync {
+    type Key: Hash + Eq + Send + Sync;
+
+    fn key(&self, item: &T) -> Option<Self::Key>;
+}
+
+/// Items are only routed to children receiving all items or matching a predicate
+pub struct NoKey;
+
+impl<T> RouteKey<T> for NoKey {
+    type Key = ();
+
+    fn key(&self, _: &T) -> Option<()> {
+        None
+    }
+}
+
+/// Items are also routed to the children given by [MultiOutput::with_output_for]
+pub struct ByKey<T, K>(pub KeyFn<T, K>);
+
+impl<T, K: Hash + Eq + Send + Sync> RouteKey<T> for ByKey<T, K> {
+    type Key = K;
+
+    fn key(&self, item: &T) -> Option<K> {
+        Some((self.0)(item))
+    }
+}
+
+/// Writes each item to every child output whose [Route] matches it, items matching none are
+/// dropped.  The stats have one part for each child, which JobRunner::run_stream records as
+/// the outputs of the stream
+pub struct MultiOutput<T, R: RouteKey<T> = NoKey> {
+    pub name: String,
+    pub key: R,
+    pub outputs: Vec<RoutedOutput<T, R::Key>>,
+}
+
+impl<T> MultiOutput<T> {
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        MultiOutput {
+            name: name.into(),
+            key: NoKey,
+            outputs: Vec::new(),
+        }
+    }
+}
+
+impl<T, K: Hash + Eq + Send + Sync> MultiOutput<T, ByKey<T, K>> {
+    /// Routes the items to the children given by [MultiOutput::with_output_for] using `key`
+    pub fn by_key<N, F>(name: N, key: F) -> Self
+    where
+        N: Into<String>,
+        F: Fn(&T) -> K + Send + Sync + 'static,
+    {
+        MultiOutput {
+            name: name.into(),
+            key: ByKey(Box::new(key)),
+            outputs: Vec::new(),
+        }
+    }
+
+    pub fn with_output_for<I>(mut self, keys: I, output: Box<dyn DataOutput<T>>) -> Self
+    where
+        I: IntoIterator<Item = K>,
+    {
+        let keys = keys.into_iter().collect();
+        self.outputs.push((Route::Keys(keys), output));
+        self
+    }
+}
+
+impl<T, R: RouteKey<T>> MultiOutput<T, R> {
+    /// Adds an output receiving every item
+    pub fn with_output(mut self, output: Box<dyn DataOutput<T>>) -> Self {
+        self.outputs.push((Route::All, output));
+        self
+    }
+
+    pub fn with_output_if<F>(mut self, predicate: F, output: Box<dyn DataOutput<T>>) -> Self
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        self.outputs.push((Route::If(Box::new(predicate)), output));
+        self
+    }
+}
+
+#[async_trait]
+impl<T, R> DataOutput<T> for MultiOutput<T, R>
+where
+    T: Debug + Clone + Send + Sync + 'static,
+    R: RouteKey<T> + 'static,
+    R::Key: 'static,
+{
+    async fn start_stream(self: Box<Self>) -> anyhow::Result<DataOutputTask<T>> {
+        use tokio::sync::mpsc::channel;
+        let mut routes = Vec::with_capacity(self.outputs.len());
+        let mut children = Vec::with_capacity(self.outputs.len());
+        for (route, output) in self.outputs {
+            routes.push(route);
+            children.push(output.start_stream().await?);
+        }
+        let name = self.name;
+        let key = self.key;
+        let (tx, mut rx): (DataOutputTx<T>, _) = channel(channels::buffer_size());
+        let jh: JoinHandle<anyhow::Result<DataOutputStats>> = tokio::spawn(async move {
+            let mut lines_written = 0_usize;
+            let mut targets = Vec::with_capacity(routes.len());
+            while let Some(DataOutputMessage::Data(item)) = rx.recv().await {
+                let item_key = key.key(&item);
+                targets.clear();
+                targets.extend(routes.iter().enumerate().filter_map(|(idx, route)| {
+                    let matches = match (route, &item_key) {
+                        (Route::All, _) => true,
+                        (Route::If(predicate), _) => predicate(&item),
+                        (Route::Keys(keys), Some(item_key)) => keys.contains(item_key),
+                        (Route::Keys(_), None) => false,
+                    };
+                    matches.then_some(idx)
+                }));
+                if targets.is_empty() {
+                    continue;
+                }
+                lines_written += 1;
+                let mut item = Some(item);
+                for (n, idx) in targets.iter().enumerate() {
+                    let message = match n + 1 == targets.len() {
+                        true => item.take(),
+                        false => item.clone(),
+                    };
+                    let (child_tx, _) = &children[*idx];
+                    if let Some(message) = message {
+                        if child_tx
+                            .send(DataOutputMessage::new(message))
+                            .await
+                            .is_err()
+                        {
+                            // the child stopped, its own error is returned if it has one
+                            let (_, child_jh) = children.swap_remove(*idx);
+                            child_jh.await??;
+                            return Err(anyhow::anyhow!(
+                                "{} output {} stopped before the end of the stream",
+                                name,
+                                idx
+                            ));
+                        }
+                    }
+                }
+            }
+            let mut parts = Vec::with_capacity(children.len());
+            let mut error = None;
+            for (child_tx, child_jh) in children {
+                drop(child_tx);
+                match child_jh.await {
+                    Ok(Ok(stats)) => parts.push(stats),
+                    Ok(Err(er)) => {
+                        error.get_or_insert(er);
+                    }
+                    Err(er) => {
+                        error.get_or_insert(anyhow::anyhow!(er));
+                    }
+                }
+            }
+            match error {
+                Some(er) => Err(er),
+                None => Ok(DataOutputStats {
+                    name,
+                    key: None,
+                    lines_written,
+                    parts,
+                }),
+            }
+        });
+        Ok((tx, jh))
+    }
+}
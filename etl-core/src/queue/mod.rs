@@ -38,7 +38,7 @@ impl<T: Debug + Send + 'static> QueueClient<T> for DynDataSource<T> {
         use tokio::task::JoinHandle;
         let ds_name: String = self.ds.name();
         let (mut source_rx, source_stream_jh) = self.ds.start_stream()?;
-        let (tx, rx) = channel(1);
+        let (tx, rx) = channel(channels::buffer_size());
         let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
             let mut lines_scanned = 0_usize;
             loop {
@@ -48,9 +48,11 @@ impl<T: Debug + Send + 'static> QueueClient<T> for DynDataSource<T> {
                         content: item,
                     })) => {
                         lines_scanned += 1;
-                        tx.send(Ok(DataSourceMessage::new(&ds_name, item)))
+                        tx.send(Ok(DataSourceMessage::new(&source, item)))
                             .await
-                            .map_err(|e| DataStoreError::send_error(&ds_name, &source, e))?;
+                            .map_err(|e| {
+                                DataStoreError::send_error(&ds_name, source.to_string(), e)
+                            })?;
                     }
                     Some(Err(val)) => {
                         tx.send(Err(DataStoreError::Deserialize {
@@ -64,7 +66,7 @@ impl<T: Debug + Send + 'static> QueueClient<T> for DynDataSource<T> {
                 }
             }
             source_stream_jh.await??;
-            Ok(DataSourceStats { lines_scanned })
+            Ok(DataSourceStats::new(lines_scanned))
         });
         Ok((rx, jh))
     }
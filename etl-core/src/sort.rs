@@ -0,0 +1,237 @@
+use crate::datastore::error::*;
+use crate::datastore::*;
+use crate::joins::KeyFn;
+use crate::spill::{SpillFiles, SpillReader};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt::Debug;
+use std::path::PathBuf;
+use tokio::sync::mpsc::Sender;
+
+/// Maximum number of runs merged at the same time, more runs are merged in several passes
+const MAX_MERGE_RUNS: usize = 64;
+
+/// Sorts the items of a DataSource by key.  Up to `run_len` items are sorted in memory at a
+/// time, when there are more the sorted runs are written to files and merged back.  Items with
+/// equal keys keep their order.  Errors of the input are sent as they come, before the sorted
+/// items
+pub struct SortedDataSource<T, K> {
+    pub input: Box<dyn DataSource<T>>,
+    pub key: KeyFn<T, K>,
+    /// Number of items to sort in memory
+    pub run_len: usize,
+    /// Where the runs are written, the temp dir by default
+    pub spill_dir: Option<PathBuf>,
+}
+
+impl<T, K> SortedDataSource<T, K> {
+    pub fn new<F>(input: Box<dyn DataSource<T>>, key: F) -> Self
+    where
+        F: Fn(&T) -> K + Send + Sync + 'static,
+    {
+        SortedDataSource {
+            input,
+            key: Box::new(key),
+            run_len: 100_000,
+            spill_dir: None,
+        }
+    }
+
+    pub fn with_run_len(self, run_len: usize) -> Self {
+        SortedDataSource {
+            run_len: run_len.max(1),
+            ..self
+        }
+    }
+
+    pub fn with_spill_dir<P: Into<PathBuf>>(self, spill_dir: P) -> Self {
+        SortedDataSource {
+            spill_dir: Some(spill_dir.into()),
+            ..self
+        }
+    }
+}
+
+impl<T, K> DataSource<T> for SortedDataSource<T, K>
+where
+    T: Serialize + DeserializeOwned + Debug + Send + Sync + 'static,
+    K: Ord + Send + Sync + 'static,
+{
+    fn name(&self) -> String {
+        format!("SortedDataSource-{}", self.input.name())
+    }
+
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<T>, DataStoreError> {
+        use tokio::sync::mpsc::channel;
+        use tokio::task::JoinHandle;
+        let (tx, rx) = channel(channels::buffer_size());
+        let name = self.name();
+        let (mut input_rx, input_jh) = self.input.start_stream()?;
+        let key = self.key;
+        let run_len = self.run_len.max(1);
+        let spill_dir = self.spill_dir.unwrap_or_else(std::env::temp_dir);
+        let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
+            let mut lines_scanned = 0_usize;
+            let mut run: Vec<(K, Provenance, T)> = Vec::new();
+            let mut runs = Runs::new(spill_dir);
+            while let Some(message) = input_rx.recv().await {
+                match message {
+                    Ok(DataSourceMessage::Data { source, content }) => {
+                        lines_scanned += 1;
+                        run.push((key(&content), source, content));
+                        if run.len() >= run_len {
+                            runs.spill(&mut run).await?;
+                        }
+                    }
+                    Err(er) => send(&tx, &name, Err(er)).await?,
+                }
+            }
+            input_jh.await??;
+            if runs.is_empty() {
+                run.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+                for (_, source, item) in run {
+                    send(&tx, &name, Ok(DataSourceMessage::new(source, item))).await?;
+                }
+            } else {
+                if !run.is_empty() {
+                    runs.spill(&mut run).await?;
+                }
+                runs.merge(&key, &tx, &name).await?;
+            }
+            Ok(DataSourceStats::new(lines_scanned))
+        });
+        Ok((rx, jh))
+    }
+}
+
+async fn send<T: Debug + Send>(
+    tx: &Sender<Result<DataSourceMessage<T>, DataStoreError>>,
+    name: &str,
+    message: Result<DataSourceMessage<T>, DataStoreError>,
+) -> Result<(), DataStoreError> {
+    tx.send(message)
+        .await
+        .map_err(|e| DataStoreError::send_error(name, "", e))
+}
+
+/// The files of the sorted runs written to disk, in the order of the input.  They are only
+/// opened when merged, and removed with the spill directory if the sort stops early
+struct Runs {
+    files: SpillFiles,
+    runs: Vec<PathBuf>,
+}
+
+impl Runs {
+    fn new(parent: PathBuf) -> Self {
+        Runs {
+            files: SpillFiles::new(parent, "etl-sort"),
+            runs: Vec::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    async fn spill<K: Ord, T: Serialize>(
+        &mut self,
+        run: &mut Vec<(K, Provenance, T)>,
+    ) -> Result<(), DataStoreError> {
+        run.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+        let mut writer = self.files.writer().await?;
+        for (_, source, item) in run.drain(..) {
+            writer.write(&source, &item).await?;
+        }
+        self.runs.push(writer.finish().await?);
+        Ok(())
+    }
+
+    /// Sends the items of every run in order, then removes the runs
+    async fn merge<T, K>(
+        mut self,
+        key: &KeyFn<T, K>,
+        tx: &Sender<Result<DataSourceMessage<T>, DataStoreError>>,
+        name: &str,
+    ) -> Result<(), DataStoreError>
+    where
+        T: Serialize + DeserializeOwned + Debug + Send,
+        K: Ord,
+    {
+        let result = self.merge_runs(key, tx, name).await;
+        self.files.remove().await;
+        result
+    }
+
+    async fn merge_runs<T, K>(
+        &mut self,
+        key: &KeyFn<T, K>,
+        tx: &Sender<Result<DataSourceMessage<T>, DataStoreError>>,
+        name: &str,
+    ) -> Result<(), DataStoreError>
+    where
+        T: Serialize + DeserializeOwned + Debug + Send,
+        K: Ord,
+    {
+        // the first runs are merged into one which takes their place, so equal keys keep
+        // their order
+        while self.runs.len() > MAX_MERGE_RUNS {
+            let runs = self.runs.drain(..MAX_MERGE_RUNS).collect::<Vec<_>>();
+            let mut merger = Merger::new(&runs, key).await?;
+            let mut writer = self.files.writer().await?;
+            while let Some((source, item)) = merger.next(key).await? {
+                writer.write(&source, &item).await?;
+            }
+            self.runs.insert(0, writer.finish().await?);
+            drop(merger);
+            for run in runs {
+                tokio::fs::remove_file(&run).await?;
+            }
+        }
+        let mut merger = Merger::new(&self.runs, key).await?;
+        while let Some((source, item)) = merger.next(key).await? {
+            send(tx, name, Ok(DataSourceMessage::new(source, item))).await?;
+        }
+        Ok(())
+    }
+}
+
+/// k-way merge of sorted runs, taking the smallest head of all runs each time.  On equal keys
+/// the earlier run goes first
+struct Merger<T, K> {
+    runs: Vec<SpillReader>,
+    heads: Vec<Option<(Provenance, T)>>,
+    heap: BinaryHeap<Reverse<(K, usize)>>,
+}
+
+impl<T: DeserializeOwned, K: Ord> Merger<T, K> {
+    async fn new(paths: &[PathBuf], key: &KeyFn<T, K>) -> Result<Self, DataStoreError> {
+        let mut runs = Vec::with_capacity(paths.len());
+        let mut heads = Vec::with_capacity(paths.len());
+        let mut heap = BinaryHeap::with_capacity(paths.len());
+        for (idx, path) in paths.iter().enumerate() {
+            let mut run = SpillReader::open(path.clone()).await?;
+            let head = run.next::<T>().await?;
+            if let Some((_, item)) = &head {
+                heap.push(Reverse((key(item), idx)));
+            }
+            heads.push(head);
+            runs.push(run);
+        }
+        Ok(Merger { runs, heads, heap })
+    }
+
+    async fn next(&mut self, key: &KeyFn<T, K>) -> Result<Option<(Provenance, T)>, DataStoreError> {
+        let idx = match self.heap.pop() {
+            Some(Reverse((_, idx))) => idx,
+            None => return Ok(None),
+        };
+        let head = self.heads[idx].take();
+        if let Some((source, item)) = self.runs[idx].next::<T>().await? {
+            self.heap.push(Reverse((key(&item), idx)));
+            self.heads[idx] = Some((source, item));
+        }
+        Ok(head)
+    }
+}
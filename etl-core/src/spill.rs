@@ -0,0 +1,158 @@
+use crate::datastore::compression::Compression;
+use crate::datastore::error::DataStoreError;
+use crate::datastore::Provenance;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::fs::File;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
+
+static SPILL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary directory for items which do not fit in memory, removed with [SpillDir::remove].
+/// A directory which is dropped without being removed, on an error for instance, is removed
+/// when dropped
+pub struct SpillDir {
+    path: PathBuf,
+    removed: bool,
+}
+
+impl SpillDir {
+    /// Creates a new directory inside `parent`, unique for the process
+    pub async fn create(parent: &Path, prefix: &str) -> Result<Self, DataStoreError> {
+        let path = parent.join(format!(
+            "{}-{}-{}",
+            prefix,
+            std::process::id(),
+            SPILL_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        tokio::fs::create_dir_all(&path).await?;
+        Ok(SpillDir {
+            path,
+            removed: false,
+        })
+    }
+
+    pub async fn writer(&self, name: &str) -> Result<SpillWriter, DataStoreError> {
+        SpillWriter::create(self.path.join(name)).await
+    }
+
+    pub async fn remove(mut self) {
+        self.removed = true;
+        if let Err(er) = tokio::fs::remove_dir_all(&self.path).await {
+            log::warn!("Could not remove {}: {}", self.path.display(), er);
+        }
+    }
+}
+
+impl Drop for SpillDir {
+    fn drop(&mut self) {
+        if self.removed {
+            return;
+        }
+        if let Err(er) = std::fs::remove_dir_all(&self.path) {
+            log::warn!("Could not remove {}: {}", self.path.display(), er);
+        }
+    }
+}
+
+/// Numbered files in a [SpillDir] which is created with the first file
+pub struct SpillFiles {
+    parent: PathBuf,
+    prefix: &'static str,
+    dir: Option<SpillDir>,
+    count: usize,
+}
+
+impl SpillFiles {
+    pub fn new(parent: PathBuf, prefix: &'static str) -> Self {
+        SpillFiles {
+            parent,
+            prefix,
+            dir: None,
+            count: 0,
+        }
+    }
+
+    pub async fn writer(&mut self) -> Result<SpillWriter, DataStoreError> {
+        let dir = match self.dir.take() {
+            Some(dir) => dir,
+            None => SpillDir::create(&self.parent, self.prefix).await?,
+        };
+        self.count += 1;
+        let writer = dir.writer(&format!("{}.jsonl.zst", self.count)).await;
+        self.dir = Some(dir);
+        writer
+    }
+
+    pub async fn remove(self) {
+        if let Some(dir) = self.dir {
+            dir.remove().await;
+        }
+    }
+}
+
+/// Writes items with their provenance as zstd compressed JSON lines
+pub struct SpillWriter {
+    path: PathBuf,
+    writer: Box<dyn AsyncWrite + Unpin + Send>,
+}
+
+impl SpillWriter {
+    async fn create(path: PathBuf) -> Result<Self, DataStoreError> {
+        let file = File::create(&path).await?;
+        Ok(SpillWriter {
+            path,
+            writer: Compression::Zstd.compress_writer(BufWriter::new(file)),
+        })
+    }
+
+    pub async fn write<T: Serialize>(
+        &mut self,
+        source: &Provenance,
+        item: &T,
+    ) -> Result<(), DataStoreError> {
+        let mut line = serde_json::to_vec(&(source, item))
+            .map_err(|er| DataStoreError::FatalIO(er.to_string()))?;
+        line.push(b'\n');
+        Ok(self.writer.write_all(&line).await?)
+    }
+
+    /// Finishes the file and returns its path, to read it back with [SpillReader::open]
+    pub async fn finish(mut self) -> Result<PathBuf, DataStoreError> {
+        self.writer.shutdown().await?;
+        Ok(self.path)
+    }
+}
+
+pub struct SpillReader {
+    path: PathBuf,
+    lines: tokio::io::Lines<Box<dyn AsyncBufRead + Unpin + Send>>,
+}
+
+impl SpillReader {
+    pub async fn open(path: PathBuf) -> Result<Self, DataStoreError> {
+        let file = BufReader::new(File::open(&path).await?);
+        Ok(SpillReader {
+            lines: Compression::Zstd.decompress_reader(file).lines(),
+            path,
+        })
+    }
+
+    pub async fn next<T: DeserializeOwned>(
+        &mut self,
+    ) -> Result<Option<(Provenance, T)>, DataStoreError> {
+        match self.lines.next_line().await? {
+            Some(line) => {
+                serde_json::from_str(&line)
+                    .map(Some)
+                    .map_err(|er| DataStoreError::Deserialize {
+                        message: format!("{} in {}", er, self.path.display()),
+                        attempted_string: line,
+                    })
+            }
+            None => Ok(None),
+        }
+    }
+}
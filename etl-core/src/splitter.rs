@@ -1,17 +1,50 @@
 use crate::datastore::error::DataStoreError;
+use crate::datastore::interop::DataSourceItem;
 use crate::datastore::*;
+use crate::spill::{SpillFiles, SpillReader, SpillWriter};
 use async_trait::async_trait;
+use futures_util::future;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
 use std::fmt::Debug;
-use tokio::sync::mpsc::Receiver;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::task::JoinHandle;
 
 pub struct DuplicateDataSource<I: Serialize + DeserializeOwned + Debug + Send + Sync> {
-    pub rx: DataSourceRx<I>,
+    pub rx: DataSourceChunkRx<I>,
     pub name: String,
 }
 
+impl<I: Serialize + DeserializeOwned + Debug + Send + Sync + 'static> DuplicateDataSource<I> {
+    /// Forwards the chunks as they come, `fw_tx` is flushed after each one
+    fn start(self, mut fw_tx: DataSourceChunkTx<I>) -> DataSourceJoinHandle {
+        let mut input_rx = self.rx;
+        let name = self.name;
+        tokio::spawn(async move {
+            let mut lines_scanned = 0_usize;
+            while let Some(chunk) = input_rx.recv_chunk().await {
+                lines_scanned += chunk.len();
+                for message in chunk {
+                    fw_tx
+                        .send(message)
+                        .await
+                        .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+                }
+                fw_tx
+                    .flush()
+                    .await
+                    .map_err(|e| DataStoreError::send_error(&name, "", e))?;
+            }
+            Ok(DataSourceStats::new(lines_scanned))
+        })
+    }
+}
+
 #[async_trait]
 impl<I: Serialize + DeserializeOwned + Debug + Send + Sync + 'static> DataSource<I>
     for DuplicateDataSource<I>
@@ -23,47 +56,14 @@ impl<I: Serialize + DeserializeOwned + Debug + Send + Sync + 'static> DataSource
     fn start_stream(self: Box<Self>) -> Result<DataSourceTask<I>, DataStoreError> {
         use tokio::sync::mpsc::channel;
         let (fw_tx, fw_rx): (_, Receiver<Result<DataSourceMessage<I>, DataStoreError>>) =
-            channel(1);
-        let mut input_rx = self.rx;
-        let name = self.name;
-        let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
-            let mut lines_scanned = 0_usize;
-            loop {
-                match input_rx.recv().await {
-                    /*
-                    Some(OkDataSourceMessage::Data(data)) => {
-                        lines_scanned += 1;
-                        // TODO: send with fw_tx
-                        fw_tx
-                            .send(Ok(DataSourceMessage::new(&name, data)))
-                            .await
-                            .map_err(|e| DataStoreError::send_error(&name, "", e))?;
-                    }
-                    //Some(Err(er)) => println!("ERROR: {}", er),
-                    Some(_) => break,
-                    */
-                    Some(Ok(DataSourceMessage::Data {
-                        // we know the source is Splitter
-                        source: _,
-                        content,
-                    })) => {
-                        fw_tx
-                            .send(Ok(DataSourceMessage::new(&name, content)))
-                            .await
-                            .map_err(|e| DataStoreError::send_error(&name, "", e))?;
-                    }
-                    Some(Err(err)) => {
-                        fw_tx
-                            .send(Err(err))
-                            .await
-                            .map_err(|e| DataStoreError::send_error(&name, "", e))?;
-                    }
-                    None => break,
-                };
-                lines_scanned += 1;
-            }
-            Ok(DataSourceStats { lines_scanned })
-        });
+            channel(channels::buffer_size());
+        let jh = (*self).start(fw_tx.into());
+        Ok((fw_rx, jh))
+    }
+
+    fn start_chunk_stream(self: Box<Self>) -> Result<DataSourceChunkTask<I>, DataStoreError> {
+        let (fw_tx, fw_rx) = channels::chunk_channel();
+        let jh = (*self).start(fw_tx);
         Ok((fw_rx, jh))
     }
 }
@@ -84,56 +84,410 @@ where
     // not sure if need to wait on the handle, but I believe so
     // the handle will need to be awaited after the duplicate streams are read
     // the JobRunner may have to be involved somehow when splitting
-    use tokio::sync::mpsc::channel;
     let mut outputs_tx = Vec::new();
     //let mut outputs_rx = Vec::new();
     let mut dup_data_sources: Vec<Box<DuplicateDataSource<I>>> = Vec::new();
     for num in 0..n {
-        // TODO: since all will be streaming at different speeds, good idea to add a parameter to
-        // configure the size of the buffer
-        let (tx, rx): (_, DataSourceRx<I>) = channel(1);
+        // all will be streaming at different speeds, a larger buffer (see
+        // channels::ChannelConfig) lets the faster ones get ahead
+        let (tx, rx) = channels::chunk_channel();
         outputs_tx.push(tx);
         dup_data_sources.push(Box::new(DuplicateDataSource {
             name: format!("{}_{}", data_source.name(), num),
             rx,
         }));
     }
-    let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
-        let (mut input_rx, input_jh) = data_source.start_stream()?;
+    let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = channels::spawn(async move {
+        let (mut input_rx, input_jh) = data_source.start_chunk_stream()?;
         let mut lines_scanned = 0_usize;
-        loop {
-            match input_rx.recv().await {
-                Some(Ok(DataSourceMessage::Data {
-                    source,
-                    content: input_item,
-                })) => {
-                    lines_scanned += 1;
-                    //let mut fx_tx_idx = 0;
-                    for fw_tx in &outputs_tx {
-                        //println!("SENDING TO: {}", fx_tx_idx);
-                        //fx_tx_idx += 1;
-                        fw_tx
-                            .send(Ok(DataSourceMessage::new("Splitter", input_item.clone())))
-                            .await
-                            .map_err(|e| {
-                                DataStoreError::send_error("DuplicateDataSource", &source, e)
-                            })?;
+        while let Some(chunk) = input_rx.recv_chunk().await {
+            for message in chunk {
+                lines_scanned += 1;
+                match message {
+                    Ok(DataSourceMessage::Data {
+                        source,
+                        content: input_item,
+                    }) => {
+                        for fw_tx in outputs_tx.iter_mut() {
+                            fw_tx
+                                .send(Ok(DataSourceMessage::new(&source, input_item.clone())))
+                                .await
+                                .map_err(|e| {
+                                    DataStoreError::send_error(
+                                        "DuplicateDataSource",
+                                        source.to_string(),
+                                        e,
+                                    )
+                                })?;
+                        }
                     }
-                }
-                Some(Err(err)) => {
-                    lines_scanned += 1;
-                    for fw_tx in &outputs_tx {
-                        fw_tx
-                            .send(Err(err.clone()))
-                            .await
-                            .map_err(|e| DataStoreError::send_error("Splitter", "", e))?;
+                    Err(err) => {
+                        for fw_tx in outputs_tx.iter_mut() {
+                            fw_tx
+                                .send(Err(err.clone()))
+                                .await
+                                .map_err(|e| DataStoreError::send_error("Splitter", "", e))?;
+                        }
                     }
                 }
-                None => break,
-            };
+            }
+            for fw_tx in outputs_tx.iter_mut() {
+                fw_tx
+                    .flush()
+                    .await
+                    .map_err(|e| DataStoreError::send_error("Splitter", "", e))?;
+            }
         }
         input_jh.await??;
-        Ok(DataSourceStats { lines_scanned })
+        Ok(DataSourceStats::new(lines_scanned))
     });
     (jh, dup_data_sources)
 }
+
+pub type PredicateFn<I> = Box<dyn Fn(&I) -> bool + Send + Sync>;
+pub type HashFn<I> = Box<dyn Fn(&I) -> u64 + Send + Sync>;
+/// The handle of the task routing the items, and the outputs
+pub type Partitions<I> = (
+    JoinHandle<Result<DataSourceStats, DataStoreError>>,
+    Vec<Box<DuplicateDataSource<I>>>,
+);
+
+/// How a [Partitioner] picks the output of an item
+pub enum Partitioning<I> {
+    /// By the hash of a key, so items with the same key go to the same output
+    Hash(HashFn<I>),
+    RoundRobin,
+    /// To the first output whose predicate holds, or to the last output when none does
+    Predicates(Vec<PredicateFn<I>>),
+}
+
+impl<I> Partitioning<I> {
+    fn outputs(&self, n: usize) -> usize {
+        match self {
+            Partitioning::Predicates(predicates) => predicates.len() + 1,
+            _ => n,
+        }
+    }
+}
+
+/// Unlike [split_datasources], which copies every item to all of the outputs, sends each item
+/// to exactly one output.  Errors of the input go to the first output.  Each output has its own
+/// buffer, once it is full the Partitioner waits for that output to be read, unless a spill
+/// dir is set in which case the items of the lagging output are written to files until it
+/// catches up
+pub struct Partitioner<I> {
+    pub input: Box<dyn DataSource<I>>,
+    pub partitioning: Partitioning<I>,
+    /// One for each output
+    pub buffer_sizes: Vec<usize>,
+    pub spill_dir: Option<PathBuf>,
+}
+
+impl<I> Partitioner<I> {
+    /// Fails when `n` is zero
+    pub fn new(
+        input: Box<dyn DataSource<I>>,
+        n: usize,
+        partitioning: Partitioning<I>,
+    ) -> Result<Self, DataStoreError> {
+        if n == 0 {
+            return Err(DataStoreError::Generic(String::from(
+                "Can't partition into zero streams",
+            )));
+        }
+        let n = partitioning.outputs(n);
+        Ok(Partitioner {
+            input,
+            partitioning,
+            buffer_sizes: vec![channels::buffer_size(); n],
+            spill_dir: None,
+        })
+    }
+
+    pub fn by_key<K, F>(
+        input: Box<dyn DataSource<I>>,
+        n: usize,
+        key: F,
+    ) -> Result<Self, DataStoreError>
+    where
+        K: Hash,
+        F: Fn(&I) -> K + Send + Sync + 'static,
+    {
+        let hash = move |item: &I| {
+            let mut hasher = DefaultHasher::new();
+            key(item).hash(&mut hasher);
+            hasher.finish()
+        };
+        Partitioner::new(input, n, Partitioning::Hash(Box::new(hash)))
+    }
+
+    pub fn round_robin(input: Box<dyn DataSource<I>>, n: usize) -> Result<Self, DataStoreError> {
+        Partitioner::new(input, n, Partitioning::RoundRobin)
+    }
+
+    /// One output for each predicate, plus one for the items which match none of them
+    pub fn by_predicates(input: Box<dyn DataSource<I>>, predicates: Vec<PredicateFn<I>>) -> Self {
+        let n = predicates.len() + 1;
+        Partitioner {
+            input,
+            partitioning: Partitioning::Predicates(predicates),
+            buffer_sizes: vec![channels::buffer_size(); n],
+            spill_dir: None,
+        }
+    }
+
+    /// Sets the buffer size of every output
+    pub fn with_buffer_size(self, size: usize) -> Self {
+        let buffer_sizes = vec![size.max(1); self.buffer_sizes.len()];
+        Partitioner {
+            buffer_sizes,
+            ..self
+        }
+    }
+
+    /// Fails when there is no such output
+    pub fn with_output_buffer_size(
+        mut self,
+        output: usize,
+        size: usize,
+    ) -> Result<Self, DataStoreError> {
+        match self.buffer_sizes.get_mut(output) {
+            Some(buffer_size) => *buffer_size = size.max(1),
+            None => {
+                return Err(DataStoreError::Generic(format!(
+                    "Partitioner has {} outputs, there is no output {}",
+                    self.buffer_sizes.len(),
+                    output
+                )))
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn with_spill_dir<P: Into<PathBuf>>(self, spill_dir: P) -> Self {
+        Partitioner {
+            spill_dir: Some(spill_dir.into()),
+            ..self
+        }
+    }
+}
+
+impl<I> Partitioner<I>
+where
+    I: Serialize + DeserializeOwned + Debug + Send + Sync + 'static,
+{
+    /// Starts routing the input.  The handle is done when every item was passed to the outputs
+    pub fn start(self) -> Partitions<I> {
+        use tokio::sync::mpsc::channel;
+        let name = format!("Partitioner-{}", self.input.name());
+        let mut lanes = Vec::new();
+        let mut data_sources = Vec::new();
+        for (num, size) in self.buffer_sizes.iter().enumerate() {
+            let (tx, rx) = channel(*size);
+            lanes.push(Lane::new(tx));
+            data_sources.push(Box::new(DuplicateDataSource {
+                name: format!("{}_{}", self.input.name(), num),
+                rx: rx.into(),
+            }));
+        }
+        let input = self.input;
+        let partitioning = self.partitioning;
+        let mut spill = self
+            .spill_dir
+            .map(|parent| SpillFiles::new(parent, "etl-partitioner"));
+        let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = channels::spawn(async move {
+            let (mut input_rx, input_jh) = input.start_chunk_stream()?;
+            let mut lines_scanned = 0_usize;
+            let result = async {
+                while let Some(message) = input_rx.recv().await {
+                    let output = match &message {
+                        Ok(DataSourceMessage::Data { content, .. }) => match &partitioning {
+                            Partitioning::Hash(hash) => {
+                                (hash(content) % lanes.len() as u64) as usize
+                            }
+                            Partitioning::RoundRobin => lines_scanned % lanes.len(),
+                            Partitioning::Predicates(predicates) => predicates
+                                .iter()
+                                .position(|predicate| predicate(content))
+                                .unwrap_or(predicates.len()),
+                        },
+                        Err(_) => 0,
+                    };
+                    // counted after picking the output, so round robin starts with output 0
+                    lines_scanned += 1;
+                    match spill.as_mut() {
+                        Some(spill) => {
+                            for lane in lanes.iter_mut().filter(|lane| lane.is_lagging()) {
+                                lane.drain(&name).await?;
+                            }
+                            lanes[output].push(message, spill, &name).await?;
+                        }
+                        None => lanes[output].send(message, &name).await?,
+                    }
+                }
+                // each output is closed once it is flushed, as its reader might be waiting for
+                // another output to end
+                let name = &name;
+                future::try_join_all(
+                    lanes
+                        .into_iter()
+                        .map(|mut lane| async move { lane.flush(name).await }),
+                )
+                .await?;
+                Ok::<_, DataStoreError>(())
+            }
+            .await;
+            if let Some(spill) = spill {
+                spill.remove().await;
+            }
+            result?;
+            input_jh.await??;
+            Ok(DataSourceStats::new(lines_scanned))
+        });
+        (jh, data_sources)
+    }
+}
+
+/// Items spilled by a lagging [Lane], in the order they have to be sent.  A file is only
+/// opened once the backlog before it was sent
+enum Backlog {
+    File(PathBuf),
+    Reading(SpillReader),
+    Error(DataStoreError),
+}
+
+/// One output of a [Partitioner]
+struct Lane<I: Send> {
+    tx: Sender<DataSourceItem<I>>,
+    /// the message which did not fit in the channel, sent before the backlog
+    pending: Option<DataSourceItem<I>>,
+    backlog: VecDeque<Backlog>,
+    /// the file being written, read once the backlog before it was sent
+    writer: Option<SpillWriter>,
+}
+
+impl<I> Lane<I>
+where
+    I: Serialize + DeserializeOwned + Debug + Send + Sync + 'static,
+{
+    fn new(tx: Sender<DataSourceItem<I>>) -> Self {
+        Lane {
+            tx,
+            pending: None,
+            backlog: VecDeque::new(),
+            writer: None,
+        }
+    }
+
+    fn is_lagging(&self) -> bool {
+        self.pending.is_some() || !self.backlog.is_empty() || self.writer.is_some()
+    }
+
+    async fn send(&mut self, message: DataSourceItem<I>, name: &str) -> Result<(), DataStoreError> {
+        self.tx
+            .send(message)
+            .await
+            .map_err(|e| DataStoreError::send_error(name, "", e))
+    }
+
+    /// Sends the message if there is room in the channel, or spills it
+    async fn push(
+        &mut self,
+        message: DataSourceItem<I>,
+        spill: &mut SpillFiles,
+        name: &str,
+    ) -> Result<(), DataStoreError> {
+        if !self.is_lagging() {
+            return self.try_send(message, name);
+        }
+        match message {
+            Ok(DataSourceMessage::Data { source, content }) => {
+                if self.writer.is_none() {
+                    self.writer = Some(spill.writer().await?);
+                }
+                if let Some(writer) = self.writer.as_mut() {
+                    writer.write(&source, &content).await?;
+                }
+            }
+            Err(er) => {
+                if let Some(writer) = self.writer.take() {
+                    self.backlog
+                        .push_back(Backlog::File(writer.finish().await?));
+                }
+                self.backlog.push_back(Backlog::Error(er));
+            }
+        }
+        Ok(())
+    }
+
+    fn try_send(&mut self, message: DataSourceItem<I>, name: &str) -> Result<(), DataStoreError> {
+        match self.tx.try_send(message) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(message)) => {
+                self.pending = Some(message);
+                Ok(())
+            }
+            Err(TrySendError::Closed(_)) => Err(DataStoreError::send_error(
+                name,
+                "",
+                "the receiving DataSource was dropped",
+            )),
+        }
+    }
+
+    /// Sends as much of the backlog as fits in the channel without waiting
+    async fn drain(&mut self, name: &str) -> Result<(), DataStoreError> {
+        while self.tx.capacity() > 0 {
+            let message = match self.pending.take() {
+                Some(message) => message,
+                None => match self.next_backlog().await? {
+                    Some(message) => message,
+                    None => break,
+                },
+            };
+            self.try_send(message, name)?;
+        }
+        Ok(())
+    }
+
+    /// Waits until all of the backlog is sent
+    async fn flush(&mut self, name: &str) -> Result<(), DataStoreError> {
+        if let Some(message) = self.pending.take() {
+            self.send(message, name).await?;
+        }
+        while let Some(message) = self.next_backlog().await? {
+            self.send(message, name).await?;
+        }
+        Ok(())
+    }
+
+    async fn next_backlog(&mut self) -> Result<Option<DataSourceItem<I>>, DataStoreError> {
+        loop {
+            match self.backlog.front_mut() {
+                Some(Backlog::File(path)) => {
+                    let reader = SpillReader::open(path.clone()).await?;
+                    self.backlog[0] = Backlog::Reading(reader);
+                }
+                Some(Backlog::Reading(reader)) => match reader.next::<I>().await? {
+                    Some((source, item)) => {
+                        return Ok(Some(Ok(DataSourceMessage::new(source, item))))
+                    }
+                    None => {
+                        self.backlog.pop_front();
+                    }
+                },
+                Some(Backlog::Error(_)) => {
+                    if let Some(Backlog::Error(er)) = self.backlog.pop_front() {
+                        return Ok(Some(Err(er)));
+                    }
+                }
+                None => match self.writer.take() {
+                    Some(writer) => self
+                        .backlog
+                        .push_back(Backlog::File(writer.finish().await?)),
+                    None => return Ok(None),
+                },
+            }
+        }
+    }
+}
@@ -1,65 +1,126 @@
 use crate::datastore::error::DataStoreError;
 use crate::datastore::*;
+use futures_core::future::BoxFuture;
 use std::fmt::Debug;
+use std::future::Future;
 use tokio::sync::mpsc::Receiver;
 use tokio::task::JoinHandle;
 
+pub type BoxedTransformFn<I, O> =
+    Box<dyn Fn(I) -> BoxFuture<'static, DataOutputItemResult<Option<O>>> + Send + Sync>;
+
+/// Maps every item of the input, items mapped to `None` are dropped
 pub struct Transformer<I, O> {
     pub input: Box<dyn DataSource<I>>,
-    pub map: fn(I) -> DataOutputItemResult<Option<O>>,
+    pub map: BoxedTransformFn<I, O>,
 }
 
-impl<I: Debug + Send + Sync + 'static, O: Debug + Send + Sync + 'static> DataSource<O>
-    for Transformer<I, O>
-{
-    fn name(&self) -> String {
-        format!("Transformer-{}", self.input.name())
+impl<I, O: Send + 'static> Transformer<I, O> {
+    /// The closure can capture state, like a lookup table
+    pub fn new<F>(input: Box<dyn DataSource<I>>, map: F) -> Self
+    where
+        F: Fn(I) -> DataOutputItemResult<Option<O>> + Send + Sync + 'static,
+    {
+        Transformer {
+            input,
+            map: Box::new(move |item| {
+                let result = map(item);
+                Box::pin(async move { result })
+            }),
+        }
     }
 
-    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<O>, DataStoreError> {
-        use tokio::sync::mpsc::channel;
-        let (tx, rx): (_, Receiver<Result<DataSourceMessage<O>, DataStoreError>>) = channel(1);
-        let (mut input_rx, _) = self.input.start_stream()?;
+    /// For maps that need to make async calls, like enriching items from a db or a cache
+    pub fn new_async<F, Fut>(input: Box<dyn DataSource<I>>, map: F) -> Self
+    where
+        F: Fn(I) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = DataOutputItemResult<Option<O>>> + Send + 'static,
+    {
+        Transformer {
+            input,
+            map: Box::new(move |item| Box::pin(map(item))),
+        }
+    }
+}
+
+impl<I: Debug + Send + Sync + 'static, O: Debug + Send + Sync + 'static> Transformer<I, O> {
+    /// Maps the input a chunk at a time, `tx` is flushed before waiting for the next chunk
+    fn start(self, mut tx: DataSourceChunkTx<O>) -> Result<DataSourceJoinHandle, DataStoreError> {
+        let (mut input_rx, _) = self.input.start_chunk_stream()?;
         let map_func = self.map;
         let name = String::from("Transformer");
         let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
             let mut lines_scanned = 0_usize;
-            loop {
-                match input_rx.recv().await {
-                    Some(Ok(DataSourceMessage::Data {
-                        source,
-                        content: input_item,
-                    })) => match map_func(input_item) {
-                        Ok(Some(output_item)) => {
-                            lines_scanned += 1;
-                            tx.send(Ok(DataSourceMessage::new(&source, output_item)))
-                                .await
-                                .map_err(|e| DataStoreError::send_error(&name, &source, e))?;
-                        }
-                        Ok(None) => {},
-                        Err(val) => {
-                            match tx
-                                .send(Err(DataStoreError::Deserialize {
-                                    message: val.to_string(),
-                                    attempted_string: format!("{:?}", val),
-                                }))
-                                .await
-                            {
-                                Ok(_) => {
-                                    lines_scanned += 1;
-                                }
-                                Err(e) => {
-                                    return Err(DataStoreError::send_error(&name, "", e));
+            while let Some(chunk) = input_rx.recv_chunk().await {
+                for message in chunk {
+                    match message {
+                        Ok(DataSourceMessage::Data {
+                            source,
+                            content: input_item,
+                        }) => match map_func(input_item).await {
+                            Ok(Some(output_item)) => {
+                                lines_scanned += 1;
+                                tx.send(Ok(DataSourceMessage::new(&source, output_item)))
+                                    .await
+                                    .map_err(|e| {
+                                        DataStoreError::send_error(&name, source.to_string(), e)
+                                    })?;
+                            }
+                            Ok(None) => {}
+                            Err(val) => {
+                                match tx
+                                    .send(Err(DataStoreError::Deserialize {
+                                        message: val.to_string(),
+                                        attempted_string: format!("{:?}", val),
+                                    }
+                                    .at(&source)))
+                                    .await
+                                {
+                                    Ok(_) => {
+                                        lines_scanned += 1;
+                                    }
+                                    Err(e) => {
+                                        return Err(DataStoreError::send_error(&name, "", e));
+                                    }
                                 }
                             }
+                        },
+                        Err(er) => {
+                            lines_scanned += 1;
+                            tx.send(Err(er))
+                                .await
+                                .map_err(|e| DataStoreError::send_error(&name, "", e))?;
                         }
-                    },
-                    Some(Err(er)) => println!("ERROR: {}", er),
-                    None => break,
-                };
+                    };
+                }
+                tx.flush()
+                    .await
+                    .map_err(|e| DataStoreError::send_error(&name, "", e))?;
             }
-            Ok(DataSourceStats { lines_scanned })
+            Ok(DataSourceStats::new(lines_scanned))
         });
+        Ok(jh)
+    }
+}
+
+impl<I: Debug + Send + Sync + 'static, O: Debug + Send + Sync + 'static> DataSource<O>
+    for Transformer<I, O>
+{
+    fn name(&self) -> String {
+        format!("Transformer-{}", self.input.name())
+    }
+
+    fn start_stream(self: Box<Self>) -> Result<DataSourceTask<O>, DataStoreError> {
+        use tokio::sync::mpsc::channel;
+        let (tx, rx): (_, Receiver<Result<DataSourceMessage<O>, DataStoreError>>) =
+            channel(channels::buffer_size());
+        let jh = (*self).start(tx.into())?;
+        Ok((rx, jh))
+    }
+
+    fn start_chunk_stream(self: Box<Self>) -> Result<DataSourceChunkTask<O>, DataStoreError> {
+        let (tx, rx) = channels::chunk_channel();
+        let jh = (*self).start(tx)?;
         Ok((rx, jh))
     }
 }
@@ -2,6 +2,7 @@ use crate::job_manager::*;
 use etl_core::datastore::error::*;
 use etl_core::datastore::simple::SimpleStore;
 use etl_core::datastore::*;
+use etl_core::deps::futures_util::future;
 use etl_core::deps::{
     anyhow, async_trait, serde, serde::de::DeserializeOwned, serde::Serialize, tokio,
 };
@@ -11,10 +12,12 @@ use std::fmt;
 use std::fmt::Debug;
 
 pub mod command;
+pub mod dead_letter;
 pub mod handler;
 pub mod stream;
 pub mod stream_handler_builder;
 use command::*;
+use dead_letter::*;
 use handler::*;
 //use stream::*;
 pub mod state;
@@ -38,6 +41,10 @@ pub struct JobRunner {
     job_state_updated: bool,
     /// the current step being run
     cur_step_index: usize,
+    /// records which failed to decode or transform are sent here
+    dead_letter_tx: Option<DataOutputTx<DeadLetter>>,
+    /// dead letter outputs to await on when the job completes
+    dead_letter_handles: Vec<DataOutputJoinHandle>,
 }
 
 pub struct JobRunnerConfig {
@@ -51,6 +58,15 @@ pub struct JobRunnerConfig {
     /// The SimpleStore used by JobRunner to store state.  The default setting uses the
     /// MockJsonDataSource which does not persist, thus all pipelines will run every time.
     pub ds: Box<dyn SimpleStore<serde_json::Value>>,
+    /// Receives every record of every stream which failed to decode or transform, see
+    /// [DeadLetter].  Use [JobRunner::with_dead_letter] to change it for particular streams
+    pub dead_letter: Option<Box<dyn DataOutput<DeadLetter>>>,
+    /// Buffer and chunk sizes of the channels between the stages of the streams this JobRunner
+    /// starts
+    pub channels: channels::ChannelConfig,
+    /// Number of items a [StreamHandler] processes at the same time in
+    /// [JobRunner::run_stream_handler]
+    pub concurrency: Concurrency,
 }
 
 impl Default for JobRunnerConfig {
@@ -59,6 +75,9 @@ impl Default for JobRunnerConfig {
             max_errors: 1000,
             stop_on_error: true,
             ds: Box::new(MockJsonDataSource::default()),
+            dead_letter: None,
+            channels: channels::ChannelConfig::default(),
+            concurrency: Concurrency::default(),
         }
     }
 }
@@ -78,6 +97,8 @@ impl JobRunner {
     {
         let name = name.into();
         let id = id.into();
+        let mut config = config;
+        let dead_letter = config.dead_letter.take();
         let mut jr = JobRunner {
             job_manager_channel: job_manager_handle.connect(id.clone()).await?,
             num_process_item_errors: 0,
@@ -88,7 +109,12 @@ impl JobRunner {
             job_state_updated: false,
             is_running: false,
             cur_step_index: 0,
+            dead_letter_tx: None,
+            dead_letter_handles: Vec::new(),
         };
+        if let Some(dead_letter) = dead_letter {
+            jr = jr.with_dead_letter(dead_letter).await?;
+        }
         jr.job_state = jr.load_job_state().await?;
         /*
         jr.register().await
@@ -109,6 +135,48 @@ impl JobRunner {
         self.job_state.id()
     }
 
+    /// Names of the files (or keys) which were already read by the given stream in a previous
+    /// run of this job.  Useful to only pick up new files, for example with
+    /// [etl_core::datastore::fs::FileDiscovery::skip]
+    pub fn processed_files(&self, stream_name: &str) -> std::collections::HashSet<String> {
+        self.job_state.processed_inputs(stream_name)
+    }
+
+    /// Sends the records which fail to decode or transform in the following streams to the given
+    /// output, replacing the one set in [JobRunnerConfig::dead_letter]
+    pub async fn with_dead_letter(
+        mut self,
+        output: Box<dyn DataOutput<DeadLetter>>,
+    ) -> Result<Self, JobRunnerError> {
+        let (tx, jh) = self.config.channels.scope(output.start_stream()).await?;
+        // the previous output finishes once its sender is dropped
+        self.dead_letter_tx = Some(tx);
+        self.dead_letter_handles.push(jh);
+        Ok(self)
+    }
+
+    async fn send_dead_letter(&mut self, dead_letter: DeadLetter) {
+        if !self.try_send_dead_letter(dead_letter).await {
+            self.dead_letter_tx = None;
+        }
+    }
+
+    /// Returns false when the dead letter output stopped
+    async fn try_send_dead_letter(&self, dead_letter: DeadLetter) -> bool {
+        if let Some(tx) = &self.dead_letter_tx {
+            if let Err(er) = tx.send(DataOutputMessage::new(dead_letter)).await {
+                self.log_err(
+                    self.job_state.name(),
+                    None,
+                    format!("The dead letter output stopped: {}", er),
+                )
+                .await;
+                return false;
+            }
+        }
+        true
+    }
+
     async fn load_job_state(&mut self) -> Result<JobState, DataStoreError> {
         if self.job_state_updated {
             self.save_job_state().await?;
@@ -231,29 +299,8 @@ impl JobRunner {
     /// whether the JobRunner has reached maximum number of errors allowed.  If it must exit, it
     /// will notify JobManager of the fact and return a JobRunnerError
     async fn process_job_manager_rx(&mut self) -> Result<(), JobRunnerError> {
-        use tokio::sync::mpsc::error::TryRecvError;
-        loop {
-            match self.job_manager_channel.rx.try_recv() {
-                Ok(message) => {
-                    use crate::job_manager::Message::*;
-                    match message {
-                        // message broadcasted from JobManager when there are too many errors and its
-                        // better to stop the job completely.  max errors are defined in the
-                        // configuration file
-                        ToJobRunner(NotifyJobRunner::TooManyErrors) => {
-                            return Err(JobRunnerError::TooManyErrors);
-                        }
-                        _ => {}
-                    }
-                }
-                // even though we're disconnected, allow the job to finish
-                Err(TryRecvError::Disconnected) => {
-                    break;
-                }
-                Err(TryRecvError::Empty) => {
-                    break;
-                }
-            }
+        if job_manager_too_many_errors(&mut self.job_manager_channel.rx) {
+            return Err(JobRunnerError::TooManyErrors);
         }
         if self.config.max_errors <= self.num_process_item_errors {
             // notify JobManager that we are exiting
@@ -298,6 +345,29 @@ impl JobRunner {
         for (name, stats) in output_stats {
             self.job_state.stream_ok(name, &self.config, vec![stats])?;
         }
+        self.dead_letter_tx = None;
+        for join_handle in std::mem::take(&mut self.dead_letter_handles) {
+            match join_handle.await {
+                Err(join_handle_err) => {
+                    self.job_state.caught_errors.push(join_handle_err.into());
+                }
+                Ok(Err(task_err)) => {
+                    self.job_state.caught_errors.push(task_err.into());
+                }
+                Ok(Ok(stats)) if stats.lines_written > 0 => {
+                    self.log_info(
+                        self.job_state.name(),
+                        format!(
+                            "{} dead letters were written to {}",
+                            stats.lines_written,
+                            stats.key.as_deref().unwrap_or(&stats.name)
+                        ),
+                    )
+                    .await;
+                }
+                Ok(Ok(_)) => {}
+            }
+        }
         if self.job_state.caught_errors.len() == 0 {
             self.job_state.set_run_status_complete()?;
         }
@@ -342,69 +412,87 @@ impl JobRunner {
             }
             Ok(_) => {
                 let input_name = input.name().to_string();
-                // no need to wait on input JoinHandle
-                let (mut input_rx, _) = input.start_stream()?;
-                let (output_tx, output_jh) = output.start_stream().await?;
+                // the input JoinHandle is only awaited for its stats once the output is done
+                let channels = self.config.channels;
+                let (mut input_rx, input_jh) =
+                    channels.sync_scope(|| input.start_chunk_stream())?;
+                let (output_tx, output_jh) = channels.scope(output.start_stream()).await?;
                 self.save_job_state().await?;
                 let mut lines_scanned = 0_usize;
-                loop {
-                    let info = JobItemInfo::new((lines_scanned, self.job_state.name()));
-                    match input_rx.recv().await {
-                        Some(Ok(DataSourceMessage::Data {
-                            source,
-                            content: input_item,
-                        })) => {
-                            lines_scanned += 1;
-                            self.job_state.stream_incr_count_ok(stream_name, &source)?;
-                            output_tx.send(DataOutputMessage::new(input_item)).await?;
-                        }
-                        Some(Err(val)) => {
-                            lines_scanned += 1;
-                            self.job_state.stream_incr_count_err(stream_name)?;
-                            self.log_err(&input_name, Some(&info), val.to_string())
+                let mut last_source = Provenance::from(&input_name);
+                while let Some(chunk) = input_rx.recv_chunk().await {
+                    for message in chunk {
+                        let info = JobItemInfo::new((lines_scanned, self.job_state.name()));
+                        match message {
+                            Ok(DataSourceMessage::Data {
+                                source,
+                                content: input_item,
+                            }) => {
+                                lines_scanned += 1;
+                                self.job_state
+                                    .stream_incr_count_ok(stream_name, &source.key)?;
+                                output_tx.send(DataOutputMessage::new(input_item)).await?;
+                                last_source = source;
+                            }
+                            Err(val) => {
+                                lines_scanned += 1;
+                                self.job_state.stream_incr_count_err(stream_name)?;
+                                self.log_err(&input_name, Some(&info), val.to_string())
+                                    .await;
+                                self.send_dead_letter(DeadLetter::from_error(
+                                    stream_name,
+                                    &last_source,
+                                    info.index,
+                                    &val,
+                                ))
                                 .await;
-                            self.num_process_item_errors += 1;
-                        }
-                        None => break,
-                    };
-                    match self.process_job_manager_rx().await {
-                        Err(JobRunnerError::TooManyErrors) => {
-                            self.job_state.stream_not_ok(
-                                name,
-                                String::from("Reached too many errors"),
-                                lines_scanned,
-                            )?;
-                            self.save_job_state().await?;
-                            drop(input_rx);
-                            drop(output_tx);
-                            // not using the number in error yet..
-                            let _ = output_jh.await??;
-                            return Err(JobRunnerError::TooManyErrors);
-                        }
-                        Err(e) => {
-                            //panic!("Received an unforseen error {}", e);
-                            self.job_state.stream_not_ok(
-                                name,
-                                format!("While processing job manager messages ran into {}", &e),
-                                lines_scanned,
-                            )?;
-                            self.save_job_state().await?;
-                            drop(input_rx);
-                            drop(output_tx);
-                            output_jh.await??;
-                            return Err(JobRunnerError::GenericError {
-                                message: e.to_string(),
-                            });
-                        }
-                        Ok(()) => {}
-                    };
+                                self.num_process_item_errors += 1;
+                            }
+                        };
+                        match self.process_job_manager_rx().await {
+                            Err(JobRunnerError::TooManyErrors) => {
+                                self.job_state.stream_not_ok(
+                                    name,
+                                    String::from("Reached too many errors"),
+                                    lines_scanned,
+                                )?;
+                                self.save_job_state().await?;
+                                drop(input_rx);
+                                drop(output_tx);
+                                // not using the number in error yet..
+                                let _ = output_jh.await??;
+                                return Err(JobRunnerError::TooManyErrors);
+                            }
+                            Err(e) => {
+                                //panic!("Received an unforseen error {}", e);
+                                self.job_state.stream_not_ok(
+                                    name,
+                                    format!(
+                                        "While processing job manager messages ran into {}",
+                                        &e
+                                    ),
+                                    lines_scanned,
+                                )?;
+                                self.save_job_state().await?;
+                                drop(input_rx);
+                                drop(output_tx);
+                                output_jh.await??;
+                                return Err(JobRunnerError::GenericError {
+                                    message: e.to_string(),
+                                });
+                            }
+                            Ok(()) => {}
+                        };
+                    }
                 }
                 self.cur_step_index += 1;
                 drop(input_rx);
                 drop(output_tx);
                 let output_stats = output_jh.await??;
+                let input_stats = input_jh.await??;
                 self.job_state
-                    .stream_ok(name, &self.config, vec![output_stats])?;
+                    .stream_ok(&name, &self.config, vec![output_stats])?;
+                self.job_state.stream_input_stats(name, &input_stats)?;
                 self.save_job_state().await?;
             }
         }
@@ -464,7 +552,7 @@ impl JobRunner {
         I: Debug + Send + Sync + 'static,
     {
         use stream::StepStreamStatus;
-        let (mut rx, source_stream_jh) = ds.start_stream()?;
+        let (rx, source_stream_jh) = self.config.channels.sync_scope(|| ds.start_stream())?;
         let stream_name = name.into();
         self.job_state = self.load_job_state().await?;
 
@@ -498,83 +586,43 @@ impl JobRunner {
                     }
                 };
 
-                let mut received_lines = 0_usize;
-
                 self.save_job_state().await?;
-                let mut lines_scanned = 0_usize;
-                loop {
-                    match rx.recv().await {
-                        Some(Ok(DataSourceMessage::Data {
-                            source,
-                            content: line,
-                        })) => {
-                            if received_lines >= index_start {
-                                lines_scanned += 1;
-                                match job_handler
-                                    .process_item(
-                                        JobItemInfo::new((
-                                            self.num_processed_items,
-                                            self.job_state.name(),
-                                        )),
-                                        line,
-                                        &self,
-                                    )
-                                    .await
-                                {
-                                    Ok(()) => {
-                                        self.num_processed_items += 1;
-                                        self.job_state
-                                            .stream_incr_count_ok(&stream_name, &source)?;
-                                    }
-                                    Err(er) => {
-                                        self.log_err(
-                                            self.job_state.name(),
-                                            Some(&JobItemInfo::new((
-                                                self.num_processed_items,
-                                                self.job_state.name(),
-                                            ))),
-                                            er.to_string(),
-                                        )
-                                        .await;
-                                        self.job_state.stream_incr_count_err(&stream_name)?;
-                                        self.num_process_item_errors += 1;
-                                    }
-                                }
-                            }
-                            received_lines += 1;
-                        }
-                        Some(Err(er)) => {
-                            self.log_err(
-                                self.job_state.name(),
-                                Some(&JobItemInfo::new((
-                                    self.num_processed_items,
-                                    self.job_state.name(),
-                                ))),
-                                er.to_string(),
-                            )
-                            .await;
-                            self.job_state.stream_incr_count_err(&stream_name)?;
-                            self.num_process_item_errors += 1;
-                        }
-                        None => {
-                            break;
-                        }
-                    };
-                    match self.process_job_manager_rx().await {
-                        Err(JobRunnerError::TooManyErrors) => {
-                            self.job_state.stream_not_ok(
-                                stream_name,
-                                "JobMonitor has reached maximum errors",
-                                lines_scanned,
-                            )?;
-                            self.save_job_state().await?;
-                            return Err(JobRunnerError::TooManyErrors);
-                        }
-                        Err(er) => {
-                            panic!("Unhandled error: {}", er);
-                        }
-                        Ok(()) => {}
-                    };
+                // the items being processed borrow the JobRunner, so the receiver is taken out of
+                // it until they are done
+                let (_, closed_rx) = tokio::sync::mpsc::channel(1);
+                let mut jm_rx = std::mem::replace(&mut self.job_manager_channel.rx, closed_rx);
+                let progress = self
+                    .handle_items(
+                        &stream_name,
+                        rx,
+                        job_handler.as_ref(),
+                        index_start,
+                        &mut jm_rx,
+                    )
+                    .await;
+                self.job_manager_channel.rx = jm_rx;
+                let progress = progress?;
+                for (source, num_ok) in progress.inputs.iter() {
+                    for _ in 0..*num_ok {
+                        self.job_state.stream_incr_count_ok(&stream_name, source)?;
+                    }
+                }
+                for _ in 0..progress.num_errors {
+                    self.job_state.stream_incr_count_err(&stream_name)?;
+                }
+                self.num_processed_items += progress.num_items;
+                self.num_process_item_errors += progress.num_errors;
+                if progress.dead_letter_stopped {
+                    self.dead_letter_tx = None;
+                }
+                if progress.too_many_errors {
+                    self.job_state.stream_not_ok(
+                        stream_name,
+                        "JobMonitor has reached maximum errors",
+                        progress.resume_index,
+                    )?;
+                    self.save_job_state().await?;
+                    return Err(JobRunnerError::TooManyErrors);
                 }
                 // TODO: num_processed_lines should be local, remove self.num_processed_items
                 /*
@@ -586,14 +634,16 @@ impl JobRunner {
                 job_handler.shutdown(&mut self).await?;
 
                 // only wait if everything is okay
-                source_stream_jh.await??;
+                let input_stats = source_stream_jh.await??;
                 let mut output_stats = Vec::new();
                 for (_name, join_handle) in self.data_output_handles {
                     let s = join_handle.await??;
                     output_stats.push(s);
                 }
                 self.job_state
-                    .stream_ok(stream_name, &self.config, output_stats)?;
+                    .stream_ok(&stream_name, &self.config, output_stats)?;
+                self.job_state
+                    .stream_input_stats(stream_name, &input_stats)?;
                 self.data_output_handles = Vec::new();
                 self.save_job_state().await?;
             }
@@ -601,6 +651,150 @@ impl JobRunner {
         Ok(self)
     }
 
+    /// Processes the items of [JobRunner::run_stream_handler], up to
+    /// [JobRunnerConfig::concurrency] at the same time.  A new item is started as soon as one is
+    /// done.  Items and errors of the input are numbered in the order they are received, from
+    /// `num_processed_items`.  Once processing has to stop no new item is started, the items
+    /// in flight are still waited for so the items done are always the first ones received.
+    /// The job state is updated with the returned [StreamProgress] once no item borrows the
+    /// JobRunner
+    async fn handle_items<I>(
+        &self,
+        stream_name: &str,
+        mut rx: DataSourceRx<I>,
+        job_handler: &dyn StreamHandler<I>,
+        index_start: usize,
+        jm_rx: &mut JobManagerRx,
+    ) -> Result<StreamProgress, JobRunnerError>
+    where
+        I: Debug + Send + Sync + 'static,
+    {
+        use etl_core::deps::futures_util::future::Either;
+        use etl_core::deps::futures_util::stream::{self, StreamExt};
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::task::Poll;
+        let concurrency = self.config.concurrency;
+        let stopping = AtomicBool::new(false);
+        let mut received_lines = 0_usize;
+        let mut index = self.num_processed_items;
+        let received = stream::poll_fn(|cx| match stopping.load(Ordering::Relaxed) {
+            true => Poll::Ready(None),
+            false => rx.poll_recv(cx),
+        })
+        .map(|message| {
+            let info = JobItemInfo::new((index, self.job_state.name()));
+            match message {
+                Ok(DataSourceMessage::Data {
+                    source,
+                    content: line,
+                }) => {
+                    let received_line = received_lines;
+                    received_lines += 1;
+                    if received_line < index_start {
+                        return Either::Right(future::ready(Received::Skipped {
+                            source,
+                            received_line,
+                        }));
+                    }
+                    index += 1;
+                    Either::Left(async move {
+                        let result = job_handler.process_item(info.clone(), line, self).await;
+                        Received::Item {
+                            info,
+                            source,
+                            received_line,
+                            result,
+                        }
+                    })
+                }
+                Err(error) => {
+                    index += 1;
+                    Either::Right(future::ready(Received::Error {
+                        info,
+                        error,
+                        received_line: received_lines,
+                    }))
+                }
+            }
+        });
+        let max = concurrency.max.max(1);
+        let mut results = match concurrency.ordered {
+            true => received.buffered(max).boxed(),
+            false => received.buffer_unordered(max).boxed(),
+        };
+        let mut progress = StreamProgress::default();
+        let mut last_source = Provenance::default();
+        while let Some(received) = results.next().await {
+            match received {
+                Received::Item {
+                    info,
+                    source,
+                    received_line,
+                    result,
+                } => {
+                    progress.num_items += 1;
+                    progress.resume_index = progress.resume_index.max(received_line + 1);
+                    match result {
+                        Ok(()) => progress.incr_ok(&source.key),
+                        Err(er) => {
+                            self.log_err(self.job_state.name(), Some(&info), er.to_string())
+                                .await;
+                            let raw = er
+                                .downcast_ref::<DataStoreError>()
+                                .and_then(|er| er.raw())
+                                .map(String::from);
+                            let dead_letter = DeadLetter::new(
+                                stream_name,
+                                &source,
+                                received_line,
+                                er.to_string(),
+                                raw,
+                            );
+                            progress.send_dead_letter(self, dead_letter).await;
+                            progress.num_errors += 1;
+                        }
+                    }
+                    last_source = source;
+                }
+                Received::Skipped {
+                    source,
+                    received_line,
+                } => {
+                    progress.resume_index = progress.resume_index.max(received_line + 1);
+                    last_source = source;
+                }
+                Received::Error {
+                    info,
+                    error,
+                    received_line,
+                } => {
+                    progress.num_items += 1;
+                    self.log_err(self.job_state.name(), Some(&info), error.to_string())
+                        .await;
+                    let dead_letter =
+                        DeadLetter::from_error(stream_name, &last_source, received_line, &error);
+                    progress.send_dead_letter(self, dead_letter).await;
+                    progress.num_errors += 1;
+                }
+            }
+            if progress.too_many_errors {
+                continue;
+            }
+            if job_manager_too_many_errors(jm_rx) {
+                progress.too_many_errors = true;
+            } else if self.config.max_errors <= self.num_process_item_errors + progress.num_errors
+            {
+                // notify JobManager that we are exiting
+                self.un_register().await?;
+                progress.too_many_errors = true;
+            }
+            if progress.too_many_errors {
+                stopping.store(true, Ordering::Relaxed);
+            }
+        }
+        Ok(progress)
+    }
+
     //TODO: it appears that after you press control-c to terminate program while it is running
     //the next time around it will think it already ran.. need to investigate
     pub async fn run_cmd(mut self, job_cmd: Box<dyn JobCommand>) -> Result<Self, JobRunnerError> {
@@ -643,7 +837,7 @@ impl JobRunner {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(crate = "serde")]
 pub struct JobItemInfo {
     pub index: usize,
@@ -663,6 +857,73 @@ impl JobItemInfo {
     }
 }
 
+/// Processes the messages from JobManager, returns true if it broadcasted that there are too many
+/// errors
+fn job_manager_too_many_errors(rx: &mut JobManagerRx) -> bool {
+    use crate::job_manager::Message::*;
+    // even when disconnected, allow the job to finish
+    while let Ok(message) = rx.try_recv() {
+        // message broadcasted from JobManager when there are too many errors and its better to
+        // stop the job completely.  max errors are defined in the configuration file
+        if let ToJobRunner(NotifyJobRunner::TooManyErrors) = message {
+            return true;
+        }
+    }
+    false
+}
+
+/// A message received by [JobRunner::run_stream_handler], with the result of processing it
+enum Received {
+    Item {
+        info: JobItemInfo,
+        source: Provenance,
+        received_line: usize,
+        result: anyhow::Result<()>,
+    },
+    /// skipped when resuming
+    Skipped {
+        source: Provenance,
+        received_line: usize,
+    },
+    Error {
+        info: JobItemInfo,
+        error: DataStoreError,
+        received_line: usize,
+    },
+}
+
+/// What [JobRunner::handle_items] did, recorded in the job state afterwards
+#[derive(Default)]
+struct StreamProgress {
+    /// the items and errors of the input which were given an index
+    num_items: usize,
+    /// the number of items received before the first one which is not done, the index to
+    /// resume from
+    resume_index: usize,
+    /// the items processed successfully by input, in the order the inputs were started
+    inputs: Vec<(String, usize)>,
+    num_ok: usize,
+    num_errors: usize,
+    dead_letter_stopped: bool,
+    too_many_errors: bool,
+}
+
+impl StreamProgress {
+    fn incr_ok(&mut self, source: &str) {
+        self.num_ok += 1;
+        match self.inputs.last_mut() {
+            Some((last, num_ok)) if last == source => *num_ok += 1,
+            _ => self.inputs.push((source.to_owned(), 1)),
+        }
+    }
+
+    async fn send_dead_letter(&mut self, job: &JobRunner, dead_letter: DeadLetter) {
+        if !self.dead_letter_stopped {
+            self.dead_letter_stopped = !job.try_send_dead_letter(dead_letter).await;
+        }
+    }
+}
+
 impl fmt::Debug for JobRunner {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("JobRunner")
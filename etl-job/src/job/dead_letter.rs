@@ -0,0 +1,61 @@
+use super::*;
+use etl_core::deps::chrono::{DateTime, Utc};
+use etl_core::deps::serde::Deserialize;
+
+/// A record which could not be decoded or transformed, sent to the dead letter output configured
+/// with [JobRunnerConfig::dead_letter] or [JobRunner::with_dead_letter].  Written as JSON lines
+/// these can be read back with the JsonDecoder to inspect and replay the `raw` records.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "serde")]
+pub struct DeadLetter {
+    /// name of the stream the error happened in
+    pub stream: String,
+    /// the input row the error was caused by, when it is not known the last record read by the
+    /// stream
+    pub source: Provenance,
+    /// position of the record in the stream
+    pub index: usize,
+    pub error: String,
+    /// the record as it was received, when the error kept it
+    pub raw: Option<String>,
+    pub datetime: DateTime<Utc>,
+}
+
+impl DeadLetter {
+    pub fn new<S: Into<String>, F: Into<Provenance>, E: Into<String>>(
+        stream: S,
+        source: F,
+        index: usize,
+        error: E,
+        raw: Option<String>,
+    ) -> Self {
+        DeadLetter {
+            stream: stream.into(),
+            source: source.into(),
+            index,
+            error: error.into(),
+            raw,
+            datetime: Utc::now(),
+        }
+    }
+
+    /// Uses the provenance and raw record kept by the error, if any
+    pub fn from_error<S: Into<String>, F: Into<Provenance>>(
+        stream: S,
+        source: F,
+        index: usize,
+        er: &DataStoreError,
+    ) -> Self {
+        let source = match er.provenance() {
+            Some(provenance) => provenance.clone(),
+            None => source.into(),
+        };
+        DeadLetter::new(
+            stream,
+            source,
+            index,
+            er.to_string(),
+            er.raw().map(String::from),
+        )
+    }
+}
@@ -60,3 +60,35 @@ where
         item: I,
     ) -> anyhow::Result<Option<TransformOutput<O>>>;
 }
+
+/// How many items a [TransformHandler] or [StreamHandler] works on at the same time
+#[derive(Clone, Copy, Debug)]
+pub struct Concurrency {
+    /// maximum number of items in flight, 1 processes the items one at a time
+    pub max: usize,
+    /// keep the input order in the output of a TransformDataSource and in the results recorded
+    /// by JobRunner::run_stream_handler, otherwise items are handled as soon as they are done
+    pub ordered: bool,
+}
+
+impl Concurrency {
+    pub fn new(max: usize) -> Self {
+        Concurrency {
+            max: max.max(1),
+            ordered: true,
+        }
+    }
+
+    pub fn unordered(self) -> Self {
+        Concurrency {
+            ordered: false,
+            ..self
+        }
+    }
+}
+
+impl Default for Concurrency {
+    fn default() -> Self {
+        Concurrency::new(1)
+    }
+}
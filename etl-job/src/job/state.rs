@@ -3,7 +3,7 @@ use crate::job::*;
 use anyhow;
 use etl_core::deps::chrono::Utc;
 use serde::{self, de::DeserializeOwned, Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub const JOB_STATE_EXT: &'static str = "job.json";
 
@@ -122,6 +122,18 @@ impl JobState {
         }
     }
 
+    /// Inputs of the named stream which were read in a previous run, see
+    /// [StepStreamStatus::processed_inputs]
+    pub fn processed_inputs(&self, stream_name: &str) -> HashSet<String> {
+        match self.step_history.get(stream_name) {
+            Some(JobStepDetails {
+                step: JobStepStatus::Stream(s),
+                ..
+            }) => s.processed_inputs(),
+            _ => HashSet::new(),
+        }
+    }
+
     fn add_command<C: Into<String>>(&mut self, cmd_name: C, cmd: StepCommandStatus) {
         let n = cmd_name.into();
         self.step_history.insert(
@@ -287,7 +299,8 @@ impl JobState {
                 step_index: _,
                 step: JobStepStatus::Stream(ref mut st),
             }) => {
-                st.complete(stats);
+                // outputs which wrote several files are recorded file by file
+                st.complete(stats.into_iter().flat_map(|s| s.into_parts()).collect());
             }
             Some(_) => panic!("Unexpectedly got a stream instead of a command"),
             None => {
@@ -297,6 +310,27 @@ impl JobState {
         Ok(())
     }
 
+    /// Records the stats of the input of a stream, once it is complete
+    pub fn stream_input_stats<N: Into<String>>(
+        &mut self,
+        name: N,
+        stats: &DataSourceStats,
+    ) -> anyhow::Result<()> {
+        let n = name.into();
+        match self.step_history.get_mut(&n) {
+            Some(JobStepDetails {
+                step: JobStepStatus::Stream(ref mut st),
+                ..
+            }) => {
+                st.set_duplicates(stats.duplicates);
+                Ok(())
+            }
+            _ => {
+                panic!("Attempted to stream_input_stats on a non existant stream");
+            }
+        }
+    }
+
     pub fn stream_not_ok<N: Into<String>, M: Into<String>>(
         &mut self,
         name: N,
@@ -1,7 +1,7 @@
 use super::*;
 use etl_core::deps::chrono::{DateTime, Utc};
 use etl_core::deps::serde::{self, Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "status", crate = "serde")]
@@ -15,6 +15,9 @@ pub enum StepStreamStatus {
         inputs: HashMap<String, FileStatus>,
         #[serde(default)]
         outputs: Vec<DataOutputStats>,
+        /// items dropped by the input because they were seen before
+        #[serde(default)]
+        duplicates: usize,
     },
     InProgress {
         started: DateTime<Utc>,
@@ -70,12 +73,25 @@ impl StepStreamStatus {
                     num_errors: *num_errors,
                     inputs: inputs.clone(),
                     outputs: stats,
+                    duplicates: 0,
                 }
             }
             _ => panic!("Can't set lines scanned on this StepStreamStatus."),
         }
     }
 
+    /// Records the duplicates counted by the input of a complete stream
+    pub fn set_duplicates(&mut self, count: usize) {
+        match self {
+            StepStreamStatus::Complete {
+                ref mut duplicates, ..
+            } => {
+                *duplicates = count;
+            }
+            _ => panic!("Can't set duplicates on this StepStreamStatus."),
+        }
+    }
+
     pub fn set_total_lines(&mut self, count: usize) {
         match self {
             StepStreamStatus::New => {
@@ -257,6 +273,38 @@ impl StepStreamStatus {
     }
 }
 
+impl StepStreamStatus {
+    /// Inputs which were read successfully.  When the stream did not complete, the input which
+    /// was started last is left out because it may have only been read partially
+    pub fn processed_inputs(&self) -> HashSet<String> {
+        let inputs = match self {
+            StepStreamStatus::New => return HashSet::new(),
+            StepStreamStatus::Complete { inputs, .. } => {
+                return inputs
+                    .iter()
+                    .filter(|(_, f)| matches!(f, FileStatus::Info { .. }))
+                    .map(|(name, _)| name.to_owned())
+                    .collect();
+            }
+            StepStreamStatus::InProgress { inputs, .. } => inputs,
+            StepStreamStatus::Error { inputs, .. } => inputs,
+        };
+        let mut started: Vec<(&DateTime<Utc>, &String)> = inputs
+            .iter()
+            .filter_map(|(name, f)| match f {
+                FileStatus::Info { started, .. } => Some((started, name)),
+                FileStatus::Error { .. } => None,
+            })
+            .collect();
+        started.sort();
+        started.pop();
+        started
+            .into_iter()
+            .map(|(_, name)| name.to_owned())
+            .collect()
+    }
+}
+
 impl Default for StepStreamStatus {
     fn default() -> Self {
         StepStreamStatus::New
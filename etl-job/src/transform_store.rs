@@ -4,7 +4,7 @@ use etl_core::datastore::*;
 use etl_core::datastore::error::*;
 use etl_core::deps::tokio::task::JoinHandle;
 use etl_core::deps::serde::{Serialize, de::DeserializeOwned};
-use crate::transform_store::handler::TransformHandler;
+use crate::transform_store::handler::{Concurrency, TransformHandler};
 
 /// Allows processing data using the TransformHandler trait to process and
 /// filter a stream, and output that as a new DataSource which can
@@ -14,6 +14,7 @@ pub struct TransformDataSource<I, O: Serialize + Debug + Send + Sync + 'static>
     pub input_ds: Box<dyn DataSource<I>>, // recv message
     pub transformer: Box<dyn TransformHandler<I, O>>,
     pub job_name: String,
+    pub concurrency: Concurrency,
 }
 
 impl<I, O> TransformDataSource<I, O>
@@ -30,10 +31,54 @@ where
             job_name: name.to_string(),
             transformer,
             input_ds: ds,
+            concurrency: Concurrency::default(),
+        }
+    }
+
+    /// Transforms several items at the same time, useful when the transformer makes api calls
+    pub fn with_concurrency(self, concurrency: Concurrency) -> Self {
+        TransformDataSource {
+            concurrency,
+            ..self
         }
     }
 }
 
+/// Transforms a single item, returning the messages to send downstream
+async fn transform<I, O>(
+    transformer: &dyn TransformHandler<I, O>,
+    job_name: &str,
+    index: usize,
+    source: Provenance,
+    item: I,
+) -> (Provenance, Vec<Result<DataSourceMessage<O>, DataStoreError>>)
+where
+    I: Serialize + DeserializeOwned + Debug + Send + Sync,
+    O: Serialize + Debug + Send + Sync,
+{
+    use crate::job::handler::TransformOutput::*;
+    // kept so a failing item can be reported, for example to a dead letter output
+    let raw = serde_json::to_string(&item).ok();
+    let messages = match transformer
+        .transform_item(JobItemInfo::new((index, job_name)), item)
+        .await
+    {
+        Ok(Some(Item(item_out))) => vec![Ok(DataSourceMessage::new(&source, item_out))],
+        // every element keeps the provenance of the input item
+        Ok(Some(List(items_out))) => items_out
+            .into_iter()
+            .map(|item_out| Ok(DataSourceMessage::new(&source, item_out)))
+            .collect(),
+        Ok(None) => Vec::new(),
+        Err(er) => vec![Err(DataStoreError::TransformerError {
+            job_name: job_name.to_owned(),
+            error: er.to_string(),
+        }
+        .at_record(&source, raw))],
+    };
+    (source, messages)
+}
+
 impl<
         I: Serialize + DeserializeOwned + Debug + Send + Sync + 'static,
         O: Serialize + DeserializeOwned + Debug + Send + Sync + 'static,
@@ -44,58 +89,48 @@ impl<
     }
 
     fn start_stream(self: Box<Self>) -> Result<DataSourceTask<O>, DataStoreError> {
+        use etl_core::deps::futures_util::future::{self, Either};
+        use etl_core::deps::futures_util::stream::{self, StreamExt};
         use tokio::sync::mpsc::channel;
         let (mut source_rx, source_stream_jh) = self.input_ds.start_stream()?;
-        let (tx, rx) = channel(1);
+        let (tx, rx) = channel(channels::buffer_size());
         let transformer = self.transformer;
         let job_name = self.job_name;
+        let concurrency = self.concurrency;
         let jh: JoinHandle<Result<DataSourceStats, DataStoreError>> = tokio::spawn(async move {
+            // counts the items and errors sent, a List counts once per element
             let mut lines_scanned = 0_usize;
-            loop {
-                use crate::job::handler::TransformOutput::*;
-                match source_rx.recv().await {
-                    Some(Ok(DataSourceMessage::Data {
-                        source,
-                        content: item,
-                    })) => {
-                        lines_scanned += 1;
-                        match transformer
-                            .transform_item(JobItemInfo::new((lines_scanned, &job_name)), item)
-                            .await
-                        {
-                            Ok(Some(Item(item_out))) => tx
-                                .send(Ok(DataSourceMessage::new(&job_name, item_out)))
-                                .await
-                                .map_err(|e| DataStoreError::send_error(&job_name, &source, e))?,
-                            Ok(Some(List(_vec))) => {
-                                panic!("Processing list not implemented")
-                            }
-                            Ok(None) => {}
-                            Err(er) => {
-                                tx.send(Err(DataStoreError::TransformerError {
-                                    job_name: job_name.to_owned(),
-                                    error: er.to_string(),
-                                }))
-                                .await
-                                .map_err(|e| DataStoreError::send_error(&job_name, &source, e))?;
-                            }
-                        };
-                    }
-                    Some(Err(val)) => {
-                        tx.send(Err(DataStoreError::Deserialize {
-                            message: val.to_string(),
-                            attempted_string: "Unknown".to_string(),
-                        }))
-                        .await
-                        .map_err(|e| DataStoreError::send_error(&job_name, "", e))?;
+            let mut index = 0_usize;
+            let transformer = transformer.as_ref();
+            let job_name = job_name.as_str();
+            // the index is given in input order, even when the items are not transformed in order
+            let transformed =
+                stream::poll_fn(|cx| source_rx.poll_recv(cx)).map(|message| match message {
+                    Ok(DataSourceMessage::Data { source, content }) => {
+                        index += 1;
+                        Either::Left(transform(transformer, job_name, index, source, content))
                     }
-                    None => break,
-                };
+                    // forwarded as is so the raw record is not lost
+                    Err(er) => Either::Right(future::ready((Provenance::default(), vec![Err(er)]))),
+                });
+            // max is a pub field, 0 would never poll a transform
+            let max = concurrency.max.max(1);
+            let mut transformed = match concurrency.ordered {
+                true => transformed.buffered(max).boxed(),
+                false => transformed.buffer_unordered(max).boxed(),
+            };
+            while let Some((source, messages)) = transformed.next().await {
+                for message in messages {
+                    lines_scanned += 1;
+                    tx.send(message).await.map_err(|e| {
+                        DataStoreError::send_error(job_name, source.to_string(), e)
+                    })?;
+                }
             }
 
             source_stream_jh.await??;
 
-            Ok(DataSourceStats { lines_scanned })
+            Ok(DataSourceStats::new(lines_scanned))
         });
         Ok((rx, jh))
     }
@@ -0,0 +1,77 @@
+use etl_core::decoder::avro::{derive_schema, read_datum, write_datum};
+use etl_core::deps::avro_schema::schema::{Record, Schema};
+use serde_json::json;
+
+/// the names of the records in the schema
+fn record_names(schema: &Schema, names: &mut Vec<String>) {
+    match schema {
+        Schema::Record(Record { name, fields, .. }) => {
+            names.push(name.clone());
+            for field in fields {
+                record_names(&field.schema, names);
+            }
+        }
+        Schema::Union(schemas) => {
+            for schema in schemas {
+                record_names(schema, names);
+            }
+        }
+        Schema::Array(items) => record_names(items, names),
+        _ => {}
+    }
+}
+
+#[test]
+fn test_write_datum_mismatch() {
+    let mut buf = Vec::new();
+    assert!(write_datum(&mut buf, &json!(null), &Schema::Null).is_ok());
+    assert!(write_datum(&mut buf, &json!(1), &Schema::Null).is_err());
+    assert!(write_datum(&mut buf, &json!(i32::MAX), &Schema::Int(None)).is_ok());
+    assert!(write_datum(&mut buf, &json!(1_i64 << 40), &Schema::Int(None)).is_err());
+    assert!(write_datum(&mut buf, &json!(1_i64 << 40), &Schema::Long(None)).is_ok());
+    let nullable_int = Schema::Union(vec![Schema::Null, Schema::Int(None)]);
+    assert!(write_datum(&mut buf, &json!(1_i64 << 40), &nullable_int).is_err());
+}
+
+#[test]
+fn test_read_datum_out_of_range() {
+    let mut long = Vec::new();
+    write_datum(&mut long, &json!(1_i64 << 40), &Schema::Long(None)).unwrap();
+    assert_eq!(
+        json!(1_i64 << 40),
+        read_datum(&mut &long[..], &Schema::Long(None)).unwrap()
+    );
+    assert!(read_datum(&mut &long[..], &Schema::Int(None)).is_err());
+    // an array block with a count of i64::MIN and a size of 0
+    let block = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, 0x00,
+    ];
+    let er = read_datum(
+        &mut &block[..],
+        &Schema::Array(Box::new(Schema::Long(None))),
+    )
+    .unwrap_err();
+    assert!(er.contains("invalid block count"), "{}", er);
+}
+
+#[test]
+fn test_derive_schema_record_names() {
+    let value = json!({
+        "billing": {"address": {"city": "a"}},
+        "shipping": {"address": {"city": "b"}, "items": [{"id": 1}]}
+    });
+    let mut names = Vec::new();
+    record_names(&derive_schema("order", &value), &mut names);
+    names.sort();
+    assert_eq!(
+        vec![
+            "order",
+            "order_billing",
+            "order_billing_address",
+            "order_shipping",
+            "order_shipping_address",
+            "order_shipping_items"
+        ],
+        names
+    );
+}
@@ -0,0 +1,72 @@
+use common::*;
+use etl_core::batch::Batcher;
+use etl_core::datastore::*;
+use etl_core::deps::*;
+use std::time::Duration;
+
+mod common;
+
+/// the ids of each batch, errors are an empty batch
+async fn batch_ids(batcher: Batcher<TestItem>) -> Vec<Vec<usize>> {
+    let (batches, _) = collect(Box::new(batcher)).await;
+    batches
+        .into_iter()
+        .map(|batch| match batch {
+            Ok((_, batch)) => batch.into_iter().map(|item| item.id).collect(),
+            Err(_) => Vec::new(),
+        })
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_batch_max_items() {
+    let batches = batch_ids(Batcher::windowed(items(1..=8, 4)).with_max_items(3)).await;
+    // the error of line 4 is sent as it comes, and the batch goes on
+    assert_eq!(vec![vec![1, 2, 3], vec![], vec![5, 6, 7], vec![8]], batches);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_batch_max_bytes() {
+    // {"id":N} is 8 bytes for one digit and 9 for two
+    let batcher = Batcher::windowed(items(1..=12, 4))
+        .with_max_bytes(17)
+        .with_max_items(5);
+    let batches = batch_ids(batcher).await;
+    assert_eq!(
+        vec![
+            vec![1, 2],
+            vec![],
+            vec![3, 5],
+            vec![6, 7],
+            vec![8, 9],
+            vec![10],
+            vec![11],
+            vec![12]
+        ],
+        batches
+    );
+    // whichever limit comes first
+    let batcher = Batcher::new(items(1..=3, 4), |item, _| item.id == 3).with_max_bytes(1000);
+    assert_eq!(vec![vec![1, 2], vec![3]], batch_ids(batcher).await);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_batch_max_wait() {
+    let (tx, rx) = tokio::sync::mpsc::channel(1);
+    let send = tokio::spawn(async move {
+        for id in 1..=5 {
+            if id == 4 {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+            }
+            tx.send(Ok(DataSourceMessage::new("test", TestItem { id })))
+                .await
+                .unwrap();
+        }
+    });
+    let batcher = Batcher::windowed(Box::new(rx) as Box<dyn DataSource<TestItem>>)
+        .with_max_wait(Duration::from_millis(100))
+        .with_max_items(10);
+    let batches = batch_ids(batcher).await;
+    send.await.unwrap();
+    assert_eq!(vec![vec![1, 2, 3], vec![4, 5]], batches);
+}
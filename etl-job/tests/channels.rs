@@ -0,0 +1,195 @@
+use etl_core::datastore::channels::*;
+use etl_core::datastore::*;
+use etl_core::decoder::csv::*;
+use etl_core::deps::bytes::Bytes;
+use etl_core::deps::serde::{Deserialize, Serialize};
+use etl_core::deps::*;
+use etl_core::splitter::DuplicateDataSource;
+use etl_core::transformer::Transformer;
+use etl_job::job::state::*;
+use etl_job::job::stream::*;
+use etl_job::job::*;
+use etl_job::job_manager::*;
+use std::time::Duration;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+struct TestCsv {
+    index: String,
+    words: String,
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_chunked_csv_decoder() {
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let config = ChannelConfig {
+        buffer_size: 8,
+        chunk_size: 4,
+    };
+    let jr = JobRunner::create(
+        "test_channels_id",
+        "test_channels",
+        &jm_handle,
+        JobRunnerConfig {
+            channels: config,
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner");
+    // 11 records do not fill the last chunk
+    let csv = std::iter::once(String::from("index,words"))
+        .chain((1..=11).map(|idx| match idx {
+            7 => format!("{},stuff,\"should error\"", idx),
+            _ => format!("{},stuff", idx),
+        }))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let job_state = jr
+        .run_stream::<TestCsv>(
+            "chunked csv",
+            CsvDecoder::new(CsvReadOptions::default(), Box::new(csv)),
+            Box::new(mock::MockJsonDataOutput::default()),
+        )
+        .await
+        .expect("Failed run_stream")
+        .complete()
+        .await
+        .expect("Fail completing");
+    match job_state.step_history.get("chunked csv") {
+        Some(JobStepDetails {
+            step:
+                JobStepStatus::Stream(StepStreamStatus::Complete {
+                    total_lines_scanned,
+                    num_errors,
+                    ..
+                }),
+            ..
+        }) => {
+            assert_eq!(10, *total_lines_scanned);
+            assert_eq!(1, *num_errors);
+        }
+        _ => panic!("chunked csv is not showing as completed"),
+    }
+    // the config only applies to the streams of the JobRunner
+    assert_eq!(ChannelConfig::default(), ChannelConfig::current());
+    jm_handle
+        .shutdown()
+        .await
+        .expect("failure shutting down JobManager");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_chunked_csv_decoder_slow_source() {
+    let config = ChannelConfig {
+        buffer_size: 2,
+        chunk_size: 4,
+    };
+    config
+        .scope(async {
+            let (tx, rx) = tokio::sync::mpsc::channel(1);
+            let source: Box<dyn DataSource<Bytes>> = Box::new(rx);
+            let decoder: Box<dyn DataSource<TestCsv>> =
+                CsvDecoder::new(CsvReadOptions::default(), source);
+            let (mut chunk_rx, jh) = decoder
+                .start_chunk_stream()
+                .expect("Could not start the stream");
+            for line in ["index,words", "1,stuff"] {
+                tx.send(Ok(DataSourceMessage::new("slow", Bytes::from(line))))
+                    .await
+                    .unwrap();
+            }
+            // the source is still open, the decoder does not wait for it to fill the chunk
+            let chunk = tokio::time::timeout(Duration::from_secs(5), chunk_rx.recv_chunk())
+                .await
+                .expect("The record was held back")
+                .expect("The stream ended");
+            assert_eq!(1, chunk.len());
+            drop(tx);
+            assert!(chunk_rx.recv_chunk().await.is_none());
+            jh.await.unwrap().expect("CsvDecoder failed");
+        })
+        .await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_chunked_transformer() {
+    let config = ChannelConfig {
+        buffer_size: 4,
+        chunk_size: 4,
+    };
+    let chunks = config
+        .scope(async {
+            // every item is ready before the transformer starts, so it never waits for its input
+            let (mut tx, rx) = chunk_channel();
+            for idx in 1..=11 {
+                let item = TestCsv {
+                    index: idx.to_string(),
+                    words: String::from("stuff"),
+                };
+                tx.send(Ok(DataSourceMessage::new("csv", item)))
+                    .await
+                    .unwrap();
+            }
+            tx.flush().await.unwrap();
+            drop(tx);
+            let input: Box<dyn DataSource<TestCsv>> = Box::new(DuplicateDataSource {
+                rx,
+                name: String::from("csv"),
+            });
+            let transformer = Transformer::new(input, |item: TestCsv| Ok(Some(item.index)));
+            let (mut rx, jh) = Box::new(transformer)
+                .start_chunk_stream()
+                .expect("Could not start the stream");
+            let mut chunks = Vec::new();
+            while let Some(chunk) = rx.recv_chunk().await {
+                chunks.push(
+                    chunk
+                        .into_iter()
+                        .map(|message| match message {
+                            Ok(DataSourceMessage::Data { content, .. }) => content,
+                            Err(er) => panic!("{}", er),
+                        })
+                        .collect::<Vec<_>>(),
+                );
+            }
+            jh.await.unwrap().expect("Transformer failed");
+            chunks
+        })
+        .await;
+    // the chunks are passed on as they are
+    assert_eq!(
+        vec![
+            vec!["1", "2", "3", "4"],
+            vec!["5", "6", "7", "8"],
+            vec!["9", "10", "11"]
+        ],
+        chunks
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_chunk_channel() {
+    let config = ChannelConfig {
+        buffer_size: 2,
+        chunk_size: 3,
+    };
+    let (mut chunk_tx, mut chunk_rx) = config.sync_scope(chunk_channel::<usize>);
+    let jh = tokio::task::spawn_blocking(move || {
+        for idx in 0..7 {
+            chunk_tx.blocking_send(idx).unwrap();
+        }
+        chunk_tx.blocking_flush().unwrap();
+    });
+    let mut received = Vec::new();
+    while let Some(chunk) = chunk_rx.recv_chunk().await {
+        received.push(chunk);
+    }
+    jh.await.unwrap();
+    assert_eq!(vec![vec![0, 1, 2], vec![3, 4, 5], vec![6]], received);
+}
@@ -0,0 +1,63 @@
+use common::*;
+use etl_core::combine::{Concat, Merge, OrderedMerge};
+use etl_core::deps::*;
+
+mod common;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_concat() {
+    let ds = Concat::new(vec![items([3, 1], 0), items([], 0), items([2, 0, 5], 0)]);
+    let (items, stats) = collect(Box::new(ds)).await;
+    assert_eq!(vec![3, 1, 2, 5], ids(&items));
+    assert!(items[3].is_err());
+    assert_eq!(5, stats.unwrap().lines_scanned);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_merge() {
+    let ds = Merge::new(vec![items(1..=50, 0), items([0], 0), items(101..=150, 0)]);
+    let (items, stats) = collect(Box::new(ds)).await;
+    assert_eq!(101, items.len());
+    assert_eq!(1, items.iter().filter(|item| item.is_err()).count());
+    // the items of each input keep their order
+    let ids = ids(&items);
+    let first = ids
+        .iter()
+        .filter(|id| **id <= 50)
+        .copied()
+        .collect::<Vec<_>>();
+    let second = ids
+        .iter()
+        .filter(|id| **id > 100)
+        .copied()
+        .collect::<Vec<_>>();
+    assert_eq!((1..=50).collect::<Vec<_>>(), first);
+    assert_eq!((101..=150).collect::<Vec<_>>(), second);
+    assert_eq!(101, stats.unwrap().lines_scanned);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_ordered_merge() {
+    let ds = OrderedMerge::new(
+        vec![
+            items([1, 4, 4, 9], 0),
+            items([2, 0, 4, 10], 0),
+            items([3], 0),
+        ],
+        |item: &TestItem| item.id,
+    );
+    let (merged, stats) = collect(Box::new(ds)).await;
+    assert_eq!(vec![1, 2, 3, 4, 4, 4, 9, 10], ids(&merged));
+    assert_eq!(1, merged.iter().filter(|item| item.is_err()).count());
+    assert_eq!(9, stats.unwrap().lines_scanned);
+
+    let ds = OrderedMerge::new(
+        vec![items([1, 5], 0), items([2, 3, 2], 0)],
+        |item: &TestItem| item.id,
+    );
+    let (merged, stats) = collect(Box::new(ds)).await;
+    assert_eq!(vec![1, 2, 3], ids(&merged));
+    let er = merged.last().unwrap().clone().unwrap_err();
+    assert!(er.contains("OrderedMerge input 1 is not sorted"), "{}", er);
+    assert!(stats.is_err());
+}
@@ -1 +1,61 @@
+//! Fixtures shared by the integration tests, not every test uses all of them
+#![allow(dead_code)]
+use etl_core::datastore::*;
+use etl_core::deps::serde::{Deserialize, Serialize};
+use etl_core::deps::*;
+use etl_core::ext::DataSourceExt;
+
 pub mod test_data;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "serde")]
+pub struct TestItem {
+    pub id: usize,
+}
+
+/// One JSON line per id, the id `poisoned` is written as a string so it fails to decode
+pub fn json_lines<I: IntoIterator<Item = usize>>(ids: I, poisoned: usize) -> String {
+    ids.into_iter()
+        .map(|id| match id {
+            id if id == poisoned => format!("{{\"id\": \"{}\"}}", id),
+            _ => format!("{{\"id\": {}}}", id),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The decoded [json_lines], with an error in place of `poisoned`
+pub fn items<I: IntoIterator<Item = usize>>(
+    ids: I,
+    poisoned: usize,
+) -> Box<dyn DataSource<TestItem>> {
+    json_lines(ids, poisoned).decode_json::<TestItem>()
+}
+
+/// An item with its provenance, or the error as a string
+pub type Message<T> = Result<(Provenance, T), String>;
+
+pub type Collected<T> = (Vec<Message<T>>, Result<DataSourceStats, String>);
+
+/// Every message of the source with the errors as strings, and the result of the source
+pub async fn collect<T: std::fmt::Debug + Send + 'static>(
+    ds: Box<dyn DataSource<T>>,
+) -> Collected<T> {
+    let (mut rx, jh) = ds.start_stream().expect("Could not start the stream");
+    let mut items = Vec::new();
+    while let Some(message) = rx.recv().await {
+        items.push(match message {
+            Ok(DataSourceMessage::Data { source, content }) => Ok((source, content)),
+            Err(er) => Err(er.to_string()),
+        });
+    }
+    (items, jh.await.unwrap().map_err(|er| er.to_string()))
+}
+
+/// The ids of the items, without the errors
+pub fn ids(items: &[Message<TestItem>]) -> Vec<usize> {
+    items
+        .iter()
+        .filter_map(|item| item.as_ref().ok().map(|(_, item)| item.id))
+        .collect()
+}
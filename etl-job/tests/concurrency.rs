@@ -0,0 +1,348 @@
+use etl_core::datastore::enumerate::*;
+use etl_core::datastore::mock::MockJsonDataSource;
+use etl_core::datastore::*;
+use etl_core::deps::anyhow;
+use etl_core::deps::async_trait;
+use etl_core::deps::*;
+use etl_job::job::error::JobRunnerError;
+use etl_job::job::handler::*;
+use etl_job::job::state::*;
+use etl_job::job::stream::*;
+use etl_job::job::*;
+use etl_job::job_manager::*;
+use etl_job::transform_store::TransformDataSource;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Keeps track of how many items are processed at the same time
+#[derive(Default)]
+struct InFlight {
+    current: AtomicUsize,
+    max: AtomicUsize,
+}
+
+impl InFlight {
+    /// later ids are processed faster, so they finish first when processed concurrently
+    async fn process(&self, id: usize) {
+        let current = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max.fetch_max(current, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(10 * (10 - id as u64))).await;
+        self.current.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn ids() -> Box<dyn DataSource<usize>> {
+    Box::new(EnumerateStream::with_max(
+        "ids",
+        8,
+        (),
+        |_, idx| Ok(idx + 1),
+    ))
+}
+
+struct SlowTransformer(Arc<InFlight>);
+
+#[async_trait]
+impl TransformHandler<usize, usize> for SlowTransformer {
+    async fn transform_item(
+        &self,
+        _: JobItemInfo,
+        id: usize,
+    ) -> anyhow::Result<Option<TransformOutput<usize>>> {
+        self.0.process(id).await;
+        Ok(Some(TransformOutput::Item(id)))
+    }
+}
+
+async fn transformed(concurrency: Concurrency) -> (Vec<usize>, usize) {
+    let in_flight = Arc::new(InFlight::default());
+    let ds: Box<dyn DataSource<usize>> = Box::new(
+        TransformDataSource::new("slow", ids(), Box::new(SlowTransformer(in_flight.clone())))
+            .with_concurrency(concurrency),
+    );
+    let (mut rx, jh) = ds.start_stream().expect("Could not start the stream");
+    let mut items = Vec::new();
+    while let Some(Ok(DataSourceMessage::Data { content, .. })) = rx.recv().await {
+        items.push(content);
+    }
+    let stats = jh.await.unwrap().expect("Transform failed");
+    assert_eq!(8, stats.lines_scanned);
+    (items, in_flight.max.load(Ordering::SeqCst))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_concurrent_transform() {
+    let (items, max) = transformed(Concurrency::new(4)).await;
+    assert_eq!((1..=8).collect::<Vec<_>>(), items);
+    assert!(max > 1 && max <= 4);
+
+    let (mut items, max) = transformed(Concurrency::new(4).unordered()).await;
+    assert_ne!(1, items[0]);
+    assert!(max > 1 && max <= 4);
+    items.sort();
+    assert_eq!((1..=8).collect::<Vec<_>>(), items);
+
+    let (items, max) = transformed(Concurrency::default()).await;
+    assert_eq!((1..=8).collect::<Vec<_>>(), items);
+    assert_eq!(1, max);
+}
+
+struct SlowHandler {
+    in_flight: Arc<InFlight>,
+    indices: Arc<Mutex<Vec<usize>>>,
+    resume: usize,
+}
+
+#[async_trait]
+impl StreamHandler<usize> for SlowHandler {
+    async fn init(&mut self, _: &JobRunner) -> anyhow::Result<JobRunnerAction> {
+        Ok(JobRunnerAction::Resume { index: self.resume })
+    }
+
+    async fn process_item(
+        &self,
+        info: JobItemInfo,
+        id: usize,
+        _: &JobRunner,
+    ) -> anyhow::Result<()> {
+        self.in_flight.process(id).await;
+        self.indices.lock().unwrap().push(info.index);
+        match id {
+            5 => Err(anyhow::anyhow!("could not process 5")),
+            _ => Ok(()),
+        }
+    }
+
+    async fn shutdown(self: Box<Self>, _: &mut JobRunner) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_concurrent_stream_handler() {
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let jr = JobRunner::create(
+        "test_concurrency_id",
+        "test_concurrency",
+        &jm_handle,
+        JobRunnerConfig {
+            concurrency: Concurrency::new(3),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner");
+    let in_flight = Arc::new(InFlight::default());
+    let indices = Arc::new(Mutex::new(Vec::new()));
+    let next_indices = Arc::new(Mutex::new(Vec::new()));
+    let job_state = jr
+        .run_stream_handler(
+            "slow handler",
+            ids(),
+            Box::new(SlowHandler {
+                in_flight: in_flight.clone(),
+                indices: indices.clone(),
+                resume: 2,
+            }),
+        )
+        .await
+        .expect("Failed run_stream_handler")
+        .run_stream_handler(
+            "next slow handler",
+            ids(),
+            Box::new(SlowHandler {
+                in_flight: Arc::new(InFlight::default()),
+                indices: next_indices.clone(),
+                resume: 0,
+            }),
+        )
+        .await
+        .expect("Failed run_stream_handler")
+        .complete()
+        .await
+        .expect("Fail completing");
+    match job_state.step_history.get("slow handler") {
+        Some(JobStepDetails {
+            step:
+                JobStepStatus::Stream(StepStreamStatus::Complete {
+                    total_lines_scanned,
+                    num_errors,
+                    ..
+                }),
+            ..
+        }) => {
+            // the first 2 items are skipped
+            assert_eq!(5, *total_lines_scanned);
+            assert_eq!(1, *num_errors);
+        }
+        _ => panic!("slow handler is not showing as completed"),
+    }
+    let max = in_flight.max.load(Ordering::SeqCst);
+    assert!(max > 1 && max <= 3);
+    // every item has its own index
+    let mut indices = indices.lock().unwrap().clone();
+    indices.sort();
+    assert_eq!((0..6).collect::<Vec<_>>(), indices);
+    // the failed item used an index too, so the next stream goes on from 6
+    let mut next_indices = next_indices.lock().unwrap().clone();
+    next_indices.sort();
+    assert_eq!((6..14).collect::<Vec<_>>(), next_indices);
+    jm_handle
+        .shutdown()
+        .await
+        .expect("failure shutting down JobManager");
+}
+
+/// the first item is much slower than the others
+struct FirstSlowHandler {
+    done: Arc<Mutex<Vec<usize>>>,
+}
+
+#[async_trait]
+impl StreamHandler<usize> for FirstSlowHandler {
+    async fn init(&mut self, _: &JobRunner) -> anyhow::Result<JobRunnerAction> {
+        Ok(JobRunnerAction::Start)
+    }
+
+    async fn process_item(&self, _: JobItemInfo, id: usize, _: &JobRunner) -> anyhow::Result<()> {
+        let wait = match id {
+            1 => 300,
+            _ => 10,
+        };
+        tokio::time::sleep(Duration::from_millis(wait)).await;
+        self.done.lock().unwrap().push(id);
+        Ok(())
+    }
+
+    async fn shutdown(self: Box<Self>, _: &mut JobRunner) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_stream_handler_sliding_window() {
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let done = Arc::new(Mutex::new(Vec::new()));
+    let jr = JobRunner::create(
+        "test_sliding_window_id",
+        "test_sliding_window",
+        &jm_handle,
+        JobRunnerConfig {
+            concurrency: Concurrency::new(2).unordered(),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner")
+    .run_stream_handler(
+        "first slow",
+        ids(),
+        Box::new(FirstSlowHandler { done: done.clone() }),
+    )
+    .await
+    .expect("Failed run_stream_handler");
+    // the other items go through the second slot while the first one is processed
+    assert_eq!(vec![2, 3, 4, 5, 6, 7, 8, 1], *done.lock().unwrap());
+    jr.complete().await.expect("Fail completing");
+    jm_handle
+        .shutdown()
+        .await
+        .expect("failure shutting down JobManager");
+}
+
+/// the first item is slow and the second one fails
+struct SecondFailsHandler {
+    done: Arc<Mutex<Vec<usize>>>,
+}
+
+#[async_trait]
+impl StreamHandler<usize> for SecondFailsHandler {
+    async fn init(&mut self, _: &JobRunner) -> anyhow::Result<JobRunnerAction> {
+        Ok(JobRunnerAction::Start)
+    }
+
+    async fn process_item(&self, _: JobItemInfo, id: usize, _: &JobRunner) -> anyhow::Result<()> {
+        match id {
+            1 => tokio::time::sleep(Duration::from_millis(300)).await,
+            2 => return Err(anyhow::anyhow!("could not process 2")),
+            _ => {}
+        }
+        self.done.lock().unwrap().push(id);
+        Ok(())
+    }
+
+    async fn shutdown(self: Box<Self>, _: &mut JobRunner) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_stream_handler_stops_after_items_in_flight() {
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let files = Arc::new(Mutex::new(RefCell::new(HashMap::new())));
+    let done = Arc::new(Mutex::new(Vec::new()));
+    let result = JobRunner::create(
+        "test_stop_id",
+        "test_stop",
+        &jm_handle,
+        JobRunnerConfig {
+            max_errors: 1,
+            ds: Box::new(MockJsonDataSource {
+                lines: Vec::new(),
+                files: files.clone(),
+            }),
+            concurrency: Concurrency::new(2).unordered(),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner")
+    .run_stream_handler(
+        "second fails",
+        ids(),
+        Box::new(SecondFailsHandler { done: done.clone() }),
+    )
+    .await;
+    assert_eq!(JobRunnerError::TooManyErrors, result.unwrap_err());
+    // no item is started after the error, the one in flight is finished
+    assert_eq!(vec![1], *done.lock().unwrap());
+    // the job state is the only file of the store
+    let job_state: JobState = {
+        let files = files.lock().unwrap();
+        let files = files.borrow();
+        let content = files.values().next().expect("The job state was not saved");
+        serde_json::from_str(content).expect("Could not read the job state")
+    };
+    match job_state.step_history.get("second fails") {
+        Some(JobStepDetails {
+            step: JobStepStatus::Stream(StepStreamStatus::Error { last_index, .. }),
+            ..
+        }) => {
+            // both items received are done, so a resume starts with the third one
+            assert_eq!(2, *last_index);
+        }
+        _ => panic!("second fails is not showing as failed"),
+    }
+    jm_handle
+        .shutdown()
+        .await
+        .expect("failure shutting down JobManager");
+}
@@ -0,0 +1,67 @@
+use common::*;
+use etl_core::datastore::*;
+use etl_core::deps::*;
+use etl_core::ext::DataSourceExt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+mod common;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_fluent_pipeline() {
+    let inspected = Arc::new(AtomicUsize::new(0));
+    let counter = inspected.clone();
+    let ds = items(1..=12, 4)
+        .skip(1)
+        .inspect(move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        })
+        .filter(|item| item.id % 2 == 0)
+        .map(|item| item.id * 10)
+        .flat_map(|id| vec![id, id + 1])
+        .filter_map(|id| match id % 3 {
+            0 => None,
+            _ => Some(id.to_string()),
+        })
+        .take(4);
+    let (mut items, _) = collect(ds).await;
+    // the error of line 4 is passed along in order, and does not count towards the take
+    assert_eq!(5, items.len());
+    assert!(items.remove(1).is_err());
+    let items: Vec<(Provenance, String)> = items.into_iter().map(Result::unwrap).collect();
+    assert_eq!(
+        vec!["20", "61", "80", "100"],
+        items
+            .iter()
+            .map(|(_, item)| item.as_str())
+            .collect::<Vec<_>>()
+    );
+    // the items keep the line they were read from
+    assert_eq!(Some(2), items[0].0.line);
+    assert_eq!(Some(6), items[1].0.line);
+    assert_eq!(Some(10), items[3].0.line);
+    assert!(inspected.load(Ordering::Relaxed) >= 5);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_batch() {
+    let (batches, _) = collect(items(1..=7, 0).batch(|_, batch| batch.len() == 3)).await;
+    let sizes = batches
+        .into_iter()
+        .map(|batch| batch.unwrap().1.len())
+        .collect::<Vec<_>>();
+    assert_eq!(vec![3, 3, 1], sizes);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_take_zero() {
+    // the input never sends anything, take(0) must not wait for it
+    let (tx, rx) = tokio::sync::mpsc::channel::<DataSourceMessage<TestItem>>(1);
+    let ds = (Box::new(rx) as Box<dyn DataSource<TestItem>>).take(0);
+    let (items, _) = tokio::time::timeout(Duration::from_secs(5), collect(ds))
+        .await
+        .expect("take(0) waited for the input");
+    assert!(items.is_empty());
+    drop(tx);
+}
@@ -152,3 +152,204 @@ async fn test_basic_json_decoder() {
     }
     jm_handle.shutdown().await.expect("failure waiting for jm");
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_multi_line_csv_decoder() {
+    use etl_core::decoder::csv::*;
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let jr = JobRunner::create(
+        "test_multi_line_csv_id",
+        "test_multi_line_csv",
+        &jm_handle,
+        JobRunnerConfig {
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner");
+    // the quoted field of the second record spans three lines
+    let csv_str = String::from(
+        "index,words\n1,stuff\n2,\"words on\nmultiple\nlines\"\n3,stuff,should error\n4,stuff",
+    );
+    let (mut rx, _) =
+        CsvDecoder::new::<TestCsv>(CsvReadOptions::default(), Box::new(csv_str.clone()))
+            .start_stream()
+            .expect("Could not start the decoder");
+    let mut words = Vec::new();
+    let mut errors = Vec::new();
+    while let Some(message) = rx.recv().await {
+        match message {
+            Ok(DataSourceMessage::Data { content, .. }) => words.push(content.words),
+            Err(er) => errors.push(er.to_string()),
+        }
+    }
+    assert_eq!(vec!["stuff", "words on\nmultiple\nlines", "stuff"], words);
+    // the record after the multi line field is on line 6 of the source
+    assert_eq!(1, errors.len());
+    assert!(errors[0].contains("(at `String:6@48`)"), "{}", errors[0]);
+    let job_state = jr
+        .run_stream::<TestCsv>(
+            "multi line csv",
+            CsvDecoder::new(CsvReadOptions::default(), Box::new(csv_str)),
+            Box::new(mock::MockJsonDataOutput::default()),
+        )
+        .await
+        .expect("Failed run_stream")
+        .complete()
+        .await
+        .expect("Fail completing");
+    if let Some(JobStepDetails {
+        step:
+            JobStepStatus::Stream(StepStreamStatus::Complete {
+                total_lines_scanned,
+                num_errors,
+                ..
+            }),
+        ..
+    }) = job_state.step_history.get("multi line csv")
+    {
+        assert_eq!(3, *total_lines_scanned);
+        assert_eq!(1, *num_errors);
+    } else {
+        panic!("multi line csv is not showing as completed");
+    }
+    jm_handle.shutdown().await.expect("failure waiting for jm");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_json_array_decoder() {
+    use etl_core::decoder::json_array::*;
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let jr = JobRunner::create(
+        "test_simple_pipeline_id",
+        "test_simple_pipeline",
+        &jm_handle,
+        JobRunnerConfig {
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner");
+    // pretty printed with the array nested inside the document, the third element is missing
+    // the words field
+    let json_str = String::from(
+        r#"{
+  "meta": { "skip": ["]", "}"], "count": 4 },
+  "data": {
+    "items": [
+      { "index": "1", "words": "stuff" },
+      {
+        "index": "2",
+        "words": "words with \"quotes\" and [brackets]"
+      },
+      { "index": "3" },
+      { "index": "4", "words": "stuff" }
+    ]
+  }
+}"#,
+    );
+    let job_state = jr
+        .run_stream::<TestCsv>(
+            "json array",
+            JsonArrayDecoder::with_pointer("/data/items", Box::new(json_str)),
+            Box::new(mock::MockJsonDataOutput::default()),
+        )
+        .await
+        .expect("Failed run_stream")
+        .complete()
+        .await
+        .expect("Fail completing");
+    if let Some(JobStepDetails {
+        step:
+            JobStepStatus::Stream(StepStreamStatus::Complete {
+                total_lines_scanned,
+                num_errors,
+                ..
+            }),
+        ..
+    }) = job_state.step_history.get("json array")
+    {
+        assert_eq!(3, *total_lines_scanned);
+        assert_eq!(1, *num_errors);
+    } else {
+        panic!("json array is not showing as completed");
+    }
+    jm_handle.shutdown().await.expect("failure waiting for jm");
+}
+
+/// the index of every record, or the error
+async fn json_array_indexes(source: Box<dyn DataSource<TestCsv>>) -> Vec<Result<String, String>> {
+    let (mut rx, jh) = source.start_stream().expect("Could not start the stream");
+    let mut indexes = Vec::new();
+    while let Some(message) = rx.recv().await {
+        indexes.push(match message {
+            Ok(DataSourceMessage::Data { content, .. }) => Ok(content.index),
+            Err(er) => Err(er.to_string()),
+        });
+    }
+    jh.await.unwrap().expect("JsonArrayDecoder failed");
+    indexes
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_json_array_decoder_concatenated_objects() {
+    use etl_core::decoder::json_array::*;
+    let json_str = String::from(
+        r#"{
+  "index": "1",
+  "words": "stuff"
+}
+{
+  "index": "2",
+  "words": "more {stuff}"
+}
+{ "index": "3", "words": "stuff" }"#,
+    );
+    let indexes = json_array_indexes(JsonArrayDecoder::new(Box::new(json_str))).await;
+    assert_eq!(
+        vec![
+            Ok("1".to_string()),
+            Ok("2".to_string()),
+            Ok("3".to_string())
+        ],
+        indexes
+    );
+    // a scan error skips the rest of the document
+    let json_str =
+        String::from("{ \"index\": \"1\", \"words\": \"stuff\" }\n}\n{ \"index\": \"2\" }");
+    let indexes = json_array_indexes(JsonArrayDecoder::new(Box::new(json_str))).await;
+    assert_eq!(2, indexes.len());
+    assert_eq!(Ok("1".to_string()), indexes[0]);
+    assert!(indexes[1].is_err());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_json_array_decoder_top_level_array() {
+    use etl_core::decoder::json_array::*;
+    let json_str = String::from(
+        r#"[
+  { "index": "1", "words": "stuff" },
+  {
+    "index": "2",
+    "words": "stuff"
+  },
+  { "index": "3" }
+]"#,
+    );
+    let indexes = json_array_indexes(JsonArrayDecoder::new(Box::new(json_str))).await;
+    assert_eq!(3, indexes.len());
+    assert_eq!(Ok("1".to_string()), indexes[0]);
+    assert_eq!(Ok("2".to_string()), indexes[1]);
+    // missing the words field
+    assert!(indexes[2].is_err());
+}
@@ -138,3 +138,152 @@ async fn test_basic_fs_csv_decoder() {
     }
     jm_handle.shutdown().await.expect("failure waiting for jm");
 }
+
+async fn create_discovery_test_dir() -> String {
+    let home = std::env::temp_dir().join("etl-job-decoder-fs-discover");
+    let _ = tokio::fs::remove_dir_all(&home).await;
+    tokio::fs::create_dir_all(home.join("nested"))
+        .await
+        .expect("Could not create test dir");
+    for (path, line) in [
+        ("b.csv", "2,b"),
+        ("a.csv", "1,a"),
+        ("nested/c.csv", "3,c"),
+        ("notes.txt", "not,csv"),
+    ] {
+        tokio::fs::write(home.join(path), format!("index,words\n{}\n", line))
+            .await
+            .expect("Could not write test file");
+    }
+    home.to_string_lossy().to_string()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_local_fs_discover() {
+    use fs::{FileDiscovery, Regex};
+    let home = create_discovery_test_dir().await;
+    let files = LocalFs {
+        home: home.clone(),
+        discover: Some(FileDiscovery {
+            globs: vec![String::from("**/*.csv")],
+            recursive: true,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+    .list_files()
+    .await
+    .expect("Could not list files");
+    assert_eq!(vec!["a.csv", "b.csv", "nested/c.csv"], files);
+
+    let files = LocalFs {
+        home: home.clone(),
+        discover: Some(FileDiscovery {
+            globs: vec![String::from("*.csv")],
+            recursive: true,
+            regex: Some(Regex::new("^[bc]").unwrap()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+    .list_files()
+    .await
+    .expect("Could not list files");
+    assert_eq!(vec!["b.csv"], files);
+
+    // a.csv was read completely, b.csv was the last file read when the stream failed
+    let jrc = JobRunnerConfig::default();
+    let mut job_state = JobState::new("decoder_fs", "decoder_fs_discover");
+    job_state
+        .start_new_stream("read csv", &jrc)
+        .expect("Could not start stream");
+    job_state.stream_incr_count_ok("read csv", "a.csv").unwrap();
+    job_state.stream_incr_count_ok("read csv", "b.csv").unwrap();
+    job_state
+        .stream_not_ok("read csv", "failed reading b.csv", 2)
+        .unwrap();
+    let skip = job_state.processed_inputs("read csv");
+    assert_eq!(1, skip.len());
+    assert!(skip.contains("a.csv"));
+
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let job_state = JobRunner::create(
+        "decoder_fs",
+        "decoder_fs_discover",
+        &jm_handle,
+        JobRunnerConfig {
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner")
+    .run_stream::<TestCsv>(
+        "read csv",
+        CsvDecoder::new(
+            CsvReadOptions::default(),
+            Box::new(LocalFs {
+                home,
+                discover: Some(FileDiscovery {
+                    globs: vec![String::from("**/*.csv")],
+                    recursive: true,
+                    skip,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+        ),
+        Box::new(mock::MockJsonDataOutput::default()),
+    )
+    .await
+    .expect("Failed run_stream")
+    .complete()
+    .await
+    .expect("Fail completing");
+    if let Some(JobStepDetails {
+        step: JobStepStatus::Stream(StepStreamStatus::Complete { inputs, .. }),
+        ..
+    }) = job_state.step_history.get("read csv")
+    {
+        let mut read: Vec<&String> = inputs.keys().collect();
+        read.sort();
+        assert_eq!(vec!["b.csv", "nested/c.csv"], read);
+    } else {
+        panic!("read csv is not showing as completed");
+    }
+    jm_handle.shutdown().await.expect("failure waiting for jm");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_fs_csv_provenance() {
+    use etl_core::splitter::split_datasources;
+    let source: Box<dyn DataSource<TestCsv>> = CsvDecoder::new(
+        CsvReadOptions::default(),
+        Box::new(LocalFs {
+            home: String::from("tests/test_data"),
+            files: vec!["14_good_lines.csv".to_string()],
+            ..Default::default()
+        }),
+    );
+    // the provenance has to survive the combinators
+    let (_, mut sources) = split_datasources(source, 1).await;
+    let (mut rx, jh) = sources.remove(0).start_stream().expect("Could not start the stream");
+    let mut provenances = Vec::new();
+    while let Some(message) = rx.recv().await {
+        let DataSourceMessage::Data { source, .. } = message.expect("Could not decode a line");
+        provenances.push(source);
+    }
+    jh.await.unwrap().unwrap();
+    assert_eq!(14, provenances.len());
+    assert_eq!("14_good_lines.csv", provenances[0].key);
+    assert_eq!("LocalFs-tests/test_data", provenances[0].origin);
+    // the headers are on the first line
+    assert_eq!(Some(2), provenances[0].line);
+    assert_eq!(Some(12), provenances[0].offset);
+    assert_eq!(Some(15), provenances[13].line);
+    assert_eq!("14_good_lines.csv:15@324", provenances[13].to_string());
+}
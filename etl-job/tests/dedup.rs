@@ -0,0 +1,177 @@
+use etl_core::datastore::mock::{MockJsonDataOutput, MockJsonDataSource};
+use etl_core::datastore::*;
+use etl_core::dedup::{BloomFilter, Dedup, StoredSeenKeys};
+use etl_core::deps::serde::{Deserialize, Serialize};
+use etl_core::deps::*;
+use etl_core::ext::DataSourceExt;
+use etl_job::job::state::*;
+use etl_job::job::stream::*;
+use etl_job::job::*;
+use etl_job::job_manager::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "serde")]
+struct Record {
+    id: String,
+    value: usize,
+}
+
+fn records(ids: &[&str]) -> Box<dyn DataSource<Record>> {
+    ids.iter()
+        .enumerate()
+        .map(|(value, id)| format!("{{\"id\": \"{}\", \"value\": {}}}", id, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .decode_json::<Record>()
+}
+
+async fn run(ds: Dedup<Record, String>) -> (Vec<(Option<usize>, usize)>, DataSourceStats) {
+    let (mut rx, jh) = Box::new(ds)
+        .start_stream()
+        .expect("Could not start the stream");
+    let mut items = Vec::new();
+    while let Some(message) = rx.recv().await {
+        match message.expect("Unexpected error") {
+            DataSourceMessage::Data { source, content } => items.push((source.line, content.value)),
+        }
+    }
+    let stats = jh.await.unwrap().expect("Dedup failed");
+    (items, stats)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_dedup_keeps_first() {
+    let input = records(&["a", "b", "a", "c", "b", "a", "d"]);
+    let (items, stats) = run(Dedup::new(input, |r: &Record| r.id.clone())).await;
+    assert_eq!(
+        vec![(Some(1), 0), (Some(2), 1), (Some(4), 3), (Some(7), 6)],
+        items
+    );
+    assert_eq!(7, stats.lines_scanned);
+    assert_eq!(3, stats.duplicates);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_dedup_bloom_filter() {
+    let ids = (0..1000)
+        .map(|i| format!("{}", i % 400))
+        .collect::<Vec<_>>();
+    let ids = ids.iter().map(String::as_str).collect::<Vec<_>>();
+    let ds = Dedup::new(records(&ids), |r: &Record| r.id.clone())
+        .with_seen_keys(BloomFilter::new(400, 0.001));
+    let (items, stats) = run(ds).await;
+    // duplicates are always dropped, a false positive might drop a unique key too
+    assert!(items.len() <= 400 && items.len() >= 395);
+    assert!(items.iter().all(|(_, value)| *value < 400));
+    assert_eq!(1000, stats.lines_scanned);
+    assert_eq!(1000 - items.len(), stats.duplicates);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_dedup_across_runs() {
+    let files = Arc::new(Mutex::new(RefCell::new(HashMap::new())));
+    let store = || {
+        Box::new(MockJsonDataSource {
+            lines: Vec::new(),
+            files: files.clone(),
+        })
+    };
+    let dedup = |ids: &[&str]| {
+        let seen = StoredSeenKeys::new(store(), "job-1", "records");
+        let commit = seen.commit_handle();
+        let ds = Dedup::new(records(ids), |r: &Record| r.id.clone()).with_seen_keys(seen);
+        (ds, commit)
+    };
+
+    let (ds, commit) = dedup(&["a", "b", "a"]);
+    let (items, stats) = run(ds).await;
+    assert_eq!(vec![0, 1], items.iter().map(|i| i.1).collect::<Vec<_>>());
+    assert_eq!(1, stats.duplicates);
+    commit.commit().await.expect("Could not store the keys");
+
+    // keys of the first run are skipped by the next one
+    let (ds, _) = dedup(&["b", "c", "a", "c", "d"]);
+    let (items, stats) = run(ds).await;
+    assert_eq!(vec![1, 4], items.iter().map(|i| i.1).collect::<Vec<_>>());
+    assert_eq!(3, stats.duplicates);
+
+    // that run was not committed, so its keys are seen again
+    let (ds, commit) = dedup(&["c", "d", "e"]);
+    let (items, stats) = run(ds).await;
+    assert_eq!(vec![0, 1, 2], items.iter().map(|i| i.1).collect::<Vec<_>>());
+    assert_eq!(0, stats.duplicates);
+    commit.commit().await.expect("Could not store the keys");
+
+    let (ds, _) = dedup(&["a", "e", "f"]);
+    let (items, _) = run(ds).await;
+    assert_eq!(vec![2], items.iter().map(|i| i.1).collect::<Vec<_>>());
+
+    // other jobs have their own keys
+    let other = Dedup::new(records(&["a", "d"]), |r: &Record| r.id.clone())
+        .with_seen_keys(StoredSeenKeys::new(store(), "job-2", "records"));
+    let (items, _) = run(other).await;
+    assert_eq!(2, items.len());
+    // each committed run has a file with only its own keys
+    let files = files.lock().unwrap();
+    let files = files.borrow();
+    assert_eq!("2", files["job-1.records.seen.json"]);
+    let mut keys: Vec<String> = serde_json::from_str(&files["job-1.records.1.seen.json"]).unwrap();
+    keys.sort();
+    assert_eq!(vec!["c", "d", "e"], keys);
+    assert!(!files.contains_key("job-2.records.seen.json"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_dedup_job_state() {
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let input = Dedup::new(records(&["a", "b", "a", "c", "b"]), |r: &Record| {
+        r.id.clone()
+    });
+    let job_state = JobRunner::create(
+        "test_dedup_job_state_id",
+        "test_dedup_job_state",
+        &jm_handle,
+        JobRunnerConfig {
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner")
+    .run_stream::<Record>(
+        "dedup records",
+        Box::new(input),
+        Box::new(MockJsonDataOutput::default()),
+    )
+    .await
+    .expect("Error running the stream")
+    .complete()
+    .await
+    .expect("Fail completing");
+    match job_state.step_history.get("dedup records") {
+        Some(JobStepDetails {
+            step:
+                JobStepStatus::Stream(StepStreamStatus::Complete {
+                    total_lines_scanned,
+                    duplicates,
+                    ..
+                }),
+            ..
+        }) => {
+            assert_eq!(3, *total_lines_scanned);
+            assert_eq!(2, *duplicates);
+        }
+        _ => panic!("dedup records is not showing as completed"),
+    }
+    jm_handle
+        .shutdown()
+        .await
+        .expect("failure shutting down JobManager");
+}
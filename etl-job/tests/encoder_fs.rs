@@ -0,0 +1,487 @@
+use enumerate::EnumerateStreamAsync;
+use etl_core::datastore::*;
+use etl_core::decoder::avro::AvroDecoder;
+use etl_core::decoder::json::*;
+use etl_core::decoder::parquet::ParquetDecoder;
+use etl_core::deps::serde::{Deserialize, Serialize};
+use etl_core::deps::*;
+use etl_core::encoder::avro_encoder::AvroEncoder;
+use etl_core::encoder::json_encoder::JsonLinesEncoder;
+use etl_core::encoder::parquet_encoder::ParquetEncoder;
+use etl_core::encoder::EncodedOutput;
+use etl_job::job::state::*;
+use etl_job::job::stream::*;
+use etl_job::job::*;
+use etl_job::job_manager::*;
+use fs::LocalFs;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+struct TestCsv {
+    index: String,
+    words: String,
+}
+
+fn create_test_source() -> Box<dyn DataSource<TestCsv>> {
+    Box::new(EnumerateStreamAsync::with_max(
+        "create test items",
+        10,
+        (),
+        |_, idx| {
+            Box::pin(async move {
+                Ok(TestCsv {
+                    index: format!("{}", idx),
+                    words: String::from("some words"),
+                })
+            })
+        },
+    ))
+}
+
+fn test_output_dir() -> String {
+    std::env::temp_dir()
+        .join("etl-job-encoder-fs")
+        .to_string_lossy()
+        .to_string()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_json_lines_encoder_fs() {
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let jr = JobRunner::create(
+        "encoder_fs",
+        "encoder_fs_test",
+        &jm_handle,
+        JobRunnerConfig {
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner");
+    let job_state = jr
+        .run_stream::<TestCsv>(
+            "write json lines",
+            create_test_source(),
+            Box::new(EncodedOutput {
+                encoder: Box::new(JsonLinesEncoder::default()),
+                output: Box::new(LocalFs {
+                    home: test_output_dir(),
+                    output_name: Some(String::from("10_lines.ndjson")),
+                    ..Default::default()
+                }),
+            }),
+        )
+        .await
+        .expect("Failed writing json lines")
+        .run_stream::<TestCsv>(
+            "read json lines",
+            JsonDecoder::new(Box::new(LocalFs {
+                home: test_output_dir(),
+                files: vec![String::from("10_lines.ndjson")],
+                ..Default::default()
+            })),
+            Box::new(mock::MockJsonDataOutput::default()),
+        )
+        .await
+        .expect("Failed reading json lines")
+        .complete()
+        .await
+        .expect("Fail completing");
+    if let Some(JobStepDetails {
+        step:
+            JobStepStatus::Stream(StepStreamStatus::Complete {
+                total_lines_scanned,
+                num_errors,
+                ..
+            }),
+        ..
+    }) = job_state.step_history.get("read json lines")
+    {
+        assert_eq!(10, *total_lines_scanned);
+        assert_eq!(0, *num_errors);
+    } else {
+        panic!("read json lines is not showing as completed");
+    }
+    jm_handle.shutdown().await.expect("failure waiting for jm");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_json_array_encoder_fs() {
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    JobRunner::create(
+        "encoder_fs",
+        "encoder_fs_array_test",
+        &jm_handle,
+        JobRunnerConfig {
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner")
+    .run_stream::<TestCsv>(
+        "write json array",
+        create_test_source(),
+        Box::new(EncodedOutput {
+            encoder: Box::new(JsonLinesEncoder {
+                pretty: true,
+                as_array: true,
+            }),
+            output: Box::new(LocalFs {
+                home: test_output_dir(),
+                output_name: Some(String::from("10_items.json")),
+                ..Default::default()
+            }),
+        }),
+    )
+    .await
+    .expect("Failed writing json array")
+    .complete()
+    .await
+    .expect("Fail completing");
+    let content = tokio::fs::read_to_string(
+        std::path::Path::new(&test_output_dir()).join("10_items.json"),
+    )
+    .await
+    .expect("Could not read the written file");
+    let items: Vec<TestCsv> =
+        serde_json::from_str(&content).expect("Written file is not a json array");
+    assert_eq!(10, items.len());
+    assert_eq!("9", items[9].index);
+    jm_handle.shutdown().await.expect("failure waiting for jm");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_compressed_json_lines_fs() {
+    // compression is detected from the extension on both ends
+    for file_name in ["10_lines.ndjson.gz", "10_lines.ndjson.zst"] {
+        let job_manager = JobManager::new(JobManagerConfig {
+            max_errors: 100,
+            ..Default::default()
+        })
+        .expect("Could not initialize job_manager");
+        let jm_handle = job_manager.start();
+        let job_state = JobRunner::create(
+            "encoder_fs",
+            file_name,
+            &jm_handle,
+            JobRunnerConfig {
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("Error creating JobRunner")
+        .run_stream::<TestCsv>(
+            "write compressed",
+            create_test_source(),
+            Box::new(EncodedOutput {
+                encoder: Box::new(JsonLinesEncoder::default()),
+                output: Box::new(LocalFs {
+                    home: test_output_dir(),
+                    output_name: Some(file_name.to_string()),
+                    ..Default::default()
+                }),
+            }),
+        )
+        .await
+        .expect("Failed writing compressed json lines")
+        .run_stream::<TestCsv>(
+            "read compressed",
+            JsonDecoder::new(Box::new(LocalFs {
+                home: test_output_dir(),
+                files: vec![file_name.to_string()],
+                ..Default::default()
+            })),
+            Box::new(mock::MockJsonDataOutput::default()),
+        )
+        .await
+        .expect("Failed reading compressed json lines")
+        .complete()
+        .await
+        .expect("Fail completing");
+        let content = tokio::fs::read(std::path::Path::new(&test_output_dir()).join(file_name))
+            .await
+            .expect("Could not read the written file");
+        assert!(serde_json::from_slice::<TestCsv>(&content).is_err());
+        if let Some(JobStepDetails {
+            step:
+                JobStepStatus::Stream(StepStreamStatus::Complete {
+                    total_lines_scanned,
+                    num_errors,
+                    ..
+                }),
+            ..
+        }) = job_state.step_history.get("read compressed")
+        {
+            assert_eq!(10, *total_lines_scanned);
+            assert_eq!(0, *num_errors);
+        } else {
+            panic!("read compressed is not showing as completed");
+        }
+        jm_handle.shutdown().await.expect("failure waiting for jm");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_rotated_json_lines_fs() {
+    let home = std::env::temp_dir()
+        .join("etl-job-encoder-fs-rotate")
+        .to_string_lossy()
+        .to_string();
+    let _ = tokio::fs::remove_dir_all(&home).await;
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let job_state = JobRunner::create(
+        "encoder_fs",
+        "encoder_fs_rotate_test",
+        &jm_handle,
+        JobRunnerConfig {
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner")
+    .run_stream::<TestCsv>(
+        "write rotated",
+        create_test_source(),
+        Box::new(EncodedOutput {
+            encoder: Box::new(JsonLinesEncoder::default()),
+            output: Box::new(LocalFs {
+                home: home.clone(),
+                output_name: Some(String::from("out.jsonl")),
+                rotate: Some(fs::FileRotation {
+                    max_lines: Some(4),
+                    name_template: Some(String::from("part-{index}.jsonl")),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+        }),
+    )
+    .await
+    .expect("Failed writing rotated json lines")
+    .complete()
+    .await
+    .expect("Fail completing");
+    if let Some(JobStepDetails {
+        step: JobStepStatus::Stream(StepStreamStatus::Complete { outputs, .. }),
+        ..
+    }) = job_state.step_history.get("write rotated")
+    {
+        let written: Vec<(&str, usize)> = outputs
+            .iter()
+            .map(|o| (o.name.as_str(), o.lines_written))
+            .collect();
+        assert_eq!(
+            vec![
+                ("part-00001.jsonl", 4),
+                ("part-00002.jsonl", 4),
+                ("part-00003.jsonl", 2)
+            ],
+            written
+        );
+    } else {
+        panic!("write rotated is not showing as completed");
+    }
+    let content = tokio::fs::read_to_string(std::path::Path::new(&home).join("part-00003.jsonl"))
+        .await
+        .expect("Could not read the last part");
+    assert_eq!(2, content.lines().count());
+    jm_handle.shutdown().await.expect("failure waiting for jm");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_parquet_fs() {
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let job_state = JobRunner::create(
+        "encoder_fs",
+        "encoder_fs_parquet_test",
+        &jm_handle,
+        JobRunnerConfig {
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner")
+    .run_stream::<TestCsv>(
+        "write parquet",
+        create_test_source(),
+        Box::new(EncodedOutput {
+            // the schema is inferred from the first row group
+            encoder: Box::new(ParquetEncoder {
+                row_group_size: 4,
+                ..Default::default()
+            }),
+            output: Box::new(LocalFs {
+                home: test_output_dir(),
+                output_name: Some(String::from("10_items.parquet")),
+                ..Default::default()
+            }),
+        }),
+    )
+    .await
+    .expect("Failed writing parquet")
+    .run_stream::<TestCsv>(
+        "read parquet",
+        ParquetDecoder::new(Box::new(LocalFs {
+            home: test_output_dir(),
+            files: vec![String::from("10_items.parquet")],
+            whole_files: true,
+            ..Default::default()
+        })),
+        Box::new(mock::MockJsonDataOutput::default()),
+    )
+    .await
+    .expect("Failed reading parquet")
+    .complete()
+    .await
+    .expect("Fail completing");
+    if let Some(JobStepDetails {
+        step:
+            JobStepStatus::Stream(StepStreamStatus::Complete {
+                total_lines_scanned,
+                num_errors,
+                ..
+            }),
+        ..
+    }) = job_state.step_history.get("read parquet")
+    {
+        assert_eq!(10, *total_lines_scanned);
+        assert_eq!(0, *num_errors);
+    } else {
+        panic!("read parquet is not showing as completed");
+    }
+    let file = std::fs::File::open(std::path::Path::new(&test_output_dir()).join("10_items.parquet"))
+        .expect("Could not open the written file");
+    let reader = parquet::file::serialized_reader::SerializedFileReader::new(file)
+        .expect("Written file is not parquet");
+    use parquet::file::reader::FileReader;
+    assert_eq!(3, reader.metadata().num_row_groups());
+    jm_handle.shutdown().await.expect("failure waiting for jm");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_parquet_inferred_schema_mismatch() {
+    use etl_core::encoder::EncodeStream;
+    // the field b only shows up after the first row group
+    let rows = JsonDecoder::new::<serde_json::Value>(Box::new(String::from(
+        "{\"a\": 1}\n{\"a\": 2}\n{\"a\": 3, \"b\": \"new\"}",
+    )));
+    let encoder = Box::new(ParquetEncoder {
+        row_group_size: 2,
+        ..Default::default()
+    });
+    let (mut rx, jh) = encoder
+        .encode_source(rows)
+        .await
+        .start_stream()
+        .expect("Could not start encoding");
+    while rx.recv().await.is_some() {}
+    let er = match jh.await.unwrap() {
+        Ok(_) => panic!("a row did not fit the schema and was written anyway"),
+        Err(er) => er.to_string(),
+    };
+    assert!(er.contains("does not fit the schema"), "{}", er);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_avro_fs() {
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let job_state = JobRunner::create(
+        "encoder_fs",
+        "encoder_fs_avro_test",
+        &jm_handle,
+        JobRunnerConfig {
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner")
+    .run_stream::<TestCsv>(
+        "write avro",
+        create_test_source(),
+        Box::new(EncodedOutput {
+            // the schema is derived from the first element
+            encoder: Box::new(AvroEncoder {
+                block_size: 4,
+                compression: Some(avro_schema::file::Compression::Deflate),
+                ..Default::default()
+            }),
+            output: Box::new(LocalFs {
+                home: test_output_dir(),
+                output_name: Some(String::from("10_items.avro")),
+                ..Default::default()
+            }),
+        }),
+    )
+    .await
+    .expect("Failed writing avro")
+    .run_stream::<TestCsv>(
+        "read avro",
+        AvroDecoder::new(Box::new(LocalFs {
+            home: test_output_dir(),
+            files: vec![String::from("10_items.avro")],
+            whole_files: true,
+            ..Default::default()
+        })),
+        Box::new(mock::MockJsonDataOutput::default()),
+    )
+    .await
+    .expect("Failed reading avro")
+    .run_stream::<serde_json::Value>(
+        "read avro as json",
+        AvroDecoder::new(Box::new(LocalFs {
+            home: test_output_dir(),
+            files: vec![String::from("10_items.avro")],
+            whole_files: true,
+            ..Default::default()
+        })),
+        Box::new(mock::MockJsonDataOutput::default()),
+    )
+    .await
+    .expect("Failed reading avro as json")
+    .complete()
+    .await
+    .expect("Fail completing");
+    for step in ["read avro", "read avro as json"] {
+        if let Some(JobStepDetails {
+            step:
+                JobStepStatus::Stream(StepStreamStatus::Complete {
+                    total_lines_scanned,
+                    num_errors,
+                    ..
+                }),
+            ..
+        }) = job_state.step_history.get(step)
+        {
+            assert_eq!(10, *total_lines_scanned);
+            assert_eq!(0, *num_errors);
+        } else {
+            panic!("{} is not showing as completed", step);
+        }
+    }
+    jm_handle.shutdown().await.expect("failure waiting for jm");
+}
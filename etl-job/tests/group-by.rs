@@ -0,0 +1,127 @@
+use etl_core::datastore::interop::DataSourceStream;
+use etl_core::datastore::*;
+use etl_core::deps::futures_util::StreamExt;
+use etl_core::deps::serde::{Deserialize, Serialize};
+use etl_core::deps::*;
+use etl_core::ext::DataSourceExt;
+use etl_core::group_by::*;
+use etl_core::sort::SortedDataSource;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "serde")]
+struct Order {
+    customer: String,
+    amount: usize,
+}
+
+/// 100 orders of 7 customers, customer `c<n>` first orders on line n + 1
+fn orders() -> Box<dyn DataSource<Order>> {
+    (0..100)
+        .map(|idx| format!("{{\"customer\":\"c{}\",\"amount\":{}}}", idx % 7, idx))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .decode_json::<Order>()
+}
+
+type Totals = (
+    usize,
+    usize,
+    Option<usize>,
+    Option<usize>,
+    Vec<usize>,
+    String,
+);
+
+fn group_by(
+    input: Box<dyn DataSource<Order>>,
+) -> GroupBy<Order, String, impl Aggregator<Order, Acc = Totals>> {
+    GroupBy::new(
+        input,
+        |o: &Order| o.customer.clone(),
+        (
+            Count,
+            Sum(|o: &Order| o.amount),
+            Min(|o: &Order| o.amount),
+            Max(|o: &Order| o.amount),
+            Collect(|o: &Order| o.amount),
+            // user defined, the last digits of the amounts
+            Fold::new(String::new(), |digits: &mut String, o: &Order| {
+                digits.push_str(&(o.amount % 10).to_string())
+            }),
+        ),
+    )
+}
+
+async fn collect(
+    ds: Box<dyn DataSource<(String, Totals)>>,
+) -> Vec<Result<(Provenance, String, Totals), String>> {
+    DataSourceStream::new(ds)
+        .expect("Could not start grouping")
+        .map(|message| match message {
+            Ok(DataSourceMessage::Data {
+                source,
+                content: (key, totals),
+            }) => Ok((source, key, totals)),
+            Err(er) => Err(er.to_string()),
+        })
+        .collect()
+        .await
+}
+
+fn expected(customer: usize) -> Totals {
+    let amounts = (0..100).filter(|a| a % 7 == customer).collect::<Vec<_>>();
+    (
+        amounts.len(),
+        amounts.iter().sum(),
+        amounts.first().cloned(),
+        amounts.last().cloned(),
+        amounts.clone(),
+        amounts.iter().map(|a| (a % 10).to_string()).collect(),
+    )
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_group_by_hash() {
+    let spill_dir = std::env::temp_dir().join("etl-job-group-by");
+    let _ = std::fs::remove_dir_all(&spill_dir);
+    for max_groups in [100, 2] {
+        let ds = group_by(orders())
+            .with_max_groups(max_groups)
+            .with_spill_dir(&spill_dir);
+        let mut groups = collect(Box::new(ds))
+            .await
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        groups.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(7, groups.len());
+        for (customer, (source, key, totals)) in groups.into_iter().enumerate() {
+            assert_eq!(format!("c{}", customer), key);
+            assert_eq!(expected(customer), totals);
+            // the provenance of the first order
+            assert_eq!(Some(customer + 1), source.line);
+        }
+    }
+    assert_eq!(0, std::fs::read_dir(&spill_dir).unwrap().count());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_group_by_sorted() {
+    let sorted = SortedDataSource::new(orders(), |o: &Order| o.customer.clone()).with_run_len(30);
+    let groups = collect(Box::new(group_by(Box::new(sorted)).sorted())).await;
+    // in key order
+    for (customer, group) in groups.into_iter().enumerate() {
+        let (_, key, totals) = group.unwrap();
+        assert_eq!(format!("c{}", customer), key);
+        assert_eq!(expected(customer), totals);
+    }
+
+    let groups = collect(Box::new(group_by(orders()).sorted())).await;
+    // c0 to c5 are sent before c0 shows up again on line 8
+    assert_eq!(6, groups.iter().filter(|group| group.is_ok()).count());
+    let error = groups
+        .iter()
+        .find_map(|group| group.as_ref().err())
+        .unwrap();
+    assert!(error.contains("input is not sorted"), "{}", error);
+}
@@ -0,0 +1,162 @@
+use etl_core::datastore::interop::DataSourceStream;
+use etl_core::datastore::*;
+use etl_core::deps::futures_util::StreamExt;
+use etl_core::deps::serde::{Deserialize, Serialize};
+use etl_core::deps::*;
+use etl_core::ext::DataSourceExt;
+use etl_core::joins::*;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "serde")]
+struct Person {
+    id: usize,
+    name: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "serde")]
+struct Order {
+    person_id: usize,
+    amount: usize,
+}
+
+fn people() -> Box<dyn DataSource<Person>> {
+    ["ann", "bob", "cat", "dan"]
+        .iter()
+        .enumerate()
+        .map(|(idx, name)| format!("{{\"id\":{},\"name\":\"{}\"}}", idx + 1, name))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .decode_json::<Person>()
+}
+
+fn orders() -> Box<dyn DataSource<Order>> {
+    [(2, 10), (2, 20), (3, 30), (6, 60)]
+        .iter()
+        .map(|(id, amount)| format!("{{\"person_id\":{},\"amount\":{}}}", id, amount))
+        .chain(std::iter::once(String::from("not json")))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .decode_json::<Order>()
+}
+
+fn join(join_type: JoinType) -> HashJoin<Person, Order, usize> {
+    HashJoin::new(
+        people(),
+        orders(),
+        |p: &Person| p.id,
+        |o: &Order| o.person_id,
+    )
+    .with_join_type(join_type)
+}
+
+/// the ids and amounts of the joined items, sorted since spilling changes the order
+async fn run(join: HashJoin<Person, Order, usize>) -> (Vec<(Option<usize>, Option<usize>)>, usize) {
+    let mut errors = 0;
+    let mut joined = Vec::new();
+    let mut stream = DataSourceStream::new(Box::new(join)).expect("Could not start the join");
+    while let Some(message) = stream.next().await {
+        match message {
+            Ok(DataSourceMessage::Data {
+                content: (left, right),
+                ..
+            }) => joined.push((left.map(|p| p.id), right.map(|o| o.amount))),
+            Err(_) => errors += 1,
+        }
+    }
+    joined.sort();
+    (joined, errors)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_join_types() {
+    let (joined, errors) = run(join(JoinType::Inner)).await;
+    assert_eq!(
+        vec![
+            (Some(2), Some(10)),
+            (Some(2), Some(20)),
+            (Some(3), Some(30))
+        ],
+        joined
+    );
+    // the order which is not json is passed along
+    assert_eq!(1, errors);
+
+    let (joined, _) = run(join(JoinType::Left)).await;
+    assert_eq!(
+        vec![
+            (Some(1), None),
+            (Some(2), Some(10)),
+            (Some(2), Some(20)),
+            (Some(3), Some(30)),
+            (Some(4), None)
+        ],
+        joined
+    );
+
+    let (joined, _) = run(join(JoinType::Right).with_build_side(JoinSide::Left)).await;
+    assert_eq!(
+        vec![
+            (None, Some(60)),
+            (Some(2), Some(10)),
+            (Some(2), Some(20)),
+            (Some(3), Some(30))
+        ],
+        joined
+    );
+
+    let (joined, _) = run(join(JoinType::FullOuter)).await;
+    assert_eq!(6, joined.len());
+    assert!(joined.contains(&(None, Some(60))));
+    assert!(joined.contains(&(Some(4), None)));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_join_spill() {
+    let spill_dir = std::env::temp_dir().join("etl-job-hash-join-spill");
+    let _ = std::fs::remove_dir_all(&spill_dir);
+    for build_side in [JoinSide::Left, JoinSide::Right] {
+        let (in_memory, _) = run(join(JoinType::FullOuter).with_build_side(build_side)).await;
+        let (spilled, errors) = run(join(JoinType::FullOuter)
+            .with_build_side(build_side)
+            .with_memory_budget(1)
+            .with_spill_dir(&spill_dir))
+        .await;
+        assert_eq!(in_memory, spilled);
+        assert_eq!(1, errors);
+    }
+    // the partitions are removed once the join is done
+    assert_eq!(0, std::fs::read_dir(&spill_dir).unwrap().count());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_join_spill_error() {
+    let spill_dir = std::env::temp_dir().join("etl-job-hash-join-spill-error");
+    let _ = std::fs::remove_dir_all(&spill_dir);
+    let join = join(JoinType::Inner)
+        .with_memory_budget(1)
+        .with_spill_dir(&spill_dir);
+    // the error of the orders can't be sent while they are partitioned, as the receiver is gone
+    let (rx, jh) = Box::new(join)
+        .start_stream()
+        .expect("Could not start the join");
+    drop(rx);
+    assert!(jh.await.unwrap().is_err());
+    assert_eq!(0, std::fs::read_dir(&spill_dir).unwrap().count());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_join_provenance() {
+    let mut stream = DataSourceStream::new(Box::new(join(JoinType::FullOuter)))
+        .expect("Could not start the join");
+    while let Some(message) = stream.next().await {
+        if let Ok(DataSourceMessage::Data { source, content }) = message {
+            match content {
+                // the left item is on the line of its id
+                (Some(person), _) => assert_eq!(Some(person.id), source.line),
+                (None, Some(order)) => assert_eq!((Some(4), 60), (source.line, order.amount)),
+                (None, None) => panic!("Nothing was joined"),
+            }
+        }
+    }
+}
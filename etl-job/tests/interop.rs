@@ -0,0 +1,94 @@
+use common::*;
+use etl_core::datastore::error::DataStoreError;
+use etl_core::datastore::interop::*;
+use etl_core::datastore::*;
+use etl_core::deps::futures_util::{future, SinkExt, StreamExt};
+use etl_core::deps::*;
+use etl_job::job::state::*;
+use etl_job::job::stream::*;
+use etl_job::job::*;
+use etl_job::job_manager::*;
+
+mod common;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_stream_to_job_runner() {
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let jr = JobRunner::create(
+        "test_interop_id",
+        "test_interop",
+        &jm_handle,
+        JobRunnerConfig {
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner");
+    // enrich the items concurrently, keeping their order and provenance
+    let stream = DataSourceStream::new(items(1..=10, 5))
+        .expect("Could not start the stream")
+        .map(|message| async move {
+            message.map(|DataSourceMessage::Data { source, content }| {
+                DataSourceMessage::new(source, TestItem { id: content.id * 10 })
+            })
+        })
+        .buffered(4);
+    let job_state = jr
+        .run_stream::<TestItem>(
+            "from stream",
+            Box::new(StreamDataSource::new("enriched", stream)),
+            Box::new(mock::MockJsonDataOutput::default()),
+        )
+        .await
+        .expect("Failed run_stream")
+        .complete()
+        .await
+        .expect("Fail completing");
+    match job_state.step_history.get("from stream") {
+        Some(JobStepDetails {
+            step:
+                JobStepStatus::Stream(StepStreamStatus::Complete {
+                    total_lines_scanned,
+                    num_errors,
+                    ..
+                }),
+            ..
+        }) => {
+            assert_eq!(9, *total_lines_scanned);
+            assert_eq!(1, *num_errors);
+        }
+        _ => panic!("from stream is not showing as completed"),
+    }
+    jm_handle
+        .shutdown()
+        .await
+        .expect("failure shutting down JobManager");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_stream_into_sink() {
+    let mut sink = DataOutputSink::new(Box::new(mock::MockJsonDataOutput::default()))
+        .await
+        .expect("Could not start the output");
+    DataSourceStream::new(items(1..=10, 5))
+        .expect("Could not start the stream")
+        .filter_map(|message| future::ready(message.ok()))
+        .map(|DataSourceMessage::Data { content, .. }| Ok::<_, DataStoreError>(content))
+        .forward(&mut sink)
+        .await
+        .expect("Could not forward the stream");
+    assert_eq!(Some(9), sink.stats().map(|stats| stats.lines_written));
+
+    // items can also be sent one at a time
+    let mut sink = DataOutputSink::new(Box::new(mock::MockJsonDataOutput::default()))
+        .await
+        .expect("Could not start the output");
+    sink.send(TestItem { id: 1 }).await.expect("Could not send");
+    sink.close().await.expect("Could not close");
+    assert_eq!(Some(1), sink.stats().map(|stats| stats.lines_written));
+}
@@ -0,0 +1,87 @@
+use etl_core::datastore::error::DataStoreError;
+use etl_core::datastore::interop::DataSourceStream;
+use etl_core::datastore::*;
+use etl_core::deps::futures_util::StreamExt;
+use etl_core::deps::*;
+use etl_core::ext::DataSourceExt;
+use etl_core::joins::*;
+
+type Pair = (usize, String);
+type JoinedPairs = Box<dyn DataSource<Joined<Pair, Pair>>>;
+
+/// (key, value) pairs, one per line
+fn sorted(pairs: &[(usize, &str)]) -> Box<dyn DataSource<Pair>> {
+    pairs
+        .iter()
+        .map(|(key, value)| format!("[{},\"{}\"]", key, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .decode_json::<Pair>()
+}
+
+fn join(left: &[(usize, &str)], right: &[(usize, &str)], join_type: JoinType) -> JoinedPairs {
+    Box::new(
+        MergeJoin::new(
+            sorted(left),
+            sorted(right),
+            |(key, _): &Pair| *key,
+            |(key, _): &Pair| *key,
+        )
+        .with_join_type(join_type),
+    )
+}
+
+async fn run(ds: JoinedPairs) -> (Vec<String>, Vec<DataStoreError>) {
+    let mut joined = Vec::new();
+    let mut errors = Vec::new();
+    let mut stream = DataSourceStream::new(ds).expect("Could not start the join");
+    while let Some(message) = stream.next().await {
+        match message {
+            Ok(DataSourceMessage::Data {
+                content: (left, right),
+                ..
+            }) => joined.push(format!(
+                "{}-{}",
+                left.map(|(_, value)| value).unwrap_or_default(),
+                right.map(|(_, value)| value).unwrap_or_default()
+            )),
+            Err(er) => errors.push(er),
+        }
+    }
+    (joined, errors)
+}
+
+const LEFT: [(usize, &str); 5] = [(1, "a"), (2, "b"), (2, "c"), (4, "d"), (5, "e")];
+const RIGHT: [(usize, &str); 5] = [(0, "v"), (2, "w"), (2, "x"), (4, "y"), (6, "z")];
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_merge_join() {
+    let (joined, errors) = run(join(&LEFT, &RIGHT, JoinType::Inner)).await;
+    assert!(errors.is_empty());
+    // every pair of the key 2 group
+    assert_eq!(vec!["b-w", "b-x", "c-w", "c-x", "d-y"], joined);
+
+    let (joined, _) = run(join(&LEFT, &RIGHT, JoinType::Left)).await;
+    assert_eq!(vec!["a-", "b-w", "b-x", "c-w", "c-x", "d-y", "e-"], joined);
+
+    let (joined, _) = run(join(&LEFT, &RIGHT, JoinType::Right)).await;
+    assert_eq!(vec!["-v", "b-w", "b-x", "c-w", "c-x", "d-y", "-z"], joined);
+
+    let (joined, _) = run(join(&LEFT, &RIGHT, JoinType::FullOuter)).await;
+    assert_eq!(
+        vec!["-v", "a-", "b-w", "b-x", "c-w", "c-x", "d-y", "e-", "-z"],
+        joined
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_merge_join_unsorted() {
+    let right = [(2, "w"), (4, "y"), (3, "x"), (6, "z")];
+    let (joined, errors) = run(join(&LEFT, &right, JoinType::Inner)).await;
+    // the join stops at the key out of order
+    assert_eq!(vec!["b-w", "c-w"], joined);
+    assert!(!errors.is_empty());
+    let message = errors[0].to_string();
+    assert!(message.contains("right input is not sorted"), "{}", message);
+    assert_eq!(Some(3), errors[0].provenance().and_then(|p| p.line));
+}
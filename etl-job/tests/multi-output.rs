@@ -0,0 +1,141 @@
+use etl_core::datastore::*;
+use etl_core::deps::anyhow;
+use etl_core::deps::async_trait;
+use etl_core::deps::serde::{Deserialize, Serialize};
+use etl_core::deps::*;
+use etl_core::ext::DataSourceExt;
+use etl_core::multi_output::MultiOutput;
+use etl_job::job::state::*;
+use etl_job::job::stream::*;
+use etl_job::job::*;
+use etl_job::job_manager::*;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "serde")]
+struct Order {
+    id: usize,
+    country: String,
+}
+
+fn orders() -> Box<dyn DataSource<Order>> {
+    ["us", "ca", "us", "mx", "ca", "us"]
+        .iter()
+        .enumerate()
+        .map(|(id, country)| format!("{{\"id\": {}, \"country\": \"{}\"}}", id, country))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .decode_json::<Order>()
+}
+
+/// keeps the ids of the orders it receives
+struct CollectOutput {
+    name: &'static str,
+    ids: Arc<Mutex<Vec<usize>>>,
+}
+
+impl CollectOutput {
+    fn new(name: &'static str) -> (Box<Self>, Arc<Mutex<Vec<usize>>>) {
+        let ids = Arc::new(Mutex::new(Vec::new()));
+        (
+            Box::new(CollectOutput {
+                name,
+                ids: ids.clone(),
+            }),
+            ids,
+        )
+    }
+}
+
+#[async_trait]
+impl DataOutput<Order> for CollectOutput {
+    async fn start_stream(self: Box<Self>) -> anyhow::Result<DataOutputTask<Order>> {
+        let (tx, mut rx): (DataOutputTx<Order>, _) = tokio::sync::mpsc::channel(1);
+        let jh: JoinHandle<anyhow::Result<DataOutputStats>> = tokio::spawn(async move {
+            let mut lines_written = 0;
+            while let Some(DataOutputMessage::Data(order)) = rx.recv().await {
+                self.ids.lock().unwrap().push(order.id);
+                lines_written += 1;
+            }
+            Ok(DataOutputStats {
+                name: self.name.to_string(),
+                lines_written,
+                ..Default::default()
+            })
+        });
+        Ok((tx, jh))
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_multi_output_predicates() {
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let (archive, archived) = CollectOutput::new("archive");
+    let (us, us_ids) = CollectOutput::new("us");
+    let (other, other_ids) = CollectOutput::new("other");
+    let output = MultiOutput::new("orders")
+        .with_output(archive)
+        .with_output_if(|o: &Order| o.country == "us", us)
+        .with_output_if(|o: &Order| o.country != "us", other);
+    let job_state = JobRunner::create(
+        "multi_output_id",
+        "multi_output",
+        &jm_handle,
+        JobRunnerConfig {
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner")
+    .run_stream::<Order>("write orders", orders(), Box::new(output))
+    .await
+    .expect("Error running the stream")
+    .complete()
+    .await
+    .expect("Fail completing");
+    assert_eq!(vec![0, 1, 2, 3, 4, 5], *archived.lock().unwrap());
+    assert_eq!(vec![0, 2, 5], *us_ids.lock().unwrap());
+    assert_eq!(vec![1, 3, 4], *other_ids.lock().unwrap());
+    // every child is recorded as an output of the stream
+    if let Some(JobStepDetails {
+        step: JobStepStatus::Stream(StepStreamStatus::Complete { outputs, .. }),
+        ..
+    }) = job_state.step_history.get("write orders")
+    {
+        let written: Vec<(&str, usize)> = outputs
+            .iter()
+            .map(|o| (o.name.as_str(), o.lines_written))
+            .collect();
+        assert_eq!(vec![("archive", 6), ("us", 3), ("other", 3)], written);
+    } else {
+        panic!("write orders is not showing as completed");
+    }
+    jm_handle.shutdown().await.expect("failure waiting for jm");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_multi_output_keys() {
+    let (north, north_ids) = CollectOutput::new("north");
+    let (us, us_ids) = CollectOutput::new("us");
+    let output = MultiOutput::by_key("orders", |o: &Order| o.country.clone())
+        .with_output_for(vec!["us".to_string(), "ca".to_string()], north)
+        .with_output_for(vec!["us".to_string()], us);
+    let (tx, jh) = Box::new(output).start_stream().await.unwrap();
+    let (mut rx, _) = orders().start_stream().unwrap();
+    while let Some(Ok(DataSourceMessage::Data { content, .. })) = rx.recv().await {
+        tx.send(DataOutputMessage::new(content)).await.unwrap();
+    }
+    drop(tx);
+    let stats = jh.await.unwrap().unwrap();
+    assert_eq!(vec![0, 1, 2, 4, 5], *north_ids.lock().unwrap());
+    assert_eq!(vec![0, 2, 5], *us_ids.lock().unwrap());
+    // the order from mx matched no output
+    assert_eq!(5, stats.lines_written);
+    assert_eq!(2, stats.parts.len());
+}
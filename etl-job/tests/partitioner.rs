@@ -0,0 +1,127 @@
+use common::*;
+use etl_core::deps::*;
+use etl_core::splitter::Partitioner;
+use std::collections::HashSet;
+
+mod common;
+
+/// reads all of the outputs at the same time
+async fn collect_all(partitioner: Partitioner<TestItem>) -> Vec<Vec<Message<TestItem>>> {
+    let (jh, outputs) = partitioner.start();
+    let readers = outputs
+        .into_iter()
+        .map(|ds| tokio::spawn(collect::<TestItem>(ds)))
+        .collect::<Vec<_>>();
+    let mut results = Vec::new();
+    for reader in readers {
+        results.push(reader.await.unwrap().0);
+    }
+    let stats = jh.await.unwrap().expect("Partitioner failed");
+    assert_eq!(
+        stats.lines_scanned,
+        results.iter().map(Vec::len).sum::<usize>()
+    );
+    results
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_partition_by_key() {
+    let outputs = collect_all(
+        Partitioner::by_key(items(1..=100, 5), 3, |item: &TestItem| item.id % 7).unwrap(),
+    )
+    .await;
+    assert_eq!(3, outputs.len());
+    let mut keys_seen = HashSet::new();
+    for output in outputs.iter() {
+        let keys = ids(output)
+            .into_iter()
+            .map(|id| id % 7)
+            .collect::<HashSet<_>>();
+        // a key is only found in one output
+        assert!(keys.is_disjoint(&keys_seen));
+        keys_seen.extend(keys);
+    }
+    assert_eq!(7, keys_seen.len());
+    // the error of line 5 goes to the first output
+    assert_eq!(1, outputs[0].iter().filter(|item| item.is_err()).count());
+    assert_eq!(
+        99,
+        outputs
+            .iter()
+            .map(|output| ids(output).len())
+            .sum::<usize>()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_partition_round_robin() {
+    let partitioner = Partitioner::round_robin(items(1..=12, 5), 4)
+        .unwrap()
+        .with_buffer_size(2)
+        .with_output_buffer_size(3, 1)
+        .unwrap();
+    let outputs = collect_all(partitioner).await;
+    // the first item goes to the first output, and so does the error of line 5
+    assert_eq!(vec![1, 9], ids(&outputs[0]));
+    assert!(outputs[0][1].is_err());
+    assert_eq!(vec![2, 6, 10], ids(&outputs[1]));
+    assert_eq!(vec![3, 7, 11], ids(&outputs[2]));
+    assert_eq!(vec![4, 8, 12], ids(&outputs[3]));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_partition_bad_outputs() {
+    assert!(Partitioner::round_robin(items(1..=1, 5), 0).is_err());
+    assert!(Partitioner::round_robin(items(1..=1, 5), 2)
+        .unwrap()
+        .with_output_buffer_size(2, 10)
+        .is_err());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_partition_by_predicates() {
+    let outputs = collect_all(Partitioner::by_predicates(
+        items(1..=10, 5),
+        vec![
+            Box::new(|item: &TestItem| item.id < 3),
+            Box::new(|item: &TestItem| item.id > 7),
+        ],
+    ))
+    .await;
+    assert_eq!(3, outputs.len());
+    assert_eq!(vec![1, 2], ids(&outputs[0]));
+    assert_eq!(vec![8, 9, 10], ids(&outputs[1]));
+    assert_eq!(vec![3, 4, 6, 7], ids(&outputs[2]));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_partition_spills_lagging_output() {
+    let spill_dir = std::env::temp_dir().join(format!("partitioner-test-{}", std::process::id()));
+    std::fs::create_dir_all(&spill_dir).unwrap();
+    let (jh, mut outputs) = Partitioner::round_robin(items(1..=2000, 5), 2)
+        .unwrap()
+        .with_buffer_size(1)
+        .with_spill_dir(&spill_dir)
+        .start();
+    let second = outputs.pop().unwrap();
+    let first = outputs.pop().unwrap();
+    // the first output is not read until the second one is done, which would wait forever
+    // without spilling
+    let (second, _) = collect::<TestItem>(second).await;
+    assert_eq!((1..=1000).map(|i| i * 2).collect::<Vec<_>>(), ids(&second));
+    // the error of line 5 goes to the first output, in its place
+    let (first, _) = collect::<TestItem>(first).await;
+    assert_eq!(1000, first.len());
+    assert!(first[2].is_err());
+    assert_eq!(
+        (0..1000)
+            .map(|i| i * 2 + 1)
+            .filter(|id| *id != 5)
+            .collect::<Vec<_>>(),
+        ids(&first)
+    );
+    assert_eq!(2000, jh.await.unwrap().unwrap().lines_scanned);
+    // the spill files are removed
+    assert_eq!(0, std::fs::read_dir(&spill_dir).unwrap().count());
+    std::fs::remove_dir(&spill_dir).unwrap();
+}
@@ -111,6 +111,175 @@ async fn test_simple_pipeline_max_error_with_failure() {
     jm_handle.shutdown().await.unwrap();
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_simple_pipeline_dead_letter() {
+    use etl_core::datastore::fs::LocalFs;
+    use etl_core::encoder::json_encoder::JsonLinesEncoder;
+    use etl_core::encoder::EncodedOutput;
+    use etl_job::job::dead_letter::DeadLetter;
+    let home = std::env::temp_dir()
+        .join("etl-job-dead-letter")
+        .to_string_lossy()
+        .to_string();
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let jr = JobRunner::create(
+        "test_simple_pipeline_id",
+        "test_simple_pipeline_dead_letter",
+        &jm_handle,
+        JobRunnerConfig {
+            dead_letter: Some(Box::new(EncodedOutput {
+                encoder: Box::new(JsonLinesEncoder::default()),
+                output: Box::new(LocalFs {
+                    home: home.clone(),
+                    output_name: Some(String::from("dead_letters.jsonl")),
+                    ..Default::default()
+                }),
+            })),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner");
+    let transformed_ds = TransformDataSource::new(
+        "transformed-mock-ds",
+        Box::new(create_mock_data_source()),
+        Box::new(TestTransformer {}),
+    );
+    jr.run_stream::<TestOutputData>(
+        "transformed-ds-1",
+        Box::new(transformed_ds),
+        Box::new(MockJsonDataOutput::default()),
+    )
+    .await
+    .expect("Error running run_data_output")
+    .complete()
+    .await
+    .expect("Error completing job");
+    let content =
+        tokio::fs::read_to_string(std::path::Path::new(&home).join("dead_letters.jsonl"))
+            .await
+            .expect("Could not read the dead letters");
+    let dead_letters: Vec<DeadLetter> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Not a dead letter"))
+        .collect();
+    assert_eq!(2, dead_letters.len());
+    assert_eq!("transformed-ds-1", dead_letters[0].stream);
+    assert_eq!(
+        Some("1 this is a malformed json"),
+        dead_letters[0].raw.as_deref()
+    );
+    assert_eq!(
+        Some("2 this is a malformed json"),
+        dead_letters[1].raw.as_deref()
+    );
+    // the mock lines are numbered from 1
+    assert_eq!(Some(2), dead_letters[0].source.line);
+    assert_eq!(Some(3), dead_letters[1].source.line);
+    jm_handle.shutdown().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_simple_pipeline_list_output() {
+    let job_manager = JobManager::new(JobManagerConfig {
+        max_errors: 100,
+        ..Default::default()
+    })
+    .expect("Could not initialize job_manager");
+    let jm_handle = job_manager.start();
+    let jr = JobRunner::create(
+        "test_simple_pipeline_id",
+        "test_simple_pipeline_list_output",
+        &jm_handle,
+        JobRunnerConfig {
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Error creating JobRunner");
+    let job_state = jr
+        .run_stream::<TestOutputData>(
+            "unnested-ds",
+            Box::new(TransformDataSource::new(
+                "unnested-mock-ds",
+                Box::new(create_mock_data_source_todos()),
+                Box::new(TestUnnestTransformer {}),
+            )),
+            Box::new(MockJsonDataOutput::default()),
+        )
+        .await
+        .expect("Error running run_data_output")
+        .complete()
+        .await
+        .expect("Error completing job");
+    use state::*;
+    match job_state.step_history.get("unnested-ds") {
+        Some(JobStepDetails {
+            step:
+                JobStepStatus::Stream(StepStreamStatus::Complete {
+                    total_lines_scanned,
+                    num_errors,
+                    ..
+                }),
+            ..
+        }) => {
+            // one item per todo, Bob has none
+            assert_eq!(3, *total_lines_scanned);
+            assert_eq!(2, *num_errors);
+        }
+        _ => panic!("unnested-ds is not showing as completed"),
+    }
+    jm_handle.shutdown().await.unwrap();
+
+    let ds: Box<dyn DataSource<TestOutputData>> = Box::new(TransformDataSource::new(
+        "unnested-mock-ds",
+        Box::new(create_mock_data_source_todos()),
+        Box::new(TestUnnestTransformer {}),
+    ));
+    let (mut rx, jh) = ds.start_stream().expect("Could not start the stream");
+    let mut items = Vec::new();
+    while let Some(message) = rx.recv().await {
+        if let Ok(DataSourceMessage::Data { source, content }) = message {
+            items.push((source.line, content.resource_type));
+        }
+    }
+    assert_eq!(
+        vec![
+            (Some(4), Some(String::from("paint the barn"))),
+            (Some(4), Some(String::from("feed the cat"))),
+            (Some(5), Some(String::from("code something up"))),
+        ],
+        items
+    );
+    let stats = jh.await.unwrap().expect("Transform failed");
+    assert_eq!(5, stats.lines_scanned);
+}
+
+pub struct TestUnnestTransformer;
+#[async_trait]
+impl TransformHandler<TestSourceData, TestOutputData> for TestUnnestTransformer {
+    async fn transform_item(
+        &self,
+        ji: JobItemInfo,
+        item: TestSourceData,
+    ) -> anyhow::Result<Option<TransformOutput<TestOutputData>>> {
+        Ok(Some(TransformOutput::List(
+            item.todo
+                .into_iter()
+                .map(|todo| TestOutputData {
+                    resource_type: Some(todo),
+                    index: Some(ji.index),
+                })
+                .collect(),
+        )))
+    }
+}
+
 pub struct TestTransformer;
 use etl_core::deps::async_trait;
 #[async_trait]
@@ -212,3 +381,14 @@ fn create_mock_data_source_many_errors() -> MockJsonDataSource {
         ..Default::default()
     }
 }
+
+fn create_mock_data_source_todos() -> MockJsonDataSource {
+    let mut ds = create_mock_data_source();
+    ds.lines[3] = serde_json::to_string(&TestSourceData {
+        name: Some(String::from("Angela")),
+        todo: vec![String::from("paint the barn"), String::from("feed the cat")],
+        id: String::from("ang23"),
+    })
+    .unwrap();
+    ds
+}
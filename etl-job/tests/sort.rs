@@ -0,0 +1,89 @@
+use etl_core::datastore::interop::DataSourceStream;
+use etl_core::datastore::*;
+use etl_core::deps::futures_util::StreamExt;
+use etl_core::deps::*;
+use etl_core::ext::DataSourceExt;
+use etl_core::sort::SortedDataSource;
+
+/// the numbers 0 to 999 shuffled, on the line of their position
+fn shuffled() -> Box<dyn DataSource<usize>> {
+    (0..1000)
+        .map(|idx| ((idx * 7919) % 1000).to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .decode_json::<usize>()
+}
+
+async fn collect(ds: SortedDataSource<usize, usize>) -> Vec<(Provenance, usize)> {
+    DataSourceStream::new(Box::new(ds))
+        .expect("Could not start sorting")
+        .map(|message| match message {
+            Ok(DataSourceMessage::Data { source, content }) => (source, content),
+            Err(er) => panic!("{}", er),
+        })
+        .collect()
+        .await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_sort() {
+    let spill_dir = std::env::temp_dir().join("etl-job-sort");
+    let _ = std::fs::remove_dir_all(&spill_dir);
+    // in memory, in runs merged at once, and in more runs than are merged at once
+    for run_len in [1000, 64, 10] {
+        let sorted = collect(
+            SortedDataSource::new(shuffled(), |n: &usize| *n)
+                .with_run_len(run_len)
+                .with_spill_dir(&spill_dir),
+        )
+        .await;
+        assert_eq!(
+            (0..1000).collect::<Vec<_>>(),
+            sorted.iter().map(|(_, n)| *n).collect::<Vec<_>>()
+        );
+        // the items keep the line they were read from
+        for (source, n) in sorted.iter() {
+            let line = source.line.expect("No line") - 1;
+            assert_eq!(*n, (line * 7919) % 1000);
+        }
+    }
+    assert_eq!(0, std::fs::read_dir(&spill_dir).unwrap().count());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_sort_stable() {
+    for run_len in [1000, 7] {
+        let sorted =
+            collect(SortedDataSource::new(shuffled(), |n: &usize| n % 3).with_run_len(run_len))
+                .await;
+        let keys = sorted.iter().map(|(_, n)| n % 3).collect::<Vec<_>>();
+        let mut expected = keys.clone();
+        expected.sort();
+        assert_eq!(expected, keys);
+        // items with the same key are in input order
+        for pair in sorted.windows(2) {
+            let ((first_source, first), (second_source, second)) = (&pair[0], &pair[1]);
+            if first % 3 == second % 3 {
+                assert!(first_source.line < second_source.line);
+            }
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_sort_removes_runs_on_error() {
+    let spill_dir = std::env::temp_dir().join("etl-job-sort-error");
+    let _ = std::fs::remove_dir_all(&spill_dir);
+    // the error of the last line can't be sent as the receiver is gone, with the runs spilled
+    let mut lines = (0..1000).map(|n| n.to_string()).collect::<Vec<_>>();
+    lines.push(String::from("not a number"));
+    let ds = SortedDataSource::new(lines.join("\n").decode_json::<usize>(), |n: &usize| *n)
+        .with_run_len(10)
+        .with_spill_dir(&spill_dir);
+    let (rx, jh) = Box::new(ds)
+        .start_stream()
+        .expect("Could not start sorting");
+    drop(rx);
+    assert!(jh.await.unwrap().is_err());
+    assert_eq!(0, std::fs::read_dir(&spill_dir).unwrap().count());
+}
@@ -0,0 +1,92 @@
+use etl_core::batch::Batcher;
+use etl_core::datastore::enumerate::*;
+use etl_core::datastore::error::DataStoreError;
+use etl_core::datastore::interop::DataSourceStream;
+use etl_core::datastore::*;
+use etl_core::deps::futures_util::StreamExt;
+use etl_core::deps::*;
+use etl_core::ext::DataSourceExt;
+use etl_core::transformer::Transformer;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+async fn collect<T: std::fmt::Debug + Send + 'static>(ds: Box<dyn DataSource<T>>) -> Vec<T> {
+    DataSourceStream::new(ds)
+        .expect("Could not start the stream")
+        .filter_map(|message| async move {
+            message
+                .ok()
+                .map(|DataSourceMessage::Data { content, .. }| content)
+        })
+        .collect()
+        .await
+}
+
+fn ids(max: usize) -> Box<dyn DataSource<usize>> {
+    let offset = 1;
+    Box::new(EnumerateStream::with_max("ids", max, (), move |_, idx| {
+        Ok(idx + offset)
+    }))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_async_lookup() {
+    let names: Arc<HashMap<usize, String>> = Arc::new(
+        vec![(1, "one"), (2, "two"), (4, "four")]
+            .into_iter()
+            .map(|(id, name)| (id, name.to_string()))
+            .collect(),
+    );
+    let transformer = Transformer::new_async(ids(5), move |id| {
+        let names = names.clone();
+        async move {
+            tokio::task::yield_now().await;
+            match id {
+                5 => Err(DataStoreError::Generic(String::from("lookup failed"))),
+                _ => Ok(names.get(&id).cloned()),
+            }
+        }
+    });
+    // 3 has no name and is dropped, 5 is sent as an error
+    assert_eq!(
+        vec!["one", "two", "four"],
+        collect(Box::new(transformer)).await
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_closures() {
+    let factor = 3;
+    let transformer = Transformer::new(ids(4), move |id| Ok(Some(id * factor)));
+    assert_eq!(vec![3, 6, 9, 12], collect(Box::new(transformer)).await);
+
+    let batch_size = 2;
+    let batcher = Batcher::new(ids(5), move |_, batch| batch.len() == batch_size);
+    assert_eq!(
+        vec![vec![1, 2], vec![3, 4], vec![5]],
+        collect(Box::new(batcher)).await
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_input_errors_are_forwarded() {
+    let input = String::from("1\n\"two\"\n3").decode_json::<usize>();
+    let transformer = Transformer::new(input, |id| Ok(Some(id * 10)));
+    let (mut rx, jh) = Box::new(transformer)
+        .start_stream()
+        .expect("Could not start the transformer");
+    let mut results = Vec::new();
+    while let Some(message) = rx.recv().await {
+        results.push(match message {
+            Ok(DataSourceMessage::Data { content, .. }) => Ok(content),
+            Err(er) => Err(er.provenance().cloned()),
+        });
+    }
+    let stats = jh.await.unwrap().expect("The transformer failed");
+    // the decode error of line 2 goes through with its provenance
+    assert_eq!(3, results.len());
+    assert_eq!(Ok(10), results[0]);
+    assert!(matches!(results[1], Err(Some(_))));
+    assert_eq!(Ok(30), results[2]);
+    assert_eq!(3, stats.lines_scanned);
+}
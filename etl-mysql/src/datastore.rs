@@ -311,6 +311,7 @@ impl<T: Serialize + std::fmt::Debug + Send + Sync + 'static> DataOutput<T> for M
                 name: "MySqlDataOutput".to_string(),
                 key: Some(format!("{}.{}", db_name, table_name)),
                 lines_written: total_inserted,
+                ..Default::default()
             })
         });
         Ok((tx, join_handle))
@@ -635,7 +636,7 @@ where
         //let ds_name = self.name(); // TODO: not seeing why the compiler complains about this
         let ds_name = format!("MySqlSelect:{}", &self.table_name);
 
-        let (tx, rx) = channel(1);
+        let (tx, rx) = channel(channels::buffer_size());
         let pool_params = self.pool.clone();
         let source_name = format!("{}.{}", &self.table_name, &self.db_name);
         let db_name = self.db_name;
@@ -653,13 +654,16 @@ where
             while let Some(row) = rows.try_next().await.map_err(|e| {
                 DataStoreError::FatalIO(format!("Could not fetch a row due to: {}", e))
             })? {
-                tx.send(Ok(DataSourceMessage::new(&ds_name, row)))
-                    .await
-                    .map_err(|er| DataStoreError::send_error(&ds_name, &source_name, er))?;
                 lines_scanned += 1;
+                tx.send(Ok(DataSourceMessage::new(
+                    Provenance::new(&ds_name, &source_name).with_line(lines_scanned),
+                    row,
+                )))
+                .await
+                .map_err(|er| DataStoreError::send_error(&ds_name, &source_name, er))?;
             }
 
-            Ok(DataSourceStats { lines_scanned })
+            Ok(DataSourceStats::new(lines_scanned))
         });
         Ok((rx, jh))
     }
@@ -57,12 +57,12 @@ async fn test_simple_mysql_output() {
             //pause: Some(std::time::Duration::from_secs(1)),
             //state: create_pool("admin", "admin", "localhost", "3306").await.expect("could not make pool"),
             state: (),
-            create: |_, idx| {
+            create: Box::new(|_, idx| {
                 Ok(TestOutputData {
                     resource_type: Some(String::from("Bob")),
                     index: Some(idx),
                 })
-            },
+            }),
         }),
         */
         /*
@@ -1,7 +1,7 @@
 use clap::Parser;
 use etl_core::deps::anyhow;
 use etl_core::deps::tokio;
-use etl_core::utils;
+use etl_core::datastore::fs::LocalFs;
 use serde::{Deserialize, Serialize};
 
 use etl_sftp::*;
@@ -31,7 +31,7 @@ async fn main() -> anyhow::Result<()> {
         ref ssh_key_path,
         ref username,
         ref password,
-    } = utils::load_toml(&args.config, true)?;
+    } = LocalFs::load_toml(&args.config, true).await?;
 
     let client = match (password, ssh_key_path) {
         (Some(ref password), None) => ssh_connect(